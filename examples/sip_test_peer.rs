@@ -0,0 +1,168 @@
+// Minimal SIP/RTP caller for trying the telephony bridge without a phone system.
+//
+//     cargo run --example sip_test_peer -- [bridge address] [input.raw]
+//
+// Calls the bridge (default 127.0.0.1:5060), streams `input.raw` as PCMU, keeps listening
+// for a while and hangs up. `input.raw` is 8kHz mono signed 16-bit audio, e.g. from
+// `sox question.wav -r 8000 -c 1 -b 16 -e signed question.raw`, without it a short tone is
+// sent. What came back is written to `received.raw` in the same format.
+
+use makepad_realtime::codec::{linear_to_ulaw, ulaw_to_linear};
+use makepad_realtime::telephony::{
+    PAYLOAD_TYPE_PCMU, RtpPacket, SdpAudio, SipMessage, random_u32, sdp,
+};
+use std::io::{Error, Result};
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+const PACKET_SAMPLES: usize = 160;
+const PACKET_INTERVAL: Duration = Duration::from_millis(20);
+// Time for the assistant to answer after the input ran out
+const LISTEN_AFTER_INPUT: Duration = Duration::from_secs(15);
+
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let bridge: SocketAddr = args
+        .next()
+        .as_deref()
+        .unwrap_or("127.0.0.1:5060")
+        .parse()
+        .map_err(Error::other)?;
+    let input: Vec<i16> = match args.next() {
+        Some(path) => std::fs::read(path)?
+            .chunks_exact(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect(),
+        None => tone(),
+    };
+
+    let sip = UdpSocket::bind("0.0.0.0:0")?;
+    sip.connect(bridge)?;
+    let local = sip.local_addr()?;
+    let rtp = UdpSocket::bind((local.ip(), 0))?;
+
+    let from = format!("<sip:test-peer@{}>;tag={:08x}", local, random_u32());
+    let call_id = format!("{:08x}@{}", random_u32(), local.ip());
+    let mut to = format!("<sip:assistant@{}>", bridge);
+    let request = |method: &str, cseq: u32, to: &str| {
+        SipMessage::request(method, &format!("sip:assistant@{}", bridge))
+            .with_header(
+                "Via",
+                &format!("SIP/2.0/UDP {};branch=z9hG4bK{:08x}", local, random_u32()),
+            )
+            .with_header("Max-Forwards", "70")
+            .with_header("From", &from)
+            .with_header("To", to)
+            .with_header("Call-ID", &call_id)
+            .with_header("CSeq", &format!("{} {}", cseq, method))
+            .with_header("Contact", &format!("<sip:test-peer@{}>", local))
+    };
+
+    let offer = sdp(local.ip(), rtp.local_addr()?.port(), &[PAYLOAD_TYPE_PCMU]);
+    let invite = request("INVITE", 1, &to).with_body("application/sdp", &offer);
+    sip.send(&invite.to_bytes())?;
+    println!("Calling {}", bridge);
+
+    let mut buffer = vec![0u8; 8192];
+    sip.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let answer = loop {
+        let len = sip.recv(&mut buffer)?;
+        let Some(response) = SipMessage::parse(&buffer[..len]) else {
+            continue;
+        };
+        match response.status() {
+            Some(status) if status < 200 => continue,
+            Some(200) => break response,
+            _ => return Err(Error::other(format!("Call rejected: {}", response.start_line))),
+        }
+    };
+    // The bridge's To carries its tag, in-dialog requests need it
+    if let Some(tagged) = answer.header("To") {
+        to = tagged.to_string();
+    }
+    let remote = SdpAudio::parse(&answer.body).ok_or_else(|| Error::other("No SDP in the answer"))?;
+    let remote_rtp = SocketAddr::new(remote.address, remote.port);
+    sip.send(&request("ACK", 1, &to).to_bytes())?;
+
+    let input_duration = PACKET_INTERVAL * input.len().div_ceil(PACKET_SAMPLES) as u32;
+    println!(
+        "Answered, sending {:.1}s of audio to {}",
+        input_duration.as_secs_f64(),
+        remote_rtp
+    );
+
+    sip.set_nonblocking(true)?;
+    rtp.set_read_timeout(Some(Duration::from_millis(5)))?;
+    let ssrc = random_u32();
+    let mut received = Vec::new();
+    let mut sent_packets = 0usize;
+    let mut datagram = Vec::new();
+    let start = Instant::now();
+    let mut hung_up_by_bridge = false;
+    while start.elapsed() < input_duration + LISTEN_AFTER_INPUT {
+        // Silence once the input ran out, like a phone that stays off-hook
+        while start + PACKET_INTERVAL * sent_packets as u32 <= Instant::now() {
+            let payload: Vec<u8> = (0..PACKET_SAMPLES)
+                .map(|i| input.get(sent_packets * PACKET_SAMPLES + i).copied().unwrap_or(0))
+                .map(linear_to_ulaw)
+                .collect();
+            datagram.clear();
+            RtpPacket {
+                payload_type: PAYLOAD_TYPE_PCMU,
+                sequence: sent_packets as u16,
+                timestamp: (sent_packets * PACKET_SAMPLES) as u32,
+                ssrc,
+                payload: &payload,
+            }
+            .write(&mut datagram);
+            rtp.send_to(&datagram, remote_rtp)?;
+            sent_packets += 1;
+        }
+
+        let packet = rtp
+            .recv_from(&mut buffer)
+            .ok()
+            .and_then(|(len, _)| RtpPacket::parse(&buffer[..len]));
+        if let Some(packet) = packet {
+            received.extend(
+                packet
+                    .payload
+                    .iter()
+                    .flat_map(|&byte| ulaw_to_linear(byte).to_le_bytes()),
+            );
+        }
+
+        let message = sip
+            .recv(&mut buffer)
+            .ok()
+            .and_then(|len| SipMessage::parse(&buffer[..len]));
+        if let Some(bye) = message.filter(|message| message.method() == Some("BYE")) {
+            sip.send(&bye.response(200, "OK").to_bytes())?;
+            hung_up_by_bridge = true;
+            break;
+        }
+    }
+
+    if hung_up_by_bridge {
+        println!("The bridge hung up");
+    } else {
+        sip.send(&request("BYE", 2, &to).to_bytes())?;
+        println!("Hung up");
+    }
+    std::fs::write("received.raw", &received)?;
+    println!(
+        "Wrote {:.1}s of received audio to received.raw",
+        received.len() as f64 / 2.0 / 8000.0
+    );
+    Ok(())
+}
+
+// Two seconds of 440Hz at -12dBFS
+fn tone() -> Vec<i16> {
+    (0..16000)
+        .map(|i| {
+            let phase = 2.0 * std::f32::consts::PI * 440.0 * i as f32 / 8000.0;
+            (phase.sin() * 0.25 * i16::MAX as f32) as i16
+        })
+        .collect()
+}
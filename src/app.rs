@@ -1,7 +1,39 @@
+use crate::audio::{
+    ChannelMix, Downmixer, InputLevel, OPENAI_SAMPLE_RATE, PlaybackBuffer, amplitude_to_db,
+};
+use crate::codec::{self, AudioCodec, AudioFormat};
+use crate::conversation::{ConversationHistory, HistoryItem, PruneStrategy, Role};
+use crate::devices::DeviceSelection;
+use crate::errors::{
+    ErrorKind, MAX_RETRY_ATTEMPTS, RealtimeError, RecoveryAction, SentEvent, SentEvents,
+};
+use crate::logging;
+use crate::metrics::LatencyTracker;
+use crate::out_of_band::{self, OutOfBandResponses, OutOfBandResult, OutOfBandTask, action_items_schema};
+use crate::rollover::{
+    ROLLOVER_HISTORY_ITEMS, ROLLOVER_RETRY_INTERVAL, ROLLOVER_SUMMARY_CHARS, SessionLifetime,
+};
+use crate::persona::{GreetingMode, GreetingTiming, PersonaProfile, TemplateVars, render_template};
+use crate::preprocess::{PreprocessConfig, Preprocessor, ServerNoiseReduction};
+use crate::relay::{RelayServer, generate_access_token};
+use crate::settings::{Settings, write_export};
+use crate::telephony::{BridgeAudio, BridgeEvent, TelephonyBridge};
+use crate::token_service::{
+    ClientSecret, ClientSecretConfig, RealtimeAuth, SESSIONS_URL, SessionsRequest, SessionsResponse,
+    TokenRequest, TokenService, error_body, token_response,
+};
+use crate::trace::{TraceRecorder, Track};
+use crate::usage::{BudgetStatus, Usage, UsageTracker};
+use crate::vad::{LocalVad, VadEvent, VadMode};
+use crate::voice_preview;
+use crate::wake_word::{ENROLLMENT_SECS, TemplateMatcher, WakeWordDetector};
+use crate::{log_debug, log_error, log_info, log_trace, log_warn};
 use base64::{Engine as _, engine::general_purpose};
 use makepad_widgets::*;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 // OpenAI Realtime API Demo Implementation
 //
@@ -27,17 +59,50 @@ pub enum OpenAIRealtimeMessage {
     #[serde(rename = "response.create")]
     ResponseCreate { response: ResponseConfig },
     #[serde(rename = "conversation.item.create")]
-    ConversationItemCreate { item: ConversationItem },
+    ConversationItemCreate {
+        /// Insert after this item, "root" for the start of the conversation, None appends
+        #[serde(skip_serializing_if = "Option::is_none")]
+        previous_item_id: Option<String>,
+        item: ConversationItem,
+    },
+    #[serde(rename = "conversation.item.delete")]
+    ConversationItemDelete { item_id: String },
     #[serde(rename = "conversation.item.truncate")]
     ConversationItemTruncate {
         item_id: String,
         content_index: u32,
         audio_end_ms: u32,
+    },
+    #[serde(rename = "response.cancel")]
+    ResponseCancel {
         #[serde(skip_serializing_if = "Option::is_none")]
-        event_id: Option<String>,
+        response_id: Option<String>,
     },
 }
 
+impl OpenAIRealtimeMessage {
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            OpenAIRealtimeMessage::SessionUpdate { .. } => "session.update",
+            OpenAIRealtimeMessage::InputAudioBufferAppend { .. } => "input_audio_buffer.append",
+            OpenAIRealtimeMessage::InputAudioBufferCommit => "input_audio_buffer.commit",
+            OpenAIRealtimeMessage::ResponseCreate { .. } => "response.create",
+            OpenAIRealtimeMessage::ConversationItemCreate { .. } => "conversation.item.create",
+            OpenAIRealtimeMessage::ConversationItemDelete { .. } => "conversation.item.delete",
+            OpenAIRealtimeMessage::ConversationItemTruncate { .. } => "conversation.item.truncate",
+            OpenAIRealtimeMessage::ResponseCancel { .. } => "response.cancel",
+        }
+    }
+}
+
+// Every client event goes out with an id so server errors can be traced back to it
+#[derive(Serialize)]
+struct ClientEvent<'a, T: Serialize> {
+    event_id: &'a str,
+    #[serde(flatten)]
+    event: &'a T,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SessionConfig {
     pub modalities: Vec<String>,
@@ -86,10 +151,32 @@ pub struct ResponseConfig {
     pub tool_choice: Option<String>,
     pub temperature: Option<f32>,
     pub max_output_tokens: Option<u32>,
+    /// "none" keeps the response out of the default conversation (out-of-band)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conversation: Option<String>,
+    /// Returned as is on the response events, up to 16 pairs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<BTreeMap<String, String>>,
+    /// Replaces the default conversation as context for this response, `[]` means no context
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input: Option<Vec<ResponseInput>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ResponseInput {
+    Item(ConversationItem),
+    /// An existing conversation item, `{"type": "item_reference", "id": ...}`
+    Reference {
+        #[serde(rename = "type")]
+        reference_type: String,
+        id: String,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ConversationItem {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<String>,
     #[serde(rename = "type")]
     pub item_type: String,
@@ -98,13 +185,32 @@ pub struct ConversationItem {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-#[serde(tag = "type")]
 pub struct ContentPart {
     #[serde(rename = "type")]
     pub part_type: String,
     pub text: Option<String>,
 }
 
+impl ConversationItem {
+    /// A text message, used to seed a session with conversation history.
+    pub fn text_message(item: &HistoryItem) -> Self {
+        // Assistant messages take output content parts, the others input ones
+        let part_type = match item.role {
+            Role::Assistant => "text",
+            Role::User | Role::System => "input_text",
+        };
+        Self {
+            id: Some(item.item_id.clone()),
+            item_type: "message".to_string(),
+            role: item.role.as_str().to_string(),
+            content: vec![ContentPart {
+                part_type: part_type.to_string(),
+                text: Some(item.text.clone()),
+            }],
+        }
+    }
+}
+
 // Incoming message types from OpenAI
 #[derive(Deserialize, Debug)]
 #[serde(tag = "type")]
@@ -119,6 +225,10 @@ pub enum OpenAIRealtimeResponse {
     ConversationItemCreated { item: serde_json::Value },
     #[serde(rename = "conversation.item.truncated")]
     ConversationItemTruncated { item: serde_json::Value },
+    #[serde(rename = "conversation.item.deleted")]
+    ConversationItemDeleted { item_id: String },
+    #[serde(rename = "response.created")]
+    ResponseCreated { response: ResponseDetails },
     #[serde(rename = "response.audio.delta")]
     ResponseAudioDelta {
         response_id: String,
@@ -150,8 +260,18 @@ pub enum OpenAIRealtimeResponse {
         content_index: u32,
         delta: String,
     },
+    #[serde(rename = "response.audio_transcript.done")]
+    ResponseAudioTranscriptDone { item_id: String, transcript: String },
+    #[serde(rename = "response.text.done")]
+    ResponseTextDone {
+        response_id: String,
+        item_id: String,
+        text: String,
+    },
+    #[serde(rename = "conversation.item.input_audio_transcription.completed")]
+    InputAudioTranscriptionCompleted { item_id: String, transcript: String },
     #[serde(rename = "response.done")]
-    ResponseDone { response: serde_json::Value },
+    ResponseDone { response: ResponseDetails },
     #[serde(rename = "input_audio_buffer.speech_started")]
     InputAudioBufferSpeechStarted {
         audio_start_ms: u32,
@@ -163,6 +283,32 @@ pub enum OpenAIRealtimeResponse {
     Other,
 }
 
+// Just the type of an incoming event, also covers the ones we don't handle
+#[derive(Deserialize)]
+struct EventType<'a> {
+    #[serde(rename = "type")]
+    event_type: &'a str,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ResponseDetails {
+    pub id: String,
+    pub status: Option<String>,
+    pub usage: Option<Usage>,
+    pub metadata: Option<BTreeMap<String, String>>,
+    #[serde(default)]
+    pub output: Vec<serde_json::Value>,
+}
+
+impl ResponseDetails {
+    /// True for a completed response that spoke or wrote to the user without calling tools.
+    pub fn answers_user(&self) -> bool {
+        self.status.as_deref() == Some("completed")
+            && self.output.iter().any(|item| item["type"] == "message")
+            && !self.output.iter().any(|item| item["type"] == "function_call")
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct ErrorDetails {
     pub code: Option<String>,
@@ -170,8 +316,31 @@ pub struct ErrorDetails {
     pub param: Option<String>,
     #[serde(rename = "type")]
     pub error_type: Option<String>,
+    /// Id of the client event that caused the error
+    pub event_id: Option<String>,
 }
 
+// Model of every Realtime session, also what client secrets are minted for
+const REALTIME_MODEL: &str = "gpt-4o-realtime-preview-2025-06-03";
+
+// Voices offered in the UI, must match `voice_selector` and `preview_voice_selector`
+pub(crate) const VOICES: [&str; 8] = ["alloy", "shimmer", "ash", "ballad", "coral", "echo", "sage", "verse"];
+
+// Jitter buffer sizes offered in the UI, must match `playback_latency_selector`
+const PLAYBACK_LATENCY_OPTIONS_MS: [usize; 4] = [50, 100, 200, 400];
+
+// Retries of failed client events back off exponentially from this delay
+const RETRY_BASE_DELAY_SECS: f64 = 0.5;
+
+// How often the input level meter is refreshed
+const LEVEL_METER_INTERVAL_SECS: f64 = 0.1;
+
+// How often relayed client events are forwarded, upstream events wake the app on their own
+const RELAY_INTERVAL_SECS: f64 = 0.02;
+
+// Warn about a dead microphone once the captured stream has been silent for this long
+const NO_INPUT_SIGNAL_WARNING: Duration = Duration::from_secs(4);
+
 live_design! {
     use link::theme::*;
     use link::shaders::*;
@@ -208,7 +377,7 @@ live_design! {
         align: {x: 0.5, y: 0.5}
 
         <Label> {
-            text: "Select voice (OpenAI only, can't change once conversation starts)"
+            text: "Select voice (changing it mid-conversation moves to a new session)"
             draw_text: {text_style: {font_size: 15}}
         }
 
@@ -227,6 +396,230 @@ live_design! {
                 }
             }
         }
+
+        // Separate from `voice_selector` so previewing never switches the session's voice
+        preview_voice_selector = <DropDown> {
+            margin: 5
+            labels: ["alloy", "shimmer", "ash", "ballad", "coral", "echo", "sage", "verse"]
+            values: [alloy, shimmer, ash, ballad, coral, echo, sage, verse]
+
+            draw_text: {
+                text_style: {font_size: 13}
+            }
+
+            popup_menu = {
+                draw_text: {
+                    text_style: {font_size: 13}
+                }
+            }
+        }
+
+        preview_voice_button = <Button> {
+            text: "▶️ Preview"
+            draw_text: {text_style: {font_size: 13}}
+        }
+    }
+
+    AudioDeviceSelectors = <View> {
+        height: Fit
+        flow: Down
+        spacing: 5
+        align: {x: 0.5, y: 0.5}
+
+        <View> {
+            height: Fit
+            align: {x: 0.5, y: 0.5}
+
+            <Label> {
+                text: "Microphone"
+                draw_text: {text_style: {font_size: 15}}
+            }
+
+            input_device_selector = <DropDown> {
+                margin: 5
+                labels: ["System default"]
+
+                draw_text: {
+                    text_style: {font_size: 15}
+                }
+
+                popup_menu = {
+                    draw_text: {
+                        text_style: {font_size: 15}
+                    }
+                }
+            }
+
+            channel_mix_selector = <DropDown> {
+                margin: 5
+                labels: ["Average all channels"]
+
+                draw_text: {
+                    text_style: {font_size: 15}
+                }
+
+                popup_menu = {
+                    draw_text: {
+                        text_style: {font_size: 15}
+                    }
+                }
+            }
+        }
+
+        <View> {
+            height: Fit
+            align: {x: 0.5, y: 0.5}
+
+            <Label> {
+                text: "Speakers"
+                draw_text: {text_style: {font_size: 15}}
+            }
+
+            output_device_selector = <DropDown> {
+                margin: 5
+                labels: ["System default"]
+
+                draw_text: {
+                    text_style: {font_size: 15}
+                }
+
+                popup_menu = {
+                    draw_text: {
+                        text_style: {font_size: 15}
+                    }
+                }
+            }
+
+            <Label> {
+                text: "Buffer"
+                draw_text: {text_style: {font_size: 15}}
+            }
+
+            playback_latency_selector = <DropDown> {
+                margin: 5
+                labels: ["50 ms", "100 ms", "200 ms", "400 ms"]
+                values: [ms_50, ms_100, ms_200, ms_400]
+
+                draw_text: {
+                    text_style: {font_size: 15}
+                }
+
+                popup_menu = {
+                    draw_text: {
+                        text_style: {font_size: 15}
+                    }
+                }
+            }
+        }
+
+        <View> {
+            height: Fit
+            align: {x: 0.5, y: 0.5}
+
+            <Label> {
+                text: "Audio format (from the next connection)"
+                draw_text: {text_style: {font_size: 15}}
+            }
+
+            audio_format_selector = <DropDown> {
+                margin: 5
+                labels: ["PCM16 24 kHz", "G.711 μ-law 8 kHz", "G.711 A-law 8 kHz"]
+                values: [pcm16, g711_ulaw, g711_alaw]
+
+                draw_text: {
+                    text_style: {font_size: 15}
+                }
+
+                popup_menu = {
+                    draw_text: {
+                        text_style: {font_size: 15}
+                    }
+                }
+            }
+        }
+    }
+
+    PersonaEditor = <View> {
+        height: Fit
+        flow: Down
+        spacing: 5
+        padding: {left: 30, right: 30}
+
+        <View> {
+            height: Fit
+            align: {x: 0.5, y: 0.5}
+            spacing: 10
+
+            <Label> {
+                text: "Persona"
+                draw_text: {text_style: {font_size: 15}}
+            }
+
+            persona_selector = <DropDown> {
+                margin: 5
+                labels: ["Assistant"]
+
+                draw_text: {
+                    text_style: {font_size: 15}
+                }
+
+                popup_menu = {
+                    draw_text: {
+                        text_style: {font_size: 15}
+                    }
+                }
+            }
+
+            persona_name_input = <TextInput> {
+                width: 200
+                empty_text: "Persona name"
+                draw_text: {text_style: {font_size: 13}}
+            }
+
+            save_persona_button = <Button> {
+                text: "💾 Save persona"
+                draw_text: {text_style: {font_size: 13}}
+            }
+        }
+
+        instructions_input = <TextInput> {
+            width: Fill
+            height: Fit
+            empty_text: "Instructions, {date}, {time}, {weekday} and {user_name} are filled in"
+            draw_text: {text_style: {font_size: 13}}
+        }
+
+        <View> {
+            height: Fit
+            align: {x: 0.5, y: 0.5}
+            spacing: 10
+
+            <Label> {
+                text: "Greeting"
+                draw_text: {text_style: {font_size: 13}}
+            }
+
+            greeting_selector = <DropDown> {
+                margin: 5
+                labels: ["No greeting", "Fixed line", "Generated"]
+                values: [off, fixed, generated]
+
+                draw_text: {
+                    text_style: {font_size: 13}
+                }
+
+                popup_menu = {
+                    draw_text: {
+                        text_style: {font_size: 13}
+                    }
+                }
+            }
+
+            apply_instructions_button = <Button> {
+                text: "✅ Apply instructions"
+                draw_text: {text_style: {font_size: 13}}
+            }
+        }
     }
 
     App = {{App}} {
@@ -252,7 +645,9 @@ live_design! {
                     }
 
                     <TranscriptionModelSelector> {}
-                    voice_selector_wrapper = <VoiceSelector> {} // Disabling for now as it cannot be changed during conversation.
+                    <AudioDeviceSelectors> {}
+                    <PersonaEditor> {}
+                    voice_selector_wrapper = <VoiceSelector> {}
                     selected_voice_view = <View> {
                         visible: false
                         height: Fit
@@ -287,6 +682,81 @@ live_design! {
                         }
                     }
 
+                    <View> {
+                        height: Fit
+                        align: {x: 0.5, y: 0.5}
+                        spacing: 20
+
+                        toggle_mute = <Toggle> {
+                            text: "Mute microphone"
+                            draw_text: {text_style: {font_size: 13}}
+                            label_walk: {
+                                margin: {left: 50}
+                            }
+                            draw_bg: {
+                                size: 25.
+                            }
+                        }
+
+                        input_level_label = <Label> {
+                            text: "🎙️ ----------------"
+                            draw_text: {text_style: {font_size: 13}}
+                        }
+
+                        vad_selector = <DropDown> {
+                            margin: 5
+                            labels: ["Server VAD", "Local VAD: skip silence", "Local VAD: turn-taking"]
+                            values: [off, gate_uploads, turn_taking]
+
+                            draw_text: {
+                                text_style: {font_size: 13}
+                            }
+
+                            popup_menu = {
+                                draw_text: {
+                                    text_style: {font_size: 13}
+                                }
+                            }
+                        }
+                    }
+
+                    <View> {
+                        height: Fit
+                        align: {x: 0.5, y: 0.5}
+                        spacing: 20
+
+                        toggle_wake_word = <Toggle> {
+                            text: "Wake word"
+                            draw_text: {text_style: {font_size: 13}}
+                            label_walk: {
+                                margin: {left: 50}
+                            }
+                            draw_bg: {
+                                size: 25.
+                            }
+                        }
+
+                        enroll_wake_word_button = <Button> {
+                            text: "🎙️ Record wake phrase"
+                            draw_text: {text_style: {font_size: 13}}
+                        }
+
+                        wake_word_label = <Label> {
+                            text: ""
+                            draw_text: {text_style: {font_size: 13}}
+                        }
+                    }
+
+                    input_warning_view = <View> {
+                        visible: false
+                        height: Fit
+                        align: {x: 0.5, y: 0.5}
+                        <Label> {
+                            text: "⚠️ No input signal detected - check that the right microphone is selected"
+                            draw_text: {text_style: {font_size: 13}}
+                        }
+                    }
+
                     transcript_label = <Label> {
                         width: Fill,
                         padding: {left: 30, right: 30}
@@ -299,9 +769,79 @@ live_design! {
                         draw_text: {text_style: {font_size: 15}}
                     }
 
-                    reset_button = <Button> {
-                        text: "🔄 Reset"
-                        draw_text: {text_style: {font_size: 15}}
+                    <View> {
+                        height: Fit
+                        align: {x: 0.5, y: 0.5}
+                        spacing: 20
+
+                        usage_label = <Label> {
+                            text: "No usage yet"
+                            draw_text: {text_style: {font_size: 13}}
+                        }
+
+                        export_usage_button = <Button> {
+                            text: "📊 Export usage CSV"
+                            draw_text: {text_style: {font_size: 13}}
+                        }
+                    }
+
+                    <View> {
+                        height: Fit
+                        align: {x: 0.5, y: 0.5}
+                        spacing: 20
+
+                        latency_label = <Label> {
+                            text: "No latency measurements yet"
+                            draw_text: {text_style: {font_size: 13}}
+                        }
+
+                        export_latency_button = <Button> {
+                            text: "⏱️ Export latency report"
+                            draw_text: {text_style: {font_size: 13}}
+                        }
+
+                        export_trace_button = <Button> {
+                            text: "🧭 Export timeline"
+                            draw_text: {text_style: {font_size: 13}}
+                        }
+                    }
+
+                    <View> {
+                        height: Fit
+                        align: {x: 0.5, y: 0.5}
+                        spacing: 20
+
+                        summarize_button = <Button> {
+                            text: "📝 Summarize"
+                            draw_text: {text_style: {font_size: 13}}
+                        }
+
+                        extract_action_items_button = <Button> {
+                            text: "🧾 Extract action items"
+                            draw_text: {text_style: {font_size: 13}}
+                        }
+                    }
+
+                    out_of_band_label = <Label> {
+                        width: Fill,
+                        padding: {left: 30, right: 30}
+                        draw_text: {text_style: {font_size: 13}}
+                    }
+
+                    <View> {
+                        height: Fit
+                        align: {x: 0.5, y: 0.5}
+                        spacing: 20
+
+                        stop_response_button = <Button> {
+                            text: "✋ Stop talking"
+                            draw_text: {text_style: {font_size: 15}}
+                        }
+
+                        reset_button = <Button> {
+                            text: "🔄 Reset"
+                            draw_text: {text_style: {font_size: 15}}
+                        }
                     }
                 }
             }
@@ -318,16 +858,52 @@ pub struct App {
     #[rust]
     recorded_audio: Arc<Mutex<Vec<f32>>>,
     #[rust]
-    playback_audio: Arc<Mutex<Vec<f32>>>,
+    playback: Arc<Mutex<PlaybackBuffer>>,
     #[rust]
     is_recording: Arc<Mutex<bool>>,
     #[rust]
-    is_playing: Arc<Mutex<bool>>,
+    is_muted: Arc<Mutex<bool>>,
+    #[rust]
+    input_level: Arc<Mutex<InputLevel>>,
     #[rust]
-    playback_position: Arc<Mutex<usize>>,
+    level_meter_timer: Option<Timer>,
+    #[rust]
+    input_warning_visible: bool,
     #[rust]
     audio_setup_done: bool,
     #[rust]
+    settings: Settings,
+    #[rust]
+    input_devices: DeviceSelection,
+    #[rust]
+    output_devices: DeviceSelection,
+    #[rust]
+    channel_mix: Arc<Mutex<ChannelMix>>,
+    #[rust]
+    preprocess_config: Arc<Mutex<PreprocessConfig>>,
+    /// Wire format of the current session, fixed when connecting
+    #[rust]
+    codec: AudioCodec,
+    #[rust]
+    telephony: Option<TelephonyBridge>,
+    /// True while a phone call replaces the mic and speakers
+    #[rust]
+    bridge_active: Arc<Mutex<bool>>,
+    /// Format of the call in progress, sessions opened for it use the same
+    #[rust]
+    call_format: Option<AudioFormat>,
+    #[rust]
+    relay: Option<RelayServer>,
+    #[rust]
+    relay_timer: Option<Timer>,
+    #[rust]
+    token_service: Option<TokenService>,
+    /// Client secrets being minted or fetched, by HTTP request id
+    #[rust]
+    pending_client_secrets: HashMap<LiveId, SecretRequester>,
+    #[rust]
+    channel_mix_options: Vec<ChannelMix>,
+    #[rust]
     websocket: Option<WebSocket>,
     #[rust]
     is_connected: bool,
@@ -348,8 +924,132 @@ pub struct App {
     #[rust]
     current_assistant_item_id: Option<String>,
     #[rust]
+    current_response_id: Option<String>,
+    #[rust]
+    cancelled_response_id: Option<String>,
+    #[rust]
     selected_voice: String,
-}
+    #[rust]
+    usage: UsageTracker,
+    #[rust]
+    budget_warning_shown: bool,
+    #[rust]
+    latency: LatencyTracker,
+    #[rust]
+    trace: TraceRecorder,
+    #[rust]
+    traced_playback_start: Option<Instant>,
+    #[rust]
+    traced_state: Option<[bool; 4]>,
+    #[rust]
+    sent_events: SentEvents,
+    #[rust]
+    pending_retries: Vec<(Timer, SentEvent)>,
+    #[rust]
+    history: ConversationHistory,
+    #[rust]
+    user_is_speaking: bool,
+    #[rust]
+    session_lifetime: Option<SessionLifetime>,
+    #[rust]
+    standby: Option<StandbySession>,
+    #[rust]
+    last_rollover_attempt: Option<Instant>,
+    #[rust]
+    summary_count: u32,
+    #[rust]
+    out_of_band: OutOfBandResponses,
+    #[rust]
+    personas: Vec<PersonaProfile>,
+    #[rust]
+    persona: PersonaProfile,
+    #[rust]
+    greeting_timer: Option<Timer>,
+    #[rust]
+    preview: Option<PreviewSession>,
+    #[rust]
+    preview_playing: bool,
+    #[rust]
+    local_vad: LocalVad,
+    #[rust]
+    wake_detector: Option<Box<dyn WakeWordDetector>>,
+    /// When the wake phrase was heard, None while waiting for it
+    #[rust]
+    awake_since: Option<Instant>,
+    /// Captured audio while recording the wake phrase
+    #[rust]
+    wake_enrollment: Arc<Mutex<Option<Vec<f32>>>>,
+}
+
+/// Items we put at the start of the conversation in place of pruned ones.
+fn is_summary(item: &HistoryItem) -> bool {
+    item.role == Role::System && item.item_id.starts_with("summary_")
+}
+
+fn server_turn_detection() -> TurnDetectionConfig {
+    TurnDetectionConfig {
+        detection_type: "server_vad".to_string(),
+        threshold: 0.5,
+        prefix_padding_ms: 300,
+        silence_duration_ms: 200,
+        interrupt_response: true,
+        create_response: true,
+    }
+}
+
+/// Upgrade request for a Realtime session, `token` is the API key or a client secret.
+fn realtime_request(token: &str) -> HttpRequest {
+    let url = format!("wss://api.openai.com/v1/realtime?model={}", REALTIME_MODEL);
+
+    let mut request = HttpRequest::new(url, HttpMethod::GET);
+    request.set_header("Authorization".to_string(), format!("Bearer {}", token));
+    request.set_header("OpenAI-Beta".to_string(), "realtime=v1".to_string());
+    request
+}
+
+/// Sends on a session other than the current one, without retries.
+fn send_to_side_session(websocket: &mut WebSocket, name: &str, event_id: &str, message: &OpenAIRealtimeMessage) {
+    match serde_json::to_string(&ClientEvent { event_id, event: message }) {
+        Ok(json_str) => {
+            log_trace!(Transport, "Sending to {} session: {}", name, logging::payload(&json_str));
+            if websocket.send_string(json_str).is_err() {
+                log_error!(Transport, "Failed to send message to the {} session", name);
+            }
+        }
+        Err(e) => {
+            log_error!(Protocol, "Failed to serialize message: {}", e);
+        }
+    }
+}
+
+/// Who gets a client secret once it arrives.
+enum SecretRequester {
+    /// `connect_to_openai`, connects with it
+    Connection,
+    /// `open_standby_session`, opens the standby session in this voice
+    Standby { voice: String },
+    /// `preview_voice`, opens the preview session for this voice
+    Preview { voice: String },
+    /// A relay client waiting for its upstream session
+    Relay(u64),
+    /// A token service client
+    Client(TokenRequest),
+}
+
+/// A throwaway session that speaks the preview sentence in one voice, see `preview_voice`.
+struct PreviewSession {
+    websocket: WebSocket,
+    voice: String,
+    pcm: Vec<u8>,
+}
+
+/// A session opened ahead of the current one's expiry, see `check_session_rollover`.
+struct StandbySession {
+    websocket: WebSocket,
+    lifetime: Option<SessionLifetime>,
+    ready: bool,
+    voice: String,
+}
 
 impl LiveRegister for App {
     fn live_register(cx: &mut Cx) {
@@ -359,10 +1059,65 @@ impl LiveRegister for App {
 
 impl MatchEvent for App {
     fn handle_startup(&mut self, cx: &mut Cx) {
+        self.settings = Settings::load();
+        logging::init(&self.settings.logging);
+        self.input_devices.set_preferred(self.settings.input_device.clone());
+        self.output_devices.set_preferred(self.settings.output_device.clone());
+        *self.channel_mix.lock().unwrap() = self.settings.input_channel_mix;
+        *self.preprocess_config.lock().unwrap() = self.settings.preprocessing;
+        self.apply_playback_latency(cx);
+        if let Some(index) = AudioFormat::ALL.iter().position(|format| *format == self.settings.audio_format) {
+            self.ui.drop_down(id!(audio_format_selector)).set_selected_item(cx, index);
+        }
+        if let Some(index) = VadMode::ALL.iter().position(|mode| *mode == self.settings.vad.mode) {
+            self.ui.drop_down(id!(vad_selector)).set_selected_item(cx, index);
+        }
+        self.ui
+            .check_box(id!(toggle_wake_word))
+            .set_active(cx, self.settings.wake_word.enabled);
+        self.set_wake_word_enabled(cx, self.settings.wake_word.enabled);
+        self.personas = PersonaProfile::load_all();
+        let persona_index = self
+            .settings
+            .persona
+            .as_ref()
+            .and_then(|name| self.personas.iter().position(|persona| &persona.name == name))
+            .unwrap_or(0);
+        self.select_persona(cx, persona_index);
+
         self.setup_audio(cx);
+        self.start_telephony_bridge(cx);
         self.update_ui_state(cx);
+        self.level_meter_timer = Some(cx.start_interval(LEVEL_METER_INTERVAL_SECS));
 
         self.openai_api_key = std::env::var("OPENAI_API_KEY").ok();
+        if let Some(api_key) = &self.openai_api_key {
+            logging::register_secret(api_key);
+        }
+        self.start_relay_server(cx);
+        self.start_token_service(cx);
+    }
+
+    fn handle_network_responses(&mut self, cx: &mut Cx, responses: &NetworkResponsesEvent) {
+        for item in responses {
+            let secret = match &item.response {
+                NetworkResponse::HttpResponse(response) => {
+                    let body = response.get_string_body().unwrap_or_default();
+                    if response.status_code == 200 {
+                        serde_json::from_str::<SessionsResponse>(&body)
+                            .map(|response| response.client_secret)
+                            .map_err(|e| format!("unexpected response: {}", e))
+                    } else {
+                        Err(format!("HTTP {}: {}", response.status_code, body))
+                    }
+                }
+                NetworkResponse::HttpRequestError(error) => Err(error.message.clone()),
+                _ => continue,
+            };
+            if let Some(requester) = self.pending_client_secrets.remove(&item.request_id) {
+                self.client_secret_received(cx, requester, secret);
+            }
+        }
     }
 
     fn handle_actions(&mut self, cx: &mut Cx, actions: &Actions) {
@@ -379,50 +1134,252 @@ impl MatchEvent for App {
             self.reset_all(cx);
         }
 
+        if self.ui.button(id!(stop_response_button)).clicked(&actions) {
+            self.cancel_response(cx);
+        }
+
+        if self.ui.button(id!(export_usage_button)).clicked(&actions) {
+            self.export_usage(cx);
+        }
+
+        if self.ui.button(id!(export_latency_button)).clicked(&actions) {
+            self.export_latency_report(cx);
+        }
+
+        if self.ui.button(id!(export_trace_button)).clicked(&actions) {
+            self.export_trace(cx);
+        }
+
+        if self.ui.button(id!(summarize_button)).clicked(&actions) {
+            self.request_out_of_band(cx, OutOfBandTask::Summary, None);
+        }
+
+        if self.ui.button(id!(extract_action_items_button)).clicked(&actions) {
+            let task = OutOfBandTask::Extraction {
+                name: "action_items".to_string(),
+                schema: action_items_schema(),
+            };
+            self.request_out_of_band(cx, task, None);
+        }
+
         if let Some(enabled) = self.ui.check_box(id!(toggle_interruptions)).changed(&actions) {
             if enabled {
                 *self.is_recording.lock().unwrap() = true;
             }
         }
 
+        if let Some(muted) = self.ui.check_box(id!(toggle_mute)).changed(&actions) {
+            self.set_muted(cx, muted);
+        }
+
+        if let Some(enabled) = self.ui.check_box(id!(toggle_wake_word)).changed(&actions) {
+            self.set_wake_word_enabled(cx, enabled);
+        }
+
+        if self.ui.button(id!(enroll_wake_word_button)).clicked(&actions) {
+            *self.wake_enrollment.lock().unwrap() = Some(Vec::new());
+            self.ui
+                .label(id!(wake_word_label))
+                .set_text(cx, "🎙️ Say the wake phrase now...");
+        }
+
+        if let Some(index) = self.ui.drop_down(id!(vad_selector)).changed(&actions) {
+            if let Some(&mode) = VadMode::ALL.get(index) {
+                log_info!(Audio, "Local VAD: {:?}", mode);
+                self.settings.vad.mode = mode;
+                self.settings.save();
+                self.local_vad.reset();
+                // Turn detection moves between server and client
+                if self.is_connected {
+                    self.update_session_config(cx);
+                }
+            }
+        }
+
+        if let Some(index) = self.ui.drop_down(id!(input_device_selector)).changed(&actions) {
+            self.input_devices.select_index(index);
+            self.settings.input_device = self.input_devices.preferred().cloned();
+            self.settings.save();
+            cx.use_audio_inputs(&self.input_devices.active_devices());
+            self.update_channel_mix_selector(cx);
+            self.input_device_changed(cx);
+        }
+
+        if let Some(index) = self.ui.drop_down(id!(channel_mix_selector)).changed(&actions) {
+            if let Some(&mix) = self.channel_mix_options.get(index) {
+                log_info!(Audio, "Input channel mix: {:?}", mix);
+                *self.channel_mix.lock().unwrap() = mix;
+                self.settings.input_channel_mix = mix;
+                self.settings.save();
+            }
+        }
+
+        if let Some(index) = self.ui.drop_down(id!(output_device_selector)).changed(&actions) {
+            self.output_devices.select_index(index);
+            self.settings.output_device = self.output_devices.preferred().cloned();
+            self.settings.save();
+            cx.use_audio_outputs(&self.output_devices.active_devices());
+        }
+
+        if let Some(index) = self.ui.drop_down(id!(audio_format_selector)).changed(&actions) {
+            if let Some(&format) = AudioFormat::ALL.get(index) {
+                // The running session keeps its format, switching mid-stream would garble audio
+                log_info!(Audio, "Audio format for new sessions: {}", format.as_str());
+                self.settings.audio_format = format;
+                self.settings.save();
+            }
+        }
+
+        if let Some(index) = self.ui.drop_down(id!(playback_latency_selector)).changed(&actions) {
+            if let Some(&latency_ms) = PLAYBACK_LATENCY_OPTIONS_MS.get(index) {
+                self.settings.playback_latency_ms = latency_ms;
+                self.settings.save();
+                self.apply_playback_latency(cx);
+            }
+        }
+
+        if self.ui.button(id!(preview_voice_button)).clicked(&actions) {
+            let voice = self.ui.drop_down(id!(preview_voice_selector)).selected_label();
+            self.preview_voice(cx, voice);
+        }
+
+        if let Some(index) = self.ui.drop_down(id!(voice_selector)).changed(&actions) {
+            if let Some(voice) = VOICES.get(index) {
+                self.change_voice(cx, voice.to_string());
+            }
+        }
+
+        if let Some(index) = self.ui.drop_down(id!(persona_selector)).changed(&actions) {
+            self.select_persona(cx, index);
+            self.settings.persona = Some(self.persona.name.clone());
+            self.settings.save();
+        }
+
+        if self.ui.button(id!(apply_instructions_button)).clicked(&actions) {
+            self.persona.instructions = self.ui.text_input(id!(instructions_input)).text();
+            self.apply_persona(cx);
+        }
+
+        if let Some(index) = self.ui.drop_down(id!(greeting_selector)).changed(&actions) {
+            if let Some(&mode) = GreetingMode::ALL.get(index) {
+                self.persona.greeting.mode = mode;
+            }
+        }
+
+        if self.ui.button(id!(save_persona_button)).clicked(&actions) {
+            self.save_persona(cx);
+        }
+
         if let Some(_value) = self.ui.drop_down(id!(transcription_model_selector)).changed(&actions) {
             self.update_session_config(cx);
         }
     }
 
     fn handle_audio_devices(&mut self, cx: &mut Cx, devices: &AudioDevicesEvent) {
-        log!(
+        log_debug!(
+            Audio,
             "App::handle_audio_devices called with {} devices",
             devices.descs.len()
         );
         for desc in &devices.descs {
-            log!("Audio device: {}", desc);
+            log_debug!(Audio, "Audio device: {}", desc);
         }
 
-        // Use default input and output devices
-        let default_input = devices.default_input();
-        let default_output = devices.default_output();
+        // Devices come and go (hot-plug), fall back to the defaults when the chosen one is gone
+        let input_notice = self
+            .input_devices
+            .update(devices.descs.iter().filter(|desc| desc.device_type.is_input()));
+        let output_notice = self
+            .output_devices
+            .update(devices.descs.iter().filter(|desc| desc.device_type.is_output()));
+
+        let input_selector = self.ui.drop_down(id!(input_device_selector));
+        input_selector.set_labels(cx, self.input_devices.labels());
+        input_selector.set_selected_item(cx, self.input_devices.selected_index());
+
+        let output_selector = self.ui.drop_down(id!(output_device_selector));
+        output_selector.set_labels(cx, self.output_devices.labels());
+        output_selector.set_selected_item(cx, self.output_devices.selected_index());
+
+        self.update_channel_mix_selector(cx);
+        if input_notice.is_some() {
+            self.input_device_changed(cx);
+        }
+
+        let notices = [
+            input_notice.map(|notice| notice.describe("Microphone")),
+            output_notice.map(|notice| notice.describe("Speakers")),
+        ];
+        let message = notices.into_iter().flatten().collect::<Vec<_>>().join(" · ");
+        if !message.is_empty() {
+            log_warn!(Audio, "{}", message);
+            self.ui.label(id!(status_label)).set_text(cx, &message);
+        }
 
-        log!("Default input: {:?}", default_input);
-        log!("Default output: {:?}", default_output);
+        log_info!(Audio, "Using input: {:?}", self.input_devices.active_device().map(|desc| &desc.name));
+        log_info!(Audio, "Using output: {:?}", self.output_devices.active_device().map(|desc| &desc.name));
 
-        cx.use_audio_inputs(&default_input);
-        cx.use_audio_outputs(&default_output);
+        cx.use_audio_inputs(&self.input_devices.active_devices());
+        cx.use_audio_outputs(&self.output_devices.active_devices());
     }
 }
 
 impl AppMain for App {
     fn handle_event(&mut self, cx: &mut Cx, event: &Event) {
         if let Event::Timer(_timer_event) = event {
+            if let Some(index) = self
+                .pending_retries
+                .iter()
+                .position(|(timer, _)| timer.is_event(event).is_some())
+            {
+                let (_, sent_event) = self.pending_retries.remove(index);
+                self.retry_client_event(sent_event);
+            }
+
+            if self.greeting_timer.is_some_and(|timer| timer.is_event(event).is_some()) {
+                self.greeting_timer = None;
+                if self.conversation_active && !self.ai_is_responding {
+                    self.create_greeting_response();
+                }
+            }
+
+            if let Some(level_timer) = &self.level_meter_timer {
+                if level_timer.is_event(event).is_some() {
+                    self.update_input_level_meter(cx);
+                    self.check_wake_word_enrollment(cx);
+                    self.handle_telephony_events(cx);
+                    self.handle_token_requests(cx);
+                    self.enforce_budget(cx);
+                }
+            }
+
             if let Some(audio_timer) = &self.audio_streaming_timer {
                 if audio_timer.is_event(event).is_some() {
                     if self.conversation_active {
                         self.send_audio_chunk_to_openai(cx);
+                        self.check_session_rollover(cx);
+                    }
+
+                    self.trace_playback_and_state();
+
+                    // The mic was gated while a preview played, see `play_voice_preview`
+                    if self.preview_playing && self.playback.lock().unwrap().is_idle() {
+                        self.preview_playing = false;
+                        *self.is_recording.lock().unwrap() = true;
+                    }
+
+                    if self.latency.awaiting_first_render() {
+                        let rendered_at = self.playback.lock().unwrap().stream_started_at();
+                        if let Some(rendered_at) = rendered_at {
+                            if self.latency.audio_rendered(rendered_at).is_some() {
+                                self.update_latency_label(cx);
+                            }
+                        }
                     }
 
                     // Check if we should resume recording when playback buffer is empty
                     // This is the backup mechanism for when toggle is OFF (no interruptions)
-                    if self.playback_audio.lock().unwrap().is_empty() {
+                    if self.playback.lock().unwrap().is_idle() {
                         let interruptions_enabled = self.ui.check_box(id!(toggle_interruptions)).active(cx);
                         
                         if !interruptions_enabled {
@@ -430,7 +1387,7 @@ impl AppMain for App {
                             // (when interruptions are enabled, recording control is handled elsewhere)
                             if let Ok(mut is_recording) = self.is_recording.try_lock() {
                                 if !*is_recording && self.conversation_active && !self.ai_is_responding {
-                                    println!("Auto-resuming recording - playback empty and interruptions disabled");
+                                    log_debug!(Audio, "Auto-resuming recording - playback empty and interruptions disabled");
                                     *is_recording = true;
                                     self.ui.label(id!(status_label)).set_text(cx, "🎤 Listening...");
                                 }
@@ -445,104 +1402,107 @@ impl AppMain for App {
         self.ui.handle_event(cx, event, &mut Scope::empty());
 
         self.handle_websocket_messages(cx);
+        self.pump_relay(cx);
     }
 }
 
 impl App {
     fn setup_audio(&mut self, cx: &mut Cx) {
         if self.audio_setup_done {
-            log!("Audio already setup, skipping");
+            log_debug!(Audio, "Audio already setup, skipping");
             return;
         }
 
         let recorded_audio = self.recorded_audio.clone();
         let is_recording = self.is_recording.clone();
-
-        log!("Setting up audio input callback");
+        let is_muted = self.is_muted.clone();
+        let input_level = self.input_level.clone();
+        let channel_mix = self.channel_mix.clone();
+        let wake_enrollment = self.wake_enrollment.clone();
+        let preprocess_config = self.preprocess_config.clone();
+        let bridge_active = self.bridge_active.clone();
+        let mut downmixer = Downmixer::default();
+        let mut preprocessor = Preprocessor::default();
+        let mut mono = Vec::new();
+        let mut captured = Vec::new();
+
+        log_info!(Audio, "Setting up audio input callback");
 
         // Audio input callback - capture for OpenAI streaming
         cx.audio_input(0, move |_info, input_buffer| {
+            // During a phone call the caller is the input, see `TelephonyBridge`
+            if bridge_active.try_lock().map(|active| *active).unwrap_or(false) {
+                return;
+            }
+
+            // Mix down to mono first, the mic may not be wired to the first channel
+            let mix = channel_mix.try_lock().map(|mix| *mix).unwrap_or_default();
+            mono.clear();
+            downmixer.process(
+                mix,
+                input_buffer.channel_count(),
+                input_buffer.frame_count(),
+                |index| input_buffer.channel(index),
+                &mut mono,
+            );
+
+            // Meter the device even while muted so users can tell the mic is alive
+            if let Ok(mut level) = input_level.try_lock() {
+                level.update(&mono);
+            }
+
+            if is_muted.try_lock().map(|muted| *muted).unwrap_or(true) {
+                return;
+            }
+
+            // Downsample from 48kHz to 24kHz by taking every other sample
+            // TODO: this is a simple decimation - for better quality, we should use proper filtering
+            captured.clear();
+            captured.extend(mono.iter().step_by(2));
+
+            // Keep the filters running between turns so they are settled when the user speaks
+            let config = preprocess_config.try_lock().map(|config| *config).unwrap_or_default();
+            preprocessor.process(&config, &mut captured);
+
+            if let Ok(Some(enrollment)) = wake_enrollment.try_lock().as_deref_mut() {
+                enrollment.extend_from_slice(&captured);
+            }
+
             if let Ok(is_recording_guard) = is_recording.try_lock() {
                 if *is_recording_guard {
                     if let Ok(mut recorded) = recorded_audio.try_lock() {
-                        let channel = input_buffer.channel(0);
-
-                        // Downsample from 48kHz to 24kHz by taking every other sample
-                        // TODO: this is a simple decimation - for better quality, we should use proper filtering
-                        for i in (0..channel.len()).step_by(2) {
-                            recorded.push(channel[i]);
-                        }
+                        recorded.extend_from_slice(&captured);
                     }
                 }
             }
         });
 
-        let playback_audio = self.playback_audio.clone();
-        let playback_position = self.playback_position.clone();
-        let is_playing = self.is_playing.clone();
+        let playback = self.playback.clone();
+        let bridge_active = self.bridge_active.clone();
+        let mut mono = Vec::new();
 
         // Audio output callback - plays OpenAI response audio
         cx.audio_output(0, move |_info, output_buffer| {
             // Always start with silence
             output_buffer.zero();
 
-            if let Ok(mut playback) = playback_audio.try_lock() {
-                if let Ok(mut pos) = playback_position.try_lock() {
-                    if let Ok(mut playing) = is_playing.try_lock() {
-                        // Check if we should continue playing
-                        if *playing && !playback.is_empty() && *pos < playback.len() * 2 {
-                            // Write to all output channels (mono -> stereo if needed)
-                            let frame_count = output_buffer.frame_count();
-                            let channel_count = output_buffer.channel_count();
-                            
-                            let mut samples_to_drain = 0;
-
-                            for frame_idx in 0..frame_count {
-                                // Upsample from 24kHz to 48kHz by duplicating each sample
-                                let sample_idx = *pos / 2; // Each 24kHz sample maps to 2 48kHz samples
-
-                                if sample_idx < playback.len() {
-                                    let audio_sample = playback[sample_idx];
-
-                                    // Write the same sample to all output channels
-                                    for channel_idx in 0..channel_count {
-                                        let channel = output_buffer.channel_mut(channel_idx);
-                                        channel[frame_idx] = audio_sample;
-                                    }
-
-                                    *pos += 1;
-                                    
-                                    // Track how many samples we can safely remove (every 2 pos increments = 1 sample)
-                                    if *pos % 2 == 0 {
-                                        samples_to_drain += 1;
-                                    }
-                                } else {
-                                    // Reached end of audio data
-                                    *playing = false;
-                                    *pos = 0;
-                                    // Drain remaining samples since we're done
-                                    samples_to_drain = playback.len();
-                                    break;
-                                }
-                            }
-                            
-                            // Remove consumed samples from the front of the buffer
-                            if samples_to_drain > 0 && samples_to_drain <= playback.len() {
-                                playback.drain(..samples_to_drain);
-                                // Adjust position since we removed samples from the front
-                                *pos = (*pos).saturating_sub(samples_to_drain * 2);
-                                // log!("Drained {} samples, buffer size now: {}, pos: {}", 
-                                //         samples_to_drain, playback.len(), *pos);
-                            }
-                        } else {
-                            // Not playing or no data - ensure we output silence
-                            if *playing && playback.is_empty() {
-                                *playing = false;
-                                *pos = 0;
-                            }
-                        }
-                    }
-                }
+            // During a phone call the bridge drains the playback buffer
+            if bridge_active.try_lock().map(|active| *active).unwrap_or(false) {
+                return;
+            }
+
+            let frame_count = output_buffer.frame_count();
+            mono.clear();
+            mono.resize(frame_count, 0.0);
+
+            // The jitter buffer upsamples from 24kHz to 48kHz and applies the fades
+            if let Ok(mut playback) = playback.try_lock() {
+                playback.render(&mut mono);
+            }
+
+            // Write the same sample to all output channels (mono -> stereo if needed)
+            for channel_idx in 0..output_buffer.channel_count() {
+                output_buffer.channel_mut(channel_idx)[..frame_count].copy_from_slice(&mono);
             }
         });
 
@@ -550,30 +1510,158 @@ impl App {
     }
 
     fn connect_to_openai(&mut self, cx: &mut Cx) {
-        if self.openai_api_key.is_none() {
+        if !self.has_credentials() {
             self.ui
                 .label(id!(connection_status))
                 .set_text(cx, "❌ Please set OPENAI_API_KEY");
             return;
         }
 
-        // Create WebSocket connection
-        let url =
-            "wss://api.openai.com/v1/realtime?model=gpt-4o-realtime-preview-2025-06-03".to_string();
+        // A call only carries 8kHz G.711, no point in streaming more than that
+        self.codec = AudioCodec::new(self.call_format.unwrap_or(self.settings.audio_format));
+        // With a client secret the socket opens once it arrives, see `client_secret_received`
+        self.websocket = None;
+        self.pending_client_secrets
+            .retain(|_, requester| !matches!(requester, SecretRequester::Connection));
+        let mut session = self.session_config();
+        // `selected_voice` is only filled in once the session exists
+        if session.voice.is_empty() {
+            session.voice = self.ui.drop_down(id!(voice_selector)).selected_label();
+        }
+        self.websocket = self
+            .session_request(cx, session, SecretRequester::Connection)
+            .map(WebSocket::open);
+        self.drop_standby();
+        self.session_lifetime = None;
+        self.history.clear();
+        self.out_of_band.clear();
+        self.sent_events = SentEvents::default();
+        for (timer, _) in self.pending_retries.drain(..) {
+            cx.stop_timer(timer);
+        }
+        self.trace = TraceRecorder::default();
+        self.traced_playback_start = None;
+        self.traced_state = None;
+        self.ui
+            .label(id!(connection_status))
+            .set_text(cx, "🔄 Connecting...");
+
+        log_info!(Transport, "WebSocket connection initiated");
+    }
+
+    fn realtime_request(&self) -> HttpRequest {
+        realtime_request(self.openai_api_key.as_deref().unwrap_or_default())
+    }
+
+    /// Whether sessions can be opened, only a token service does without the API key.
+    fn has_credentials(&self) -> bool {
+        matches!(self.settings.auth, RealtimeAuth::TokenService { .. }) || self.openai_api_key.is_some()
+    }
+
+    /// The request that opens a Realtime session configured like `session`. With the API
+    /// key that is right away. Otherwise a client secret is fetched first and None returned,
+    /// the session opens for `requester` once it arrives, see `client_secret_received`.
+    fn session_request(
+        &mut self,
+        cx: &mut Cx,
+        session: SessionConfig,
+        requester: SecretRequester,
+    ) -> Option<HttpRequest> {
+        match self.settings.auth.clone() {
+            RealtimeAuth::ApiKey => return Some(self.realtime_request()),
+            RealtimeAuth::Ephemeral => self.mint_client_secret(cx, session, requester),
+            RealtimeAuth::TokenService { url, access_token } => {
+                let mut request = HttpRequest::new(url, HttpMethod::POST);
+                if let Some(access_token) = access_token {
+                    request.set_header("Authorization".to_string(), format!("Bearer {}", access_token));
+                }
+                // The service decides the rest of the session
+                request.set_header("Content-Type".to_string(), "application/json".to_string());
+                request.set_body(serde_json::to_vec(&serde_json::json!({ "voice": session.voice })).unwrap_or_default());
+                let request_id = LiveId::unique();
+                cx.http_request(request_id, request);
+                self.pending_client_secrets.insert(request_id, requester);
+            }
+        }
+        None
+    }
+
+    fn secret_pending(&self, is_requester: impl Fn(&SecretRequester) -> bool) -> bool {
+        self.pending_client_secrets.values().any(is_requester)
+    }
 
-        let mut request = HttpRequest::new(url, HttpMethod::GET);
+    /// Asks the sessions endpoint for a client secret bound to `session`.
+    fn mint_client_secret(&mut self, cx: &mut Cx, session: SessionConfig, requester: SecretRequester) {
+        let body = SessionsRequest {
+            model: REALTIME_MODEL,
+            session,
+            client_secret: ClientSecretConfig::expiring_after(self.settings.token_service.expires_after_secs),
+        };
+        let mut request = HttpRequest::new(SESSIONS_URL.to_string(), HttpMethod::POST);
         request.set_header(
             "Authorization".to_string(),
-            format!("Bearer {}", self.openai_api_key.as_ref().unwrap()),
+            format!("Bearer {}", self.openai_api_key.as_deref().unwrap_or_default()),
         );
-        request.set_header("OpenAI-Beta".to_string(), "realtime=v1".to_string());
+        request.set_header("Content-Type".to_string(), "application/json".to_string());
+        request.set_body(serde_json::to_vec(&body).unwrap_or_default());
 
-        self.websocket = Some(WebSocket::open(request));
-        self.ui
-            .label(id!(connection_status))
-            .set_text(cx, "🔄 Connecting...");
+        let request_id = LiveId::unique();
+        cx.http_request(request_id, request);
+        self.pending_client_secrets.insert(request_id, requester);
+    }
 
-        log!("WebSocket connection initiated");
+    fn client_secret_received(&mut self, cx: &mut Cx, requester: SecretRequester, secret: Result<ClientSecret, String>) {
+        // Our own secrets are kept out of the logs, handed out ones are the client's business
+        if let (Ok(secret), false) = (&secret, matches!(requester, SecretRequester::Client(_))) {
+            logging::register_secret(&secret.value);
+        }
+        match (requester, secret) {
+            (SecretRequester::Connection, Ok(secret)) => {
+                log_info!(Transport, "Connecting with a client secret valid until {}", secret.expires_at);
+                self.websocket = Some(WebSocket::open(realtime_request(&secret.value)));
+            }
+            (SecretRequester::Connection, Err(e)) => {
+                log_error!(Transport, "Failed to get a client secret: {}", e);
+                self.ui
+                    .label(id!(connection_status))
+                    .set_text(cx, &format!("❌ Couldn't get a session token: {}", e));
+            }
+            (SecretRequester::Standby { voice }, Ok(secret)) => {
+                self.start_standby(realtime_request(&secret.value), voice);
+            }
+            (SecretRequester::Standby { .. }, Err(e)) => {
+                // Retried by `check_session_rollover`
+                log_warn!(Transport, "Failed to get a client secret for the standby session: {}", e);
+            }
+            (SecretRequester::Preview { voice }, Ok(secret)) => {
+                self.start_preview(realtime_request(&secret.value), voice);
+            }
+            (SecretRequester::Preview { .. }, Err(e)) => {
+                log_warn!(Transport, "Failed to get a client secret for the voice preview: {}", e);
+                self.ui
+                    .label(id!(status_label))
+                    .set_text(cx, &format!("❌ Voice preview failed: {}", e));
+            }
+            (SecretRequester::Relay(id), Ok(secret)) => {
+                if let Some(relay) = &mut self.relay {
+                    relay.open_upstream(id, realtime_request(&secret.value));
+                }
+            }
+            (SecretRequester::Relay(id), Err(e)) => {
+                log_warn!(Transport, "Failed to get a client secret for relay client {}: {}", id, e);
+                if let Some(relay) = &mut self.relay {
+                    relay.refuse(id, "the upstream session could not be opened");
+                }
+            }
+            (SecretRequester::Client(request), Ok(secret)) => {
+                log_info!(Transport, "Minted a client secret valid until {}", secret.expires_at);
+                request.respond(200, &token_response(&secret, REALTIME_MODEL));
+            }
+            (SecretRequester::Client(request), Err(e)) => {
+                log_error!(Transport, "Failed to mint a client secret: {}", e);
+                request.respond(502, &error_body(&e));
+            }
+        }
     }
 
     fn handle_websocket_messages(&mut self, cx: &mut Cx) {
@@ -586,26 +1674,40 @@ impl App {
             }
         }
 
+        self.handle_standby_messages();
+        self.handle_preview_messages(cx);
+
         // Process messages
         for message in messages {
             match message {
                 WebSocketMessage::String(data) => {
-                    // log!("Received WebSocket message: {}", data);
+                    log_trace!(Transport, "Received WebSocket message: {}", logging::payload(&data));
                     self.handle_openai_message(cx, &data);
                 }
                 WebSocketMessage::Binary(data) => {
-                    log!("Received binary WebSocket message: {} bytes", data.len());
+                    log_debug!(Transport, "Received binary WebSocket message: {} bytes", data.len());
                 }
                 WebSocketMessage::Error(error) => {
-                    log!("WebSocket error: {}", error);
+                    log_error!(Transport, "WebSocket error: {}", error);
                 }
                 WebSocketMessage::Closed => {
-                    log!("WebSocket closed");
+                    // Expired before we found a gap between turns, switch right away
+                    if self.standby.as_ref().is_some_and(|standby| standby.ready) {
+                        log_warn!(Transport, "Session closed during rollover, switching to the standby session");
+                        self.switch_to_standby(cx);
+                        continue;
+                    }
+                    log_info!(Transport, "WebSocket closed");
                     self.ui
                         .label(id!(connection_status))
                         .set_text(cx, "❌ Disconnected");
                     self.is_connected = false;
                     self.conversation_active = false;
+                    // A caller would otherwise be left listening to silence
+                    if let Some(telephony) = self.telephony.as_ref().filter(|_| self.call_format.is_some()) {
+                        log_warn!(Transport, "Session closed during a call, hanging up");
+                        telephony.hang_up();
+                    }
                     self.update_ui_state(cx);
                 },
                 _ => {}
@@ -613,53 +1715,76 @@ impl App {
         }
     }
 
+    /// Near or far field noise reduction may follow the microphone.
+    fn input_device_changed(&mut self, cx: &mut Cx) {
+        if self.is_connected && self.settings.preprocessing.server_noise_reduction == ServerNoiseReduction::Auto {
+            self.update_session_config(cx);
+        }
+    }
+
     /// Update the OpenAI Realtime session with audio configuration
     fn update_session_config(&mut self, cx: &mut Cx) {
-        self.selected_voice = self.ui.drop_down(id!(voice_selector)).selected_label();
-        self.ui.view(id!(voice_selector_wrapper)).set_visible(cx, false);
+        // Mid-session the dropdown may hold a voice we are still switching to
+        if !self.conversation_active {
+            self.selected_voice = self.ui.drop_down(id!(voice_selector)).selected_label();
+        }
         self.ui.view(id!(selected_voice_view)).set_visible(cx, true);
         self.ui.label(id!(selected_voice)).set_text(cx, format!("Selected voice: {}", self.selected_voice).as_str());
 
-        let session_config = SessionConfig {
+        let message = OpenAIRealtimeMessage::SessionUpdate {
+            session: self.session_config(),
+        };
+
+        self.send_openai_message(message);
+    }
+
+    fn session_config(&self) -> SessionConfig {
+        SessionConfig {
             modalities: vec!["text".to_string(), "audio".to_string()],
-            instructions: "You are a helpful AI assistant. Respond naturally and conversationally. Always respond in the same language as the user."
-                .to_string(),
+            instructions: render_template(&self.persona.instructions, &self.template_vars()),
             voice: self.selected_voice.clone(),
-            input_audio_format: "pcm16".to_string(),
-            output_audio_format: "pcm16".to_string(),
+            input_audio_format: self.codec.format().as_str().to_string(),
+            output_audio_format: self.codec.format().as_str().to_string(),
             input_audio_transcription: Some(TranscriptionConfig {
                 model: self.ui.drop_down(id!(transcription_model_selector)).selected_label()
             }),
-            input_audio_noise_reduction: Some(NoiseReductionConfig {
-                noise_reduction_type: "far_field".to_string(), // TODO: do this programmatically based on microphone type
-            }),
-            turn_detection: Some(TurnDetectionConfig {
-                detection_type: "server_vad".to_string(), // Server-side VAD. Turns are detected by the server.
-                threshold: 0.5,
-                prefix_padding_ms: 300,
-                silence_duration_ms: 200,
-                interrupt_response: true,
-                create_response: true,
-            }),
-            tools: vec![],
-            tool_choice: "none".to_string(),
-            temperature: 0.8,
+            input_audio_noise_reduction: self
+                .settings
+                .preprocessing
+                .server_noise_reduction
+                .resolve(self.input_devices.active_device().map(|desc| desc.name.as_str()))
+                .map(|noise_reduction_type| NoiseReductionConfig {
+                    noise_reduction_type: noise_reduction_type.to_string(),
+                }),
+            // Turns are detected by the server unless the local VAD takes care of it
+            turn_detection: (self.settings.vad.mode != VadMode::TurnTaking).then(server_turn_detection),
+            tools: self.persona.tools.clone(),
+            tool_choice: if self.persona.tools.is_empty() { "none" } else { "auto" }.to_string(),
+            temperature: self.persona.temperature,
             max_response_output_tokens: Some(4096),
-        };
-
-        let message = OpenAIRealtimeMessage::SessionUpdate {
-            session: session_config,
-        };
+        }
+    }
 
-        self.send_openai_message(message);
+    fn template_vars(&self) -> TemplateVars {
+        TemplateVars::new(self.settings.user_name.as_deref())
     }
 
     fn handle_openai_message(&mut self, cx: &mut Cx, data: &str) {
+        let event_type = serde_json::from_str::<EventType>(data)
+            .map(|event| event.event_type)
+            .unwrap_or("unknown");
+        self.trace.instant(Track::ServerEvents, event_type, None);
+
         match serde_json::from_str::<OpenAIRealtimeResponse>(data) {
             Ok(response) => {
                 match response {
-                    OpenAIRealtimeResponse::SessionCreated { .. } => {
-                        log!("OpenAI session created successfully");
+                    OpenAIRealtimeResponse::SessionCreated { session } => {
+                        log_info!(Protocol, "OpenAI session created successfully");
+                        self.session_lifetime = Some(SessionLifetime::from_session(&session));
+                        self.usage.start_clock();
+                        self.latency = LatencyTracker::default();
+                        self.update_usage_label(cx);
+                        self.update_latency_label(cx);
                         self.ui
                             .label(id!(status_label))
                             .set_text(cx, "✅ Session ready");
@@ -672,21 +1797,33 @@ impl App {
                         self.update_ui_state(cx);
                     }
                     OpenAIRealtimeResponse::SessionUpdated { .. } => {
-                        log!("OpenAI session updated successfully");
+                        log_info!(Protocol, "OpenAI session updated successfully");
                         self.ui
                             .label(id!(status_label))
                             .set_text(cx, "✅ Session configured");
-                        self.start_conversation(cx);
+                        // Later updates (instructions, transcription model) apply to the running conversation
+                        if !self.conversation_active {
+                            self.start_conversation(cx);
+                        }
                     }
-                    OpenAIRealtimeResponse::ResponseAudioDelta { item_id, delta, .. } => {
+                    OpenAIRealtimeResponse::ResponseAudioDelta { response_id, item_id, delta, .. } => {
                         if self.user_is_interrupting {
-                            log!("Ignoring AI audio delta - user is interrupting");
+                            log_debug!(Protocol, "Ignoring AI audio delta - user is interrupting");
+                            return;
+                        }
+
+                        // Deltas keep arriving for a short while after response.cancel
+                        if self.cancelled_response_id.as_ref() == Some(&response_id) {
                             return;
                         }
+                        self.current_response_id = Some(response_id);
+                        self.latency.audio_delta_received();
 
                         if self.current_assistant_item_id.is_none() {
                             self.current_assistant_item_id = Some(item_id.clone());
-                            log!("Started receiving audio for assistant item ID: {}", item_id);
+                            log_debug!(Protocol, "Started receiving audio for assistant item ID: {}", item_id);
+                            // Played samples are counted per item, for `conversation.item.truncate`
+                            self.playback.lock().unwrap().begin_stream(Some(&item_id));
                         }
 
                         self.ai_is_responding = true;
@@ -709,6 +1846,10 @@ impl App {
 
                         self.ui.label(id!(status_label)).set_text(cx, "🔊 Playing audio...");
                     }
+                    OpenAIRealtimeResponse::ResponseAudioDone { .. } => {
+                        // Let the jitter buffer play out the tail even if it's below the target latency
+                        self.playback.lock().unwrap().end_stream();
+                    }
                     OpenAIRealtimeResponse::ResponseAudioTranscriptDelta { delta, .. } => {
                         self.ai_is_responding = true;
 
@@ -729,11 +1870,65 @@ impl App {
                             .label(id!(transcript_label))
                             .set_text(cx, &self.current_transcript);
                     }
-                    OpenAIRealtimeResponse::ResponseDone { .. } => {
+                    OpenAIRealtimeResponse::ResponseAudioTranscriptDone { item_id, transcript } => {
+                        log_info!(Protocol, "Assistant: {}", logging::transcript(&transcript));
+                        self.history.set_text(&item_id, Role::Assistant, &transcript);
+                    }
+                    OpenAIRealtimeResponse::ResponseTextDone { response_id, item_id, text } => {
+                        if self.out_of_band.is_out_of_band(&response_id) {
+                            return;
+                        }
+                        self.history.set_text(&item_id, Role::Assistant, &text);
+                    }
+                    OpenAIRealtimeResponse::InputAudioTranscriptionCompleted { item_id, transcript } => {
+                        log_info!(Protocol, "User: {}", logging::transcript(&transcript));
+                        self.history.set_text(&item_id, Role::User, &transcript);
+                    }
+                    OpenAIRealtimeResponse::ResponseCreated { response } => {
+                        if self.out_of_band.response_created(&response.id, response.metadata.as_ref()) {
+                            log_debug!(Protocol, "Out-of-band response {} created", response.id);
+                            return;
+                        }
+                        self.trace.begin(Track::AssistantResponse, "response", Instant::now());
+                        self.latency.response_created();
+                    }
+                    OpenAIRealtimeResponse::ResponseDone { response } if self.out_of_band.is_out_of_band(&response.id) => {
+                        if let Some(usage) = response.usage {
+                            self.record_usage(cx, response.id.clone(), usage);
+                        }
+                        if let Some(result) = self.out_of_band.finish(&response.id, response.status, &response.output) {
+                            self.show_out_of_band_result(cx, result);
+                        }
+                    }
+                    OpenAIRealtimeResponse::ResponseDone { response } => {
+                        self.trace.end(Track::AssistantResponse, "response", Instant::now());
+                        // One exchange per wake phrase, tool calls are followed by another response
+                        if self.awake_since.is_some() && response.answers_user() {
+                            self.go_to_sleep(cx);
+                        }
+                        if let Some(usage) = response.usage {
+                            self.prune_context(cx, usage.input_tokens);
+                            self.record_usage(cx, response.id, usage);
+                        }
+                        if self.latency.response_done().is_some() {
+                            self.update_latency_label(cx);
+                        }
+
                         let status_label = self.ui.label(id!(status_label));
                         self.user_is_interrupting = false;
                         self.ai_is_responding = false;
                         self.current_assistant_item_id = None;
+                        self.current_response_id = None;
+                        self.cancelled_response_id = None;
+
+                        let underruns = {
+                            let mut playback = self.playback.lock().unwrap();
+                            playback.end_stream();
+                            playback.take_underruns()
+                        };
+                        if underruns > 0 {
+                            log_warn!(Audio, "Playback buffer ran dry {} times during the response", underruns);
+                        }
 
                         // Resume recording after AI response is complete
                         if self.conversation_active {
@@ -746,49 +1941,39 @@ impl App {
                                 status_label.set_text(cx, "✅ Response generated - 🎤 listening...");
                             } else {
                                 // Without interruptions, only resume when playback buffer is truly empty
-                                if self.playback_audio.lock().unwrap().is_empty() {
-                                    println!("Setting is_recording to true - response completed and playback empty");
+                                if self.playback.lock().unwrap().is_idle() {
+                                    log_debug!(Audio, "Setting is_recording to true - response completed and playback empty");
                                     *self.is_recording.lock().unwrap() = true;
                                     status_label.set_text(cx, "✅ Response generated - 🎤 listening...");
                                 } else {
                                     status_label.set_text(cx, "✅ Response generated - 🔊 playing audio");
-                                    println!("Playback still active, keeping recording disabled");
+                                    log_debug!(Audio, "Playback still active, keeping recording disabled");
                                 }
                             }
                         }
                     }
                     OpenAIRealtimeResponse::InputAudioBufferSpeechStarted { .. } => {
-                        log!("Speech detected by OpenAI - interrupting AI audio");
+                        log_info!(Protocol, "Speech detected by OpenAI - interrupting AI audio");
+                        self.trace.begin(Track::UserSpeech, "user speech", Instant::now());
+                        self.user_speech_started(cx);
                         self.ui
                             .label(id!(status_label))
                             .set_text(cx, "🎤 User speech detected");
 
-                        // CRITICAL: Clear the playback audio buffer to stop ongoing AI audio
+                        // CRITICAL: Stop ongoing AI audio (with a short fade to avoid a click)
                         // This prevents audio accumulation and feedback loops
-                        if let Ok(mut playback) = self.playback_audio.try_lock() {
-                            let cleared_samples = playback.len();
-                            playback.clear();
-                            log!(
-                                "Cleared {} audio samples from playback buffer to prevent feedback",
-                                cleared_samples
-                            );
-                        }
-
-                        // Stop current playback and reset position
-                        if let Ok(mut is_playing) = self.is_playing.try_lock() {
-                            *is_playing = false;
-                        }
-                        if let Ok(mut position) = self.playback_position.try_lock() {
-                            *position = 0;
-                        }
+                        self.playback.lock().unwrap().fade_out();
 
                         // Resume recording immediately when user starts speaking
                         if self.conversation_active {
                             *self.is_recording.lock().unwrap() = true;
                         }
                     }
-                    OpenAIRealtimeResponse::InputAudioBufferSpeechStopped { .. } => {
-                        log!("Speech ended, processing...");
+                    OpenAIRealtimeResponse::InputAudioBufferSpeechStopped { audio_end_ms, .. } => {
+                        log_info!(Protocol, "Speech ended, processing...");
+                        self.trace.end(Track::UserSpeech, "user speech", Instant::now());
+                        self.user_is_speaking = false;
+                        self.latency.speech_stopped(audio_end_ms);
                         self.ui
                             .label(id!(status_label))
                             .set_text(cx, "🤔 Processing...");
@@ -798,7 +1983,8 @@ impl App {
                             *self.is_recording.lock().unwrap() = false;
                         }
                     }
-                    OpenAIRealtimeResponse::ConversationItemCreated { .. } => {
+                    OpenAIRealtimeResponse::ConversationItemCreated { item } => {
+                        self.track_conversation_item(&item);
                         self.ui
                             .label(id!(status_label))
                             .set_text(cx, "✅ User speech transcribed");
@@ -808,63 +1994,1029 @@ impl App {
                             .label(id!(status_label))
                             .set_text(cx, "✅ AI speech truncated");
                     }
+                    OpenAIRealtimeResponse::ConversationItemDeleted { item_id } => {
+                        log_debug!(Protocol, "Conversation item {} deleted", item_id);
+                        self.history.remove(&item_id);
+                    }
                     OpenAIRealtimeResponse::Error { error } => {
-                        log!("OpenAI API error: {:?}", error);
-                        self.ui
-                            .label(id!(status_label))
-                            .set_text(cx, &format!("❌ Error: {}", error.message));
-
-                        // Resume recording on error
-                        if self.conversation_active {
-                            *self.is_recording.lock().unwrap() = true;
-                        }
+                        self.handle_realtime_error(cx, error);
                     }
                     _ => {
-                        log!("Received other OpenAI message type: {:?}", data);
+                        log_debug!(Protocol, "Unhandled OpenAI event: {}", event_type);
+                        log_trace!(Protocol, "{}", logging::payload(data));
                     }
                 }
             }
             Err(e) => {
-                log!("Failed to parse OpenAI message: {}", e);
+                log_warn!(Protocol, "Failed to parse OpenAI {} event: {}", event_type, e);
             }
         }
     }
 
     fn send_openai_message(&mut self, message: OpenAIRealtimeMessage) {
-        if let Some(websocket) = &mut self.websocket {
-            match serde_json::to_string(&message) {
-                Ok(json_str) => {
-                    // log!("Sending to OpenAI: {}", json_str);
-                    if let Err(_) = websocket.send_string(json_str) {
-                        log!("Failed to send message to OpenAI");
-                    }
-                }
-                Err(e) => {
-                    log!("Failed to serialize message: {}", e);
-                }
-            }
+        // Audio appends are traced on their own track, see `send_audio_chunk_to_openai`
+        if !matches!(message, OpenAIRealtimeMessage::InputAudioBufferAppend { .. }) {
+            self.trace.instant(Track::ClientEvents, message.event_type(), None);
+        }
+
+        // Audio appends are too big to keep around and pointless to retry
+        let payload = match &message {
+            OpenAIRealtimeMessage::InputAudioBufferAppend { .. } => None,
+            _ => serde_json::to_value(&message).ok(),
+        };
+        self.send_client_event(&message, message.event_type(), payload, 0);
+    }
+
+    fn send_client_event<T: Serialize>(
+        &mut self,
+        event: &T,
+        event_type: &'static str,
+        payload: Option<serde_json::Value>,
+        attempt: u32,
+    ) {
+        let Some(websocket) = &mut self.websocket else {
+            return;
+        };
+
+        let event_id = self.sent_events.next_event_id();
+        match serde_json::to_string(&ClientEvent { event_id: &event_id, event }) {
+            Ok(json_str) => {
+                log_trace!(Transport, "Sending to OpenAI: {}", logging::payload(&json_str));
+                if websocket.send_string(json_str).is_err() {
+                    log_error!(Transport, "Failed to send message to OpenAI");
+                }
+            }
+            Err(e) => {
+                log_error!(Protocol, "Failed to serialize message: {}", e);
+            }
+        }
+
+        self.sent_events.record(SentEvent {
+            event_id,
+            event_type,
+            payload,
+            attempt,
+        });
+    }
+
+    fn retry_client_event(&mut self, sent_event: SentEvent) {
+        let Some(payload) = sent_event.payload else {
+            return;
+        };
+        log_info!(
+            Protocol,
+            "Retrying {} (attempt {})",
+            sent_event.event_type,
+            sent_event.attempt + 1
+        );
+        self.send_client_event(&payload, sent_event.event_type, Some(payload.clone()), sent_event.attempt + 1);
+    }
+
+    /// Classify a server error, correlate it with the client event that caused it and
+    /// apply the configured recovery policy for its class.
+    fn handle_realtime_error(&mut self, cx: &mut Cx, details: ErrorDetails) {
+        let error = RealtimeError {
+            kind: ErrorKind::classify(details.error_type.as_deref(), details.code.as_deref()),
+            caused_by: details
+                .event_id
+                .as_deref()
+                .and_then(|event_id| self.sent_events.find(event_id))
+                .cloned(),
+            message: details.message,
+            code: details.code,
+            param: details.param,
+        };
+
+        if error.is_benign() {
+            log_debug!(Protocol, "Ignoring benign OpenAI error: {}", error.describe());
+            return;
+        }
+
+        let action = self.settings.error_recovery.action_for(error.kind);
+        log_error!(
+            Protocol,
+            "OpenAI API error {:?} (code: {:?}, param: {:?}), {:?}: {}",
+            error.kind,
+            error.code,
+            error.param,
+            action,
+            error.describe()
+        );
+        self.trace.instant(
+            Track::ServerEvents,
+            "error",
+            Some(serde_json::json!({
+                "kind": format!("{:?}", error.kind),
+                "action": format!("{:?}", action),
+                "caused_by": error.caused_by.as_ref().map(|event| event.event_type),
+            })),
+        );
+
+        let retryable = error
+            .caused_by
+            .clone()
+            .filter(|event| action == RecoveryAction::Retry && event.payload.is_some())
+            .filter(|event| event.attempt < MAX_RETRY_ATTEMPTS);
+        // Without a retry a failed out-of-band request never gets its `response.created`
+        if retryable.is_none() {
+            let request_id = error
+                .caused_by
+                .as_ref()
+                .and_then(|event| event.payload.as_ref())
+                .and_then(out_of_band::request_id);
+            if let Some(task) = request_id.and_then(|request_id| self.out_of_band.fail(request_id)) {
+                log_warn!(Protocol, "Out-of-band {} failed", task.name());
+                if !matches!(task, OutOfBandTask::ContextSummary { .. }) {
+                    self.ui
+                        .label(id!(out_of_band_label))
+                        .set_text(cx, &format!("❌ The {} failed: {}", task.name(), error.message));
+                }
+            }
+        }
+
+        match action {
+            RecoveryAction::Retry => {
+                match retryable {
+                    Some(sent_event) => {
+                        let delay = RETRY_BASE_DELAY_SECS * 2f64.powi(sent_event.attempt as i32);
+                        let timer = cx.start_timeout(delay);
+                        self.pending_retries.push((timer, sent_event));
+                        self.ui.label(id!(status_label)).set_text(
+                            cx,
+                            &format!("⏳ {}, retrying in {:.1}s", error.describe(), delay),
+                        );
+                        self.resume_recording_after_error();
+                    }
+                    None => self.surface_error(cx, &error),
+                }
+            }
+            RecoveryAction::Reconnect => {
+                self.reconnect(cx, &error.describe());
+            }
+            RecoveryAction::SurfaceToUser => {
+                self.surface_error(cx, &error);
+            }
+            RecoveryAction::Abort => {
+                self.abort_session(cx, &format!("⛔ Session ended: {}", error.describe()));
+            }
+        }
+    }
+
+    fn surface_error(&mut self, cx: &mut Cx, error: &RealtimeError) {
+        self.ui
+            .label(id!(status_label))
+            .set_text(cx, &format!("❌ Error: {}", error.describe()));
+        self.resume_recording_after_error();
+    }
+
+    fn resume_recording_after_error(&mut self) {
+        if self.conversation_active {
+            *self.is_recording.lock().unwrap() = true;
+        }
+    }
+
+    /// Drop the current session and open a new one.
+    fn reconnect(&mut self, cx: &mut Cx, reason: &str) {
+        log_warn!(Transport, "Reconnecting: {}", reason);
+        self.stop_conversation(cx);
+        if let Some(websocket) = self.websocket.as_mut() {
+            websocket.close();
+        }
+        self.is_connected = false;
+        self.update_ui_state(cx);
+
+        self.connect_to_openai(cx);
+        self.ui
+            .label(id!(status_label))
+            .set_text(cx, &format!("🔄 Reconnecting: {}", reason));
+    }
+
+    /// End the session for good: cancel the active response, stop streaming and close the socket.
+    fn abort_session(&mut self, cx: &mut Cx, status: &str) {
+        if self.assistant_is_audible() {
+            self.cancel_response(cx);
+        }
+        self.stop_conversation(cx);
+
+        if let Some(websocket) = self.websocket.as_mut() {
+            websocket.close();
+        }
+        self.drop_standby();
+        self.is_connected = false;
+        self.ui
+            .label(id!(connection_status))
+            .set_text(cx, "❌ Disconnected");
+        self.ui.label(id!(status_label)).set_text(cx, status);
+        self.update_ui_state(cx);
+    }
+
+    /// Open a standby session ahead of the current one's expiry, and swap it in once
+    /// it's configured and nobody is talking.
+    fn check_session_rollover(&mut self, cx: &mut Cx) {
+        match &self.standby {
+            None => {
+                let rollover_due = self
+                    .session_lifetime
+                    .as_ref()
+                    .is_some_and(SessionLifetime::rollover_due);
+                let retry_due = self
+                    .last_rollover_attempt
+                    .is_none_or(|attempt| attempt.elapsed() >= ROLLOVER_RETRY_INTERVAL)
+                    && !self.secret_pending(|requester| matches!(requester, SecretRequester::Standby { .. }));
+                if rollover_due && retry_due {
+                    let remaining = self.session_lifetime.as_ref().map(SessionLifetime::remaining);
+                    log_info!(Transport, "Session expires in {:?}, opening a standby session", remaining);
+                    self.open_standby_session(cx, self.selected_voice.clone());
+                }
+            }
+            Some(standby) if standby.ready && self.between_turns() => {
+                self.switch_to_standby(cx);
+            }
+            Some(_) => {}
+        }
+    }
+
+    fn between_turns(&self) -> bool {
+        !self.ai_is_responding
+            && !self.user_is_speaking
+            && self.current_assistant_item_id.is_none()
+            && self.playback.lock().unwrap().is_idle()
+    }
+
+    fn open_standby_session(&mut self, cx: &mut Cx, voice: String) {
+        self.trace.instant(
+            Track::State,
+            "rollover started",
+            Some(serde_json::json!({ "voice": voice })),
+        );
+        self.last_rollover_attempt = Some(Instant::now());
+        let mut session = self.session_config();
+        session.voice = voice.clone();
+        let requester = SecretRequester::Standby { voice: voice.clone() };
+        if let Some(request) = self.session_request(cx, session, requester) {
+            self.start_standby(request, voice);
+        }
+    }
+
+    fn start_standby(&mut self, request: HttpRequest, voice: String) {
+        self.standby = Some(StandbySession {
+            websocket: WebSocket::open(request),
+            lifetime: None,
+            ready: false,
+            voice,
+        });
+    }
+
+    /// The server can't change the voice once audio has been produced, so the conversation
+    /// moves to a new session with the new voice, the same way as on session expiry.
+    fn change_voice(&mut self, cx: &mut Cx, voice: String) {
+        if !self.is_connected {
+            return;
+        }
+        if voice == self.selected_voice {
+            // Changed back before the switch happened
+            if self.standby.as_ref().is_some_and(|standby| standby.voice != voice) {
+                self.drop_standby();
+            }
+            return;
+        }
+
+        log_info!(Transport, "Changing voice from {} to {}, opening a new session", self.selected_voice, voice);
+        if let Some(voice_index) = VOICES.iter().position(|option| *option == voice) {
+            self.ui.drop_down(id!(voice_selector)).set_selected_item(cx, voice_index);
+        }
+        self.ui
+            .label(id!(status_label))
+            .set_text(cx, &format!("🔄 Switching to voice {}...", voice));
+
+        self.drop_standby();
+        self.open_standby_session(cx, voice);
+    }
+
+    /// Play a sample sentence in `voice`, from the cache or from a throwaway session.
+    fn preview_voice(&mut self, cx: &mut Cx, voice: String) {
+        // The assistant may still be speaking after its response is done
+        if self.ai_is_responding || !self.playback.lock().unwrap().is_idle() {
+            self.ui
+                .label(id!(status_label))
+                .set_text(cx, "❌ Wait for the assistant to finish before previewing a voice");
+            return;
+        }
+
+        if let Some(pcm) = voice_preview::load_cached(&voice) {
+            log_debug!(Audio, "Playing cached {} preview", voice);
+            self.play_voice_preview(cx, &voice, &pcm);
+            return;
+        }
+
+        if !self.has_credentials() {
+            self.ui
+                .label(id!(status_label))
+                .set_text(cx, "❌ Please set OPENAI_API_KEY to generate voice previews");
+            return;
+        }
+
+        log_info!(Transport, "Generating the {} preview in a throwaway session", voice);
+        if let Some(mut previous) = self.preview.take() {
+            previous.websocket.close();
+        }
+        self.pending_client_secrets
+            .retain(|_, requester| !matches!(requester, SecretRequester::Preview { .. }));
+        let mut session = self.session_config();
+        session.voice = voice.clone();
+        let requester = SecretRequester::Preview { voice: voice.clone() };
+        if let Some(request) = self.session_request(cx, session, requester) {
+            self.start_preview(request, voice.clone());
+        }
+        self.ui
+            .label(id!(status_label))
+            .set_text(cx, &format!("⏳ Generating a preview of {}...", voice));
+    }
+
+    fn start_preview(&mut self, request: HttpRequest, voice: String) {
+        self.preview = Some(PreviewSession {
+            websocket: WebSocket::open(request),
+            voice,
+            pcm: Vec::new(),
+        });
+    }
+
+    fn play_voice_preview(&mut self, cx: &mut Cx, voice: &str, pcm: &[u8]) {
+        // Previews are always generated and cached as PCM16
+        let samples = codec::pcm16_to_f32(pcm);
+        {
+            // A generated preview can arrive after the assistant started talking again,
+            // it is cached by then and can be played later
+            let mut playback = self.playback.lock().unwrap();
+            if self.ai_is_responding || !playback.is_idle() {
+                drop(playback);
+                self.ui
+                    .label(id!(status_label))
+                    .set_text(cx, &format!("✅ The {} preview is ready, the assistant is speaking", voice));
+                return;
+            }
+            playback.begin_stream(None);
+            playback.push(&samples);
+            playback.end_stream();
+        }
+
+        // Keep the preview from being picked up by the mic and sent as user speech
+        if self.conversation_active {
+            *self.is_recording.lock().unwrap() = false;
+            self.preview_playing = true;
+        }
+        self.ui
+            .label(id!(status_label))
+            .set_text(cx, &format!("🔊 Previewing {}", voice));
+    }
+
+    fn handle_preview_messages(&mut self, cx: &mut Cx) {
+        let mut messages = Vec::new();
+        if let Some(preview) = &mut self.preview {
+            while let Ok(message) = preview.websocket.try_recv() {
+                messages.push(message);
+            }
+        }
+
+        for message in messages {
+            match message {
+                WebSocketMessage::String(data) => self.handle_preview_message(cx, &data),
+                WebSocketMessage::Error(error) => {
+                    log_warn!(Transport, "Voice preview session failed: {}", error);
+                    self.preview = None;
+                    self.ui
+                        .label(id!(status_label))
+                        .set_text(cx, &format!("❌ Voice preview failed: {}", error));
+                }
+                WebSocketMessage::Closed => {
+                    self.preview = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn handle_preview_message(&mut self, cx: &mut Cx, data: &str) {
+        let Some(preview) = &mut self.preview else {
+            return;
+        };
+
+        let message = match serde_json::from_str::<OpenAIRealtimeResponse>(data) {
+            Ok(OpenAIRealtimeResponse::SessionCreated { .. }) => OpenAIRealtimeMessage::SessionUpdate {
+                // No VAD and no mic audio, the sentence comes in through the instructions
+                session: SessionConfig {
+                    modalities: vec!["text".to_string(), "audio".to_string()],
+                    instructions: voice_preview::instructions(),
+                    voice: preview.voice.clone(),
+                    input_audio_format: "pcm16".to_string(),
+                    output_audio_format: "pcm16".to_string(),
+                    input_audio_transcription: None,
+                    input_audio_noise_reduction: None,
+                    turn_detection: None,
+                    tools: vec![],
+                    tool_choice: "none".to_string(),
+                    temperature: 0.6,
+                    max_response_output_tokens: Some(1024),
+                },
+            },
+            Ok(OpenAIRealtimeResponse::SessionUpdated { .. }) => OpenAIRealtimeMessage::ResponseCreate {
+                response: ResponseConfig {
+                    modalities: vec!["text".to_string(), "audio".to_string()],
+                    instructions: Some(voice_preview::instructions()),
+                    voice: Some(preview.voice.clone()),
+                    output_audio_format: Some("pcm16".to_string()),
+                    tools: None,
+                    tool_choice: None,
+                    temperature: Some(0.6),
+                    max_output_tokens: Some(1024),
+                    conversation: None,
+                    metadata: None,
+                    input: None,
+                },
+            },
+            Ok(OpenAIRealtimeResponse::ResponseAudioDelta { delta, .. }) => {
+                if let Ok(audio_bytes) = general_purpose::STANDARD.decode(&delta) {
+                    preview.pcm.extend_from_slice(&audio_bytes);
+                }
+                return;
+            }
+            Ok(OpenAIRealtimeResponse::ResponseDone { response }) => {
+                let Some(mut preview) = self.preview.take() else {
+                    return;
+                };
+                preview.websocket.close();
+                if response.status.as_deref() != Some("completed") || preview.pcm.is_empty() {
+                    log_warn!(Protocol, "Voice preview response ended with status {:?}", response.status);
+                    self.ui
+                        .label(id!(status_label))
+                        .set_text(cx, &format!("❌ No preview for {}", preview.voice));
+                    return;
+                }
+                voice_preview::save_cached(&preview.voice, &preview.pcm);
+                self.play_voice_preview(cx, &preview.voice, &preview.pcm);
+                return;
+            }
+            Ok(OpenAIRealtimeResponse::Error { error }) => {
+                log_warn!(Protocol, "Voice preview session error: {}", error.message);
+                return;
+            }
+            _ => return,
+        };
+
+        let event_id = self.sent_events.next_event_id();
+        if let Some(preview) = &mut self.preview {
+            send_to_side_session(&mut preview.websocket, "preview", &event_id, &message);
+        }
+    }
+
+    fn drop_standby(&mut self) {
+        if let Some(mut standby) = self.standby.take() {
+            standby.websocket.close();
+        }
+        self.pending_client_secrets
+            .retain(|_, requester| !matches!(requester, SecretRequester::Standby { .. }));
+    }
+
+    fn handle_standby_messages(&mut self) {
+        let mut messages = Vec::new();
+        if let Some(standby) = &mut self.standby {
+            while let Ok(message) = standby.websocket.try_recv() {
+                messages.push(message);
+            }
+        }
+
+        for message in messages {
+            match message {
+                WebSocketMessage::String(data) => {
+                    log_trace!(Transport, "Received standby message: {}", logging::payload(&data));
+                    self.handle_standby_message(&data);
+                }
+                WebSocketMessage::Error(error) => {
+                    log_warn!(Transport, "Standby session failed: {}", error);
+                    self.drop_standby();
+                }
+                WebSocketMessage::Closed => {
+                    log_warn!(Transport, "Standby session closed before the switch");
+                    self.standby = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The standby session only needs to be configured, everything else it says is ignored
+    /// until it becomes the current session.
+    fn handle_standby_message(&mut self, data: &str) {
+        match serde_json::from_str::<OpenAIRealtimeResponse>(data) {
+            Ok(OpenAIRealtimeResponse::SessionCreated { session }) => {
+                let mut config = self.session_config();
+                if let Some(standby) = &mut self.standby {
+                    standby.lifetime = Some(SessionLifetime::from_session(&session));
+                    config.voice = standby.voice.clone();
+                }
+
+                self.send_to_standby(&OpenAIRealtimeMessage::SessionUpdate { session: config });
+            }
+            Ok(OpenAIRealtimeResponse::SessionUpdated { .. }) => {
+                log_info!(Protocol, "Standby session configured");
+                if let Some(standby) = &mut self.standby {
+                    standby.ready = true;
+                }
+            }
+            Ok(OpenAIRealtimeResponse::Error { error }) => {
+                log_warn!(Protocol, "Standby session error: {}", error.message);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                log_warn!(Protocol, "Failed to parse standby session event: {}", e);
+            }
+        }
+    }
+
+    fn send_to_standby(&mut self, message: &OpenAIRealtimeMessage) {
+        let event_id = self.sent_events.next_event_id();
+        if let Some(standby) = &mut self.standby {
+            send_to_side_session(&mut standby.websocket, "standby", &event_id, message);
+        }
+    }
+
+    /// Make the standby session the current one. Usage, latency and the trace carry on,
+    /// from the user's point of view it is still the same conversation.
+    fn switch_to_standby(&mut self, cx: &mut Cx) {
+        let Some(standby) = self.standby.take() else {
+            return;
+        };
+
+        let age = self.session_lifetime.as_ref().map(SessionLifetime::age);
+        log_info!(Transport, "Switching to the standby session after {:?}", age);
+        self.trace.instant(Track::State, "rollover", None);
+
+        if let Some(websocket) = self.websocket.as_mut() {
+            websocket.close();
+        }
+        self.websocket = Some(standby.websocket);
+        self.session_lifetime = standby.lifetime;
+        self.last_rollover_attempt = None;
+        if standby.voice != self.selected_voice {
+            self.selected_voice = standby.voice;
+            self.ui
+                .label(id!(selected_voice))
+                .set_text(cx, &format!("Selected voice: {}", self.selected_voice));
+            self.ui
+                .label(id!(status_label))
+                .set_text(cx, &format!("✅ Now speaking as {}", self.selected_voice));
+        }
+
+        // Retries, out-of-band responses and `audio_end_ms` refer to the old session
+        for (timer, _) in self.pending_retries.drain(..) {
+            cx.stop_timer(timer);
+        }
+        self.out_of_band.clear();
+        self.latency.reset_input_audio();
+
+        // Seeded only now so turns that finished while the standby was waiting are included,
+        // the server handles these before any audio we append next
+        self.summary_count += 1;
+        let summary_id = format!("summary_{}", self.summary_count);
+        let history = self
+            .history
+            .compacted(ROLLOVER_HISTORY_ITEMS, ROLLOVER_SUMMARY_CHARS, &summary_id);
+        log_info!(Protocol, "Carrying {} conversation items over to the new session", history.len());
+        for item in &history {
+            self.send_openai_message(OpenAIRealtimeMessage::ConversationItemCreate {
+                previous_item_id: None,
+                item: ConversationItem::text_message(item),
+            });
+        }
+        self.history.replace(history);
+    }
+
+    /// Ask for a text-only response that runs next to the dialogue without becoming part
+    /// of it. `input` replaces the conversation as context, None uses the whole conversation.
+    pub fn request_out_of_band(&mut self, cx: &mut Cx, task: OutOfBandTask, input: Option<Vec<ResponseInput>>) {
+        if !self.is_connected {
+            self.ui
+                .label(id!(status_label))
+                .set_text(cx, "❌ Not connected to OpenAI");
+            return;
+        }
+
+        log_info!(Protocol, "Requesting out-of-band {}", task.name());
+        // Context summaries happen behind the scenes
+        if !matches!(task, OutOfBandTask::ContextSummary { .. }) {
+            self.ui
+                .label(id!(out_of_band_label))
+                .set_text(cx, &format!("⏳ Working on the {}...", task.name()));
+        }
+
+        let message = OpenAIRealtimeMessage::ResponseCreate {
+            response: ResponseConfig {
+                modalities: vec!["text".to_string()],
+                instructions: Some(task.instructions()),
+                voice: None,
+                output_audio_format: None,
+                tools: None,
+                tool_choice: None,
+                temperature: Some(0.6),
+                max_output_tokens: Some(2048),
+                conversation: Some("none".to_string()),
+                metadata: Some(self.out_of_band.start(task)),
+                input,
+            },
+        };
+        self.send_openai_message(message);
+    }
+
+    fn show_out_of_band_result(&mut self, cx: &mut Cx, result: OutOfBandResult) {
+        if let OutOfBandTask::ContextSummary { item_ids, max_chars } = result.task {
+            self.context_summary_received(result.status, result.text, item_ids, max_chars);
+            return;
+        }
+        let name = result.task.name().to_string();
+        if result.status.as_deref() != Some("completed") {
+            log_warn!(Protocol, "Out-of-band {} ended with status {:?}", name, result.status);
+            self.ui.label(id!(out_of_band_label)).set_text(
+                cx,
+                &format!("❌ The {} failed ({})", name, result.status.unwrap_or_default()),
+            );
+            return;
+        }
+
+        let text = match &result.json {
+            None => result.text,
+            Some(Ok(json)) => {
+                let contents = serde_json::to_string_pretty(json).unwrap_or_default();
+                match write_export(&name, "json", &contents) {
+                    Ok(path) => format!("{}\n\n🧾 Saved to {}", contents, path.display()),
+                    Err(e) => format!("{}\n\n❌ Failed to save: {}", contents, e),
+                }
+            }
+            Some(Err(e)) => {
+                log_warn!(Protocol, "Out-of-band {} is invalid: {}", name, e);
+                format!("❌ The {} is invalid ({}):\n{}", name, e, result.text)
+            }
+        };
+        log_info!(Protocol, "Out-of-band {} done", name);
+        self.ui.label(id!(out_of_band_label)).set_text(cx, &text);
+    }
+
+    fn select_persona(&mut self, cx: &mut Cx, index: usize) {
+        let Some(persona) = self.personas.get(index).cloned() else {
+            return;
+        };
+        log_info!(Ui, "Persona: {}", persona.name);
+
+        let selector = self.ui.drop_down(id!(persona_selector));
+        selector.set_labels(cx, self.personas.iter().map(|persona| persona.name.clone()).collect());
+        selector.set_selected_item(cx, index);
+        self.ui.text_input(id!(persona_name_input)).set_text(cx, &persona.name);
+        self.ui.text_input(id!(instructions_input)).set_text(cx, &persona.instructions);
+        if let Some(greeting_index) = GreetingMode::ALL.iter().position(|mode| *mode == persona.greeting.mode) {
+            self.ui.drop_down(id!(greeting_selector)).set_selected_item(cx, greeting_index);
+        }
+
+        if self.is_connected {
+            self.change_voice(cx, persona.voice.clone());
+        } else if let Some(voice_index) = VOICES.iter().position(|voice| *voice == persona.voice) {
+            self.ui.drop_down(id!(voice_selector)).set_selected_item(cx, voice_index);
+        }
+
+        self.persona = persona;
+        self.apply_persona(cx);
+    }
+
+    /// Push the persona to a running session, otherwise it's picked up on connect.
+    fn apply_persona(&mut self, cx: &mut Cx) {
+        if !self.is_connected {
+            return;
+        }
+        log_info!(Protocol, "Updating session instructions for persona {}", self.persona.name);
+        let message = OpenAIRealtimeMessage::SessionUpdate {
+            session: self.session_config(),
+        };
+        self.send_openai_message(message);
+        self.ui
+            .label(id!(status_label))
+            .set_text(cx, &format!("✅ Instructions updated ({})", self.persona.name));
+    }
+
+    /// Store the edited instructions under the name in the editor, a new name adds a profile.
+    fn save_persona(&mut self, cx: &mut Cx) {
+        let name = self.ui.text_input(id!(persona_name_input)).text().trim().to_string();
+        if name.is_empty() {
+            self.ui.label(id!(status_label)).set_text(cx, "❌ Give the persona a name first");
+            return;
+        }
+
+        let mut persona = self.persona.clone();
+        persona.name = name.clone();
+        persona.instructions = self.ui.text_input(id!(instructions_input)).text();
+        persona.voice = self.ui.drop_down(id!(voice_selector)).selected_label();
+
+        let index = match self.personas.iter().position(|existing| existing.name == name) {
+            Some(index) => {
+                self.personas[index] = persona;
+                index
+            }
+            None => {
+                self.personas.push(persona);
+                self.personas.len() - 1
+            }
+        };
+        PersonaProfile::save_all(&self.personas);
+        self.settings.persona = Some(name.clone());
+        self.settings.save();
+
+        self.select_persona(cx, index);
+        self.ui
+            .label(id!(status_label))
+            .set_text(cx, &format!("💾 Persona {} saved", name));
+    }
+
+    /// Greet right away or once the user has been silent for a while, as the persona says.
+    fn schedule_greeting(&mut self, cx: &mut Cx) {
+        if self.settings.wake_word.enabled {
+            log_debug!(Protocol, "Wake word enabled, not greeting until spoken to");
+            return;
+        }
+
+        let greeting = &self.persona.greeting;
+        if greeting.mode == GreetingMode::Off {
+            log_debug!(Protocol, "Greeting disabled, waiting for the user");
+            return;
+        }
+
+        match greeting.timing {
+            GreetingTiming::OnConnect => self.create_greeting_response(),
+            GreetingTiming::AfterSilence => {
+                self.greeting_timer = Some(cx.start_timeout(greeting.silence_secs));
+            }
+        }
+    }
+
+    /// Bookkeeping shared by the server and the local VAD when the user starts talking.
+    fn user_speech_started(&mut self, cx: &mut Cx) {
+        self.user_is_speaking = true;
+        self.cancel_greeting(cx);
+        if self.awake_since.is_some() {
+            self.awake_since = Some(Instant::now());
+        }
+    }
+
+    fn cancel_greeting(&mut self, cx: &mut Cx) {
+        if let Some(timer) = self.greeting_timer.take() {
+            log_debug!(Protocol, "User spoke first, skipping the greeting");
+            cx.stop_timer(timer);
         }
     }
 
     // Trigger a greeting response from the AI
     fn create_greeting_response(&mut self) {
+        let vars = self.template_vars();
+        let Some(greeting) = self.persona.greeting.instructions(&vars) else {
+            return;
+        };
+        // Response instructions replace the session ones, keep the persona
+        let instructions = format!(
+            "{}\n\n{}",
+            render_template(&self.persona.instructions, &vars),
+            greeting
+        );
+
         let message = OpenAIRealtimeMessage::ResponseCreate {
             response: ResponseConfig {
                 modalities: vec!["text".to_string(), "audio".to_string()],
-                instructions: Some("You are a helpful AI assistant. Respond naturally and conversationally,
-                 start with a very short but enthusiastic and playful greeting in English, the greeting must not exceed 3 words".to_string()),
+                instructions: Some(instructions),
                 voice: Some(self.selected_voice.clone()),
-                output_audio_format: Some("pcm16".to_string()),
+                output_audio_format: Some(self.codec.format().as_str().to_string()),
                 tools: None,
                 tool_choice: None,
-                temperature: Some(0.8),
+                temperature: Some(self.persona.temperature),
                 max_output_tokens: Some(4096),
+                conversation: None,
+                metadata: None,
+                input: None,
             },
         };
 
         self.send_openai_message(message);
     }
 
+    fn start_telephony_bridge(&mut self, cx: &mut Cx) {
+        if !self.settings.telephony.enabled {
+            return;
+        }
+
+        let audio = BridgeAudio {
+            recorded_audio: self.recorded_audio.clone(),
+            is_recording: self.is_recording.clone(),
+            is_muted: self.is_muted.clone(),
+            input_level: self.input_level.clone(),
+            playback: self.playback.clone(),
+            active: self.bridge_active.clone(),
+        };
+        match TelephonyBridge::start(&self.settings.telephony, audio) {
+            Ok(bridge) => {
+                self.telephony = Some(bridge);
+                self.ui.label(id!(status_label)).set_text(
+                    cx,
+                    &format!("📞 Waiting for calls on SIP port {}", self.settings.telephony.sip_port),
+                );
+            }
+            Err(e) => {
+                log_error!(Transport, "Failed to start the telephony bridge: {}", e);
+                self.ui
+                    .label(id!(status_label))
+                    .set_text(cx, &format!("❌ Telephony bridge failed to start: {}", e));
+            }
+        }
+    }
+
+    fn start_relay_server(&mut self, cx: &mut Cx) {
+        if !self.settings.relay.enabled {
+            return;
+        }
+        if !self.has_credentials() {
+            log_error!(Transport, "The relay needs OPENAI_API_KEY or a token service");
+            return;
+        }
+        if self.settings.relay.access_token.as_deref().is_none_or(str::is_empty) {
+            match generate_access_token() {
+                Ok(access_token) => {
+                    self.settings.relay.access_token = Some(access_token);
+                    self.settings.save();
+                    log_info!(Transport, "Generated a relay access token, it is in the settings file");
+                }
+                // Starting below fails for want of a token and says so
+                Err(e) => log_error!(Transport, "Failed to generate a relay access token: {}", e),
+            }
+        }
+
+        match RelayServer::start(&self.settings.relay) {
+            Ok(relay) => {
+                self.ui.label(id!(status_label)).set_text(
+                    cx,
+                    &format!("🔁 Relaying clients on ws://{}", relay.local_addr()),
+                );
+                self.relay = Some(relay);
+                self.relay_timer = Some(cx.start_interval(RELAY_INTERVAL_SECS));
+            }
+            Err(e) => {
+                log_error!(Transport, "Failed to start the relay: {}", e);
+                self.ui
+                    .label(id!(status_label))
+                    .set_text(cx, &format!("❌ Relay failed to start: {}", e));
+            }
+        }
+    }
+
+    fn pump_relay(&mut self, cx: &mut Cx) {
+        let Some(mut relay) = self.relay.take() else {
+            return;
+        };
+        let connected = relay.pump(|| self.relay_session_update());
+        self.relay = Some(relay);
+
+        // Secrets are minted with the policy, `relay_session_update` applies it either way
+        for id in connected {
+            let session = self.policy_session_config(None);
+            let request = self.session_request(cx, session, SecretRequester::Relay(id));
+            if let (Some(request), Some(relay)) = (request, &mut self.relay) {
+                relay.open_upstream(id, request);
+            }
+        }
+    }
+
+    /// The policy part of relayed sessions, sent before any client event.
+    fn relay_session_update(&self) -> String {
+        let session = self.policy_session_config(None);
+        serde_json::to_string(&OpenAIRealtimeMessage::SessionUpdate { session }).unwrap_or_default()
+    }
+
+    /// Session config for clients: the relay policy on top of the current persona.
+    fn policy_session_config(&self, voice: Option<&str>) -> SessionConfig {
+        let policy = &self.settings.relay;
+        let mut session = self.session_config();
+        if let Some(voice) = voice {
+            session.voice = voice.to_string();
+        }
+        if let Some(instructions) = &policy.instructions {
+            session.instructions = render_template(instructions, &self.template_vars());
+        }
+        if let Some(tools) = &policy.tools {
+            session.tools = tools.clone();
+            session.tool_choice = if tools.is_empty() { "none" } else { "auto" }.to_string();
+        }
+        if !policy.allowed_voices.contains(&session.voice) {
+            session.voice = policy.allowed_voices.first().cloned().unwrap_or(session.voice);
+        }
+        // Clients bring their own audio: PCM16, no local VAD and no mic to guess the noise reduction from
+        session.input_audio_format = AudioFormat::Pcm16.as_str().to_string();
+        session.output_audio_format = AudioFormat::Pcm16.as_str().to_string();
+        session.turn_detection = Some(server_turn_detection());
+        session.input_audio_noise_reduction = self
+            .settings
+            .preprocessing
+            .server_noise_reduction
+            .resolve(None)
+            .map(|noise_reduction_type| NoiseReductionConfig {
+                noise_reduction_type: noise_reduction_type.to_string(),
+            });
+        session
+    }
+
+    fn start_token_service(&mut self, cx: &mut Cx) {
+        if !self.settings.token_service.enabled {
+            return;
+        }
+        if self.openai_api_key.is_none() {
+            log_error!(Transport, "The token service needs OPENAI_API_KEY");
+            return;
+        }
+        if self.settings.token_service.access_token.as_deref().is_none_or(str::is_empty) {
+            match generate_access_token() {
+                Ok(access_token) => {
+                    self.settings.token_service.access_token = Some(access_token);
+                    self.settings.save();
+                    log_info!(Transport, "Generated a token service access token, it is in the settings file");
+                }
+                // Starting below fails for want of a token and says so
+                Err(e) => log_error!(Transport, "Failed to generate a token service access token: {}", e),
+            }
+        }
+
+        match TokenService::start(&self.settings.token_service) {
+            Ok(service) => {
+                self.ui.label(id!(status_label)).set_text(
+                    cx,
+                    &format!("🔑 Handing out session tokens on http://{}/token", service.local_addr()),
+                );
+                self.token_service = Some(service);
+            }
+            Err(e) => {
+                log_error!(Transport, "Failed to start the token service: {}", e);
+                self.ui
+                    .label(id!(status_label))
+                    .set_text(cx, &format!("❌ Token service failed to start: {}", e));
+            }
+        }
+    }
+
+    /// Mints a secret per token service request. Its session starts from the relay policy,
+    /// but the client talks to OpenAI directly and can change it, nothing is enforced.
+    fn handle_token_requests(&mut self, cx: &mut Cx) {
+        let Some(service) = &self.token_service else {
+            return;
+        };
+
+        for request in service.poll_requests() {
+            let refused_voice = request
+                .voice
+                .clone()
+                .filter(|voice| !self.settings.relay.allowed_voices.contains(voice));
+            if let Some(voice) = refused_voice {
+                request.respond(403, &error_body(&format!("voice '{}' is not allowed", voice)));
+                continue;
+            }
+            let session = self.policy_session_config(request.voice.as_deref());
+            self.mint_client_secret(cx, session, SecretRequester::Client(request));
+        }
+    }
+
+    /// Calls drive the conversation: answering connects, hanging up disconnects.
+    fn handle_telephony_events(&mut self, cx: &mut Cx) {
+        let Some(telephony) = &self.telephony else {
+            return;
+        };
+
+        for event in telephony.poll_events() {
+            match event {
+                BridgeEvent::CallStarted { caller, format } => {
+                    self.call_format = Some(format);
+                    if !self.is_connected {
+                        self.connect_to_openai(cx);
+                    } else if !self.conversation_active {
+                        self.start_conversation(cx);
+                    }
+                    self.trace.instant(Track::State, "call started", None);
+                    self.ui
+                        .label(id!(status_label))
+                        .set_text(cx, &format!("📞 Call from {}", caller));
+                }
+                BridgeEvent::CallEnded { reason } => {
+                    self.trace.instant(Track::State, "call ended", None);
+                    self.call_format = None;
+                    if self.is_connected {
+                        self.reset_all(cx);
+                    }
+                    self.ui
+                        .label(id!(status_label))
+                        .set_text(cx, &format!("📞 Call ended ({})", reason));
+                }
+            }
+        }
+    }
+
     fn start_conversation(&mut self, cx: &mut Cx) {
         if !self.is_connected {
             self.ui
@@ -873,7 +3025,7 @@ impl App {
             return;
         }
 
-        log!("Starting conversation");
+        log_info!(Protocol, "Starting conversation");
         self.conversation_active = true;
         self.ai_is_responding = false;
         *self.is_recording.lock().unwrap() = true;
@@ -881,12 +3033,10 @@ impl App {
 
         // Clear previous audio
         self.recorded_audio.lock().unwrap().clear();
-        self.playback_audio.lock().unwrap().clear();
-        *self.is_playing.lock().unwrap() = false;
-        *self.playback_position.lock().unwrap() = 0;
+        self.playback.lock().unwrap().clear();
         self.current_transcript.clear();
 
-        self.create_greeting_response();
+        self.schedule_greeting(cx);
 
         self.update_ui_state(cx);
 
@@ -895,11 +3045,18 @@ impl App {
     }
 
     fn reset_all(&mut self, cx: &mut Cx) {
+        if let Some(telephony) = &self.telephony {
+            telephony.hang_up();
+        }
         self.stop_conversation(cx);
 
         self.is_connected = false;
         self.has_sent_audio = false;
         self.current_transcript.clear();
+        // Only disconnecting starts a new budget, reconnects and rollovers keep counting
+        self.usage = UsageTracker::default();
+        self.budget_warning_shown = false;
+        self.update_usage_label(cx);
         self.ui.label(id!(status_label)).set_text(cx, "Ready to connect");
         self.ui.label(id!(transcript_label)).set_text(cx, "");
 
@@ -908,14 +3065,24 @@ impl App {
 
         self.update_ui_state(cx);
 
-        // Close the websocket connection
-        self.websocket.as_mut().unwrap().close();
+        // Close the websocket connection, or forget the one that was waiting for a client secret
+        if let Some(websocket) = self.websocket.as_mut() {
+            websocket.close();
+        }
+        self.pending_client_secrets
+            .retain(|_, requester| !matches!(requester, SecretRequester::Connection));
+        self.drop_standby();
     }
 
     fn stop_conversation(&mut self, cx: &mut Cx) {
-        log!("Stopping conversation");
+        log_info!(Protocol, "Stopping conversation");
+        if let Some(timer) = self.greeting_timer.take() {
+            cx.stop_timer(timer);
+        }
         self.conversation_active = false;
         self.ai_is_responding = false;
+        self.user_is_speaking = false;
+        self.local_vad.reset();
         *self.is_recording.lock().unwrap() = false;
 
         // Stop the audio streaming timer
@@ -925,22 +3092,447 @@ impl App {
         }
 
         // Cancel any pending audio playback
-        if let Ok(mut playback) = self.playback_audio.try_lock() {
-            playback.clear();
-        }
+        self.playback.lock().unwrap().fade_out();
 
         self.ui
             .label(id!(status_label))
             .set_text(cx, "⏹️ Conversation stopped");
     }
 
+    /// True while a response is being generated or its audio can still be heard.
+    fn assistant_is_audible(&self) -> bool {
+        self.ai_is_responding
+            || self.current_assistant_item_id.is_some()
+            || self.playback.lock().unwrap().stream_item_id().is_some()
+    }
+
+    /// Stop the assistant mid-response and hand the turn back to the user.
+    ///
+    /// Cancels generation on the server, fades out whatever is left in the playback buffer
+    /// and truncates the assistant item so the server only keeps the audio that was heard.
+    fn cancel_response(&mut self, cx: &mut Cx) {
+        if !self.conversation_active || !self.assistant_is_audible() {
+            log_debug!(Protocol, "No assistant response to cancel");
+            return;
+        }
+
+        log_info!(Protocol, "Cancelling assistant response {:?}", self.current_response_id);
+        self.trace.instant(Track::State, "stop talking", None);
+        let (played_samples, playing_item_id) = {
+            let mut playback = self.playback.lock().unwrap();
+            let item_id = playback.stream_item_id().map(str::to_string);
+            playback.fade_out();
+            (playback.stream_played_samples(), item_id)
+        };
+
+        // After response.done most of the answer is still playing, then there is nothing to cancel
+        if self.ai_is_responding {
+            self.send_openai_message(OpenAIRealtimeMessage::ResponseCancel {
+                response_id: self.current_response_id.clone(),
+            });
+            self.cancelled_response_id = self.current_response_id.take();
+        }
+
+        if let Some(item_id) = playing_item_id.or(self.current_assistant_item_id.take()) {
+            let audio_end_ms = (played_samples * 1000 / OPENAI_SAMPLE_RATE) as u32;
+            log_debug!(Protocol, "Truncating assistant item {} at {}ms", item_id, audio_end_ms);
+            self.send_openai_message(OpenAIRealtimeMessage::ConversationItemTruncate {
+                item_id,
+                content_index: 0,
+                audio_end_ms,
+            });
+        }
+
+        self.ai_is_responding = false;
+        self.user_is_interrupting = false;
+
+        *self.is_recording.lock().unwrap() = true;
+        self.ui.label(id!(status_label)).set_text(cx, "🎤 Listening...");
+    }
+
+    /// Keep the local conversation model in step with the server, only messages are tracked.
+    fn track_conversation_item(&mut self, item: &serde_json::Value) {
+        let Some(item_id) = item["id"].as_str() else {
+            return;
+        };
+        let Ok(role) = Role::deserialize(&item["role"]) else {
+            return;
+        };
+        let text = item["content"].as_array().and_then(|parts| {
+            parts
+                .iter()
+                .find_map(|part| part["text"].as_str().or(part["transcript"].as_str()))
+        });
+        self.history.insert(item_id, role, text);
+    }
+
+    /// Shrink the server-side conversation once a response reports more input tokens than
+    /// the context policy allows, according to its strategy.
+    fn prune_context(&mut self, cx: &mut Cx, input_tokens: u64) {
+        let policy = self.settings.context.clone();
+        if !policy.should_prune(input_tokens) || self.out_of_band.summarizing_context() {
+            return;
+        }
+
+        // Earlier summaries are kept as they are rather than folded into the next one
+        let oldest: Vec<HistoryItem> = self
+            .history
+            .oldest(policy.keep_recent_items)
+            .iter()
+            .filter(|item| !is_summary(item))
+            .cloned()
+            .collect();
+        if oldest.is_empty() {
+            log_warn!(
+                Protocol,
+                "{} input tokens but only the {} most recent items are left, nothing to prune",
+                input_tokens,
+                policy.keep_recent_items
+            );
+            return;
+        }
+
+        log_info!(
+            Protocol,
+            "{} input tokens exceed {:?}, pruning the {} oldest items ({:?})",
+            input_tokens,
+            policy.max_input_tokens,
+            oldest.len(),
+            policy.strategy
+        );
+        self.trace.instant(
+            Track::State,
+            "context pruned",
+            Some(serde_json::json!({ "input_tokens": input_tokens, "items": oldest.len() })),
+        );
+
+        let has_text = oldest.iter().any(|item| !item.text.trim().is_empty());
+        if policy.strategy == PruneStrategy::Summarize && has_text {
+            // The originals stay until the summary is in, see `context_summary_received`
+            let task = OutOfBandTask::ContextSummary {
+                item_ids: oldest.iter().map(|item| item.item_id.clone()).collect(),
+                max_chars: policy.max_summary_chars,
+            };
+            let input = oldest
+                .iter()
+                .filter(|item| !item.text.trim().is_empty())
+                .map(|item| ResponseInput::Reference {
+                    reference_type: "item_reference".to_string(),
+                    id: item.item_id.clone(),
+                })
+                .collect();
+            self.request_out_of_band(cx, task, Some(input));
+            return;
+        }
+
+        self.delete_items(oldest.iter().map(|item| item.item_id.clone()));
+    }
+
+    fn delete_items(&mut self, item_ids: impl IntoIterator<Item = String>) {
+        for item_id in item_ids {
+            self.history.remove(&item_id);
+            self.send_openai_message(OpenAIRealtimeMessage::ConversationItemDelete { item_id });
+        }
+    }
+
+    /// Puts the summary of pruned items after the earlier summaries at the start of the
+    /// conversation and only then deletes the items. Without a summary nothing is deleted,
+    /// the next response tries again.
+    fn context_summary_received(
+        &mut self,
+        status: Option<String>,
+        text: String,
+        item_ids: Vec<String>,
+        max_chars: usize,
+    ) {
+        if status.as_deref() != Some("completed") || text.trim().is_empty() {
+            log_warn!(Protocol, "Context summary ended with status {:?}, nothing pruned", status);
+            return;
+        }
+        // A rollover may have replaced the history meanwhile
+        let item_ids: Vec<String> = item_ids
+            .into_iter()
+            .filter(|item_id| self.history.items().iter().any(|item| &item.item_id == item_id))
+            .collect();
+        if item_ids.is_empty() {
+            return;
+        }
+
+        let mut text = text.trim().to_string();
+        if text.len() > max_chars {
+            let mut end = max_chars;
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            text.truncate(end);
+        }
+        let previous_summary = self
+            .history
+            .items()
+            .iter()
+            .take_while(|item| is_summary(item))
+            .last()
+            .map(|item| item.item_id.clone());
+
+        self.summary_count += 1;
+        let summary = HistoryItem {
+            item_id: format!("summary_{}", self.summary_count),
+            role: Role::System,
+            text: format!("Earlier in this conversation:\n{}", text),
+        };
+        log_info!(Protocol, "Replacing {} items with a summary of {} chars", item_ids.len(), summary.text.len());
+        self.send_openai_message(OpenAIRealtimeMessage::ConversationItemCreate {
+            previous_item_id: Some(previous_summary.clone().unwrap_or_else(|| "root".to_string())),
+            item: ConversationItem::text_message(&summary),
+        });
+        self.history.insert_after(previous_summary.as_deref(), summary);
+        self.delete_items(item_ids);
+    }
+
+    fn record_usage(&mut self, cx: &mut Cx, response_id: String, usage: Usage) {
+        let turn = self.usage.record(response_id, usage, &self.settings.prices);
+        log_info!(
+            Protocol,
+            "Response {} used {} tokens (in: {}, out: {}), ${:.4}",
+            turn.response_id,
+            turn.usage.total_tokens,
+            turn.usage.input_tokens,
+            turn.usage.output_tokens,
+            turn.cost
+        );
+        self.update_usage_label(cx);
+        self.enforce_budget(cx);
+    }
+
+    /// Warn when getting close to a configured limit, end the session once one is reached.
+    fn enforce_budget(&mut self, cx: &mut Cx) {
+        if !self.is_connected {
+            return;
+        }
+
+        match self.settings.budget.check(&self.usage) {
+            BudgetStatus::WithinLimits => {}
+            BudgetStatus::NearLimit(description) => {
+                if !self.budget_warning_shown {
+                    self.budget_warning_shown = true;
+                    log_warn!(Protocol, "Session budget almost used: {}", description);
+                    self.ui
+                        .label(id!(status_label))
+                        .set_text(cx, &format!("⚠️ Budget almost used: {}", description));
+                }
+            }
+            BudgetStatus::Exceeded(description) => {
+                log_warn!(Protocol, "Session budget exceeded ({}), closing the session", description);
+                self.trace.instant(
+                    Track::State,
+                    "budget exceeded",
+                    Some(serde_json::json!({ "limit": description })),
+                );
+                self.abort_session(cx, &format!("⛔ Session stopped, budget reached: {}", description));
+            }
+        }
+    }
+
+    fn update_usage_label(&mut self, cx: &mut Cx) {
+        let text = match self.usage.last_turn() {
+            Some(turn) => format!(
+                "Last turn: {} tokens (${:.4}) · Session: {} tokens (${:.4})",
+                turn.usage.total_tokens,
+                turn.cost,
+                self.usage.totals().total_tokens,
+                self.usage.total_cost()
+            ),
+            None => "No usage yet".to_string(),
+        };
+        self.ui.label(id!(usage_label)).set_text(cx, &text);
+    }
+
+    fn export_usage(&mut self, cx: &mut Cx) {
+        let status = match write_export("usage", "csv", &self.usage.to_csv()) {
+            Ok(path) => format!("📊 Usage exported to {}", path.display()),
+            Err(e) => format!("❌ Failed to export usage: {}", e),
+        };
+        log_info!(Ui, "{}", status);
+        self.ui.label(id!(status_label)).set_text(cx, &status);
+    }
+
+    /// Playback and state changes happen outside of message handling, sample them on the streaming timer.
+    fn trace_playback_and_state(&mut self) {
+        let (started_at, finished_at) = {
+            let playback = self.playback.lock().unwrap();
+            (playback.stream_started_at(), playback.stream_finished_at())
+        };
+        if let Some(traced_start) = self.traced_playback_start {
+            // A new stream may have started before we saw the previous one finish
+            let finished_at = finished_at
+                .filter(|finished_at| *finished_at >= traced_start)
+                .or(started_at.filter(|started_at| *started_at != traced_start));
+            if let Some(finished_at) = finished_at {
+                self.trace.end(Track::Playback, "assistant audio", finished_at);
+                self.traced_playback_start = None;
+            }
+        } else if let Some(started_at) = started_at.filter(|_| finished_at.is_none()) {
+            self.trace.begin(Track::Playback, "assistant audio", started_at);
+            self.traced_playback_start = Some(started_at);
+        }
+
+        let state = [
+            self.conversation_active,
+            *self.is_recording.lock().unwrap(),
+            self.ai_is_responding,
+            *self.is_muted.lock().unwrap(),
+        ];
+        if self.traced_state != Some(state) {
+            self.traced_state = Some(state);
+            let [conversation_active, recording, responding, muted] = state.map(u8::from);
+            self.trace.counter(
+                "state",
+                serde_json::json!({
+                    "conversation_active": conversation_active,
+                    "recording": recording,
+                    "ai_responding": responding,
+                    "muted": muted,
+                }),
+            );
+        }
+    }
+
+    fn export_trace(&mut self, cx: &mut Cx) {
+        let status = match write_export("timeline", "json", &self.trace.to_json()) {
+            Ok(path) => format!("🧭 Timeline exported to {} (open it in ui.perfetto.dev)", path.display()),
+            Err(e) => format!("❌ Failed to export timeline: {}", e),
+        };
+        log_info!(Ui, "{}", status);
+        self.ui.label(id!(status_label)).set_text(cx, &status);
+    }
+
+    fn update_latency_label(&mut self, cx: &mut Cx) {
+        let ms = |value: Option<f64>| value.map(|ms| format!("{:.0}", ms)).unwrap_or("?".to_string());
+
+        let mut text = match self.latency.last_turn() {
+            Some(turn) => format!(
+                "Last turn: {} ms (network+VAD {}, server {}, buffering {})",
+                ms(turn.voice_to_voice_ms),
+                ms(turn.network_and_vad_ms),
+                ms(turn.server_ms),
+                ms(turn.local_buffering_ms)
+            ),
+            None => "No latency measurements yet".to_string(),
+        };
+        if let Some(p) = self.latency.voice_to_voice_percentiles() {
+            text.push_str(&format!(
+                " · {} turns: p50 {:.0} / p90 {:.0} / p95 {:.0} ms",
+                self.latency.turn_count(),
+                p.p50,
+                p.p90,
+                p.p95
+            ));
+        }
+        self.ui.label(id!(latency_label)).set_text(cx, &text);
+    }
+
+    fn export_latency_report(&mut self, cx: &mut Cx) {
+        let status = match write_export("latency", "csv", &self.latency.to_report()) {
+            Ok(path) => format!("⏱️ Latency report exported to {}", path.display()),
+            Err(e) => format!("❌ Failed to export latency report: {}", e),
+        };
+        log_info!(Ui, "{}", status);
+        self.ui.label(id!(status_label)).set_text(cx, &status);
+    }
+
+    /// The channel options depend on how many channels the active input device has.
+    fn update_channel_mix_selector(&mut self, cx: &mut Cx) {
+        let channel_count = self
+            .input_devices
+            .active_device()
+            .map(|desc| desc.channel_count)
+            .unwrap_or(1);
+        self.channel_mix_options = ChannelMix::options(channel_count);
+
+        let current = *self.channel_mix.lock().unwrap();
+        let selected = self
+            .channel_mix_options
+            .iter()
+            .position(|mix| *mix == current)
+            .unwrap_or(0);
+
+        let selector = self.ui.drop_down(id!(channel_mix_selector));
+        selector.set_labels(cx, self.channel_mix_options.iter().map(ChannelMix::label).collect());
+        selector.set_selected_item(cx, selected);
+    }
+
+    fn apply_playback_latency(&mut self, cx: &mut Cx) {
+        let latency_ms = self.settings.playback_latency_ms;
+        self.playback.lock().unwrap().set_target_latency_ms(latency_ms);
+
+        if let Some(index) = PLAYBACK_LATENCY_OPTIONS_MS.iter().position(|&option| option == latency_ms) {
+            self.ui.drop_down(id!(playback_latency_selector)).set_selected_item(cx, index);
+        }
+    }
+
+    /// Muting only gates what is sent to OpenAI, the turn logic keeps driving `is_recording`.
+    fn set_muted(&mut self, cx: &mut Cx, muted: bool) {
+        log_info!(Audio, "Microphone {}", if muted { "muted" } else { "unmuted" });
+        self.trace.instant(Track::State, if muted { "mute" } else { "unmute" }, None);
+        *self.is_muted.lock().unwrap() = muted;
+
+        if muted {
+            // Drop anything captured but not yet streamed
+            self.recorded_audio.lock().unwrap().clear();
+
+            // Muting mid-sentence ends the turn with what was said so far
+            if self.local_vad.is_speaking() {
+                self.handle_local_vad_event(cx, VadEvent::SpeechEnded);
+            }
+            self.local_vad.reset();
+        }
+
+        self.update_input_level_meter(cx);
+    }
+
+    fn update_input_level_meter(&mut self, cx: &mut Cx) {
+        const METER_WIDTH: usize = 16;
+
+        let (rms, peak, clipped, silence) = {
+            let mut level = self.input_level.lock().unwrap();
+            let (rms, peak) = level.take_levels();
+            (rms, peak, level.take_clipped_samples(), level.silence_duration())
+        };
+        let muted = *self.is_muted.lock().unwrap();
+
+        let rms_db = amplitude_to_db(rms);
+        let filled = (((rms_db + 60.0) / 60.0).clamp(0.0, 1.0) * METER_WIDTH as f32).round() as usize;
+        let meter = format!(
+            "{} {}{} {:>4.0} dB (peak {:.0} dB){}",
+            if muted { "🔇" } else { "🎙️" },
+            "▮".repeat(filled),
+            "-".repeat(METER_WIDTH - filled),
+            rms_db,
+            amplitude_to_db(peak),
+            if clipped > 0 { " ⚠️ clipping, lower the mic gain" } else { "" },
+        );
+        if clipped > 0 {
+            log_debug!(Audio, "{} input samples clipped", clipped);
+        }
+        self.ui.label(id!(input_level_label)).set_text(cx, &meter);
+
+        let show_warning = !muted && silence >= NO_INPUT_SIGNAL_WARNING;
+        if show_warning != self.input_warning_visible {
+            if show_warning {
+                log_warn!(Audio, "No input signal detected for {:?}", silence);
+            }
+            self.input_warning_visible = show_warning;
+            self.ui.view(id!(input_warning_view)).set_visible(cx, show_warning);
+        }
+    }
+
     fn start_audio_streaming(&mut self, cx: &mut Cx) {
         // Start a timer to send audio chunks every 20ms
         let timer = cx.start_interval(0.020);
         self.audio_streaming_timer = Some(timer);
     }
 
-    fn send_audio_chunk_to_openai(&mut self, _cx: &mut Cx) {
+    fn send_audio_chunk_to_openai(&mut self, cx: &mut Cx) {
         // Collect audio data to avoid borrowing conflicts
         let audio_data = if let Ok(mut recorded) = self.recorded_audio.try_lock() {
             if !recorded.is_empty() {
@@ -954,76 +3546,217 @@ impl App {
             None
         };
 
+        // Nothing goes out until the wake phrase was heard
+        let audio_data = if self.settings.wake_word.enabled {
+            self.gate_on_wake_word(cx, audio_data)
+        } else {
+            audio_data
+        };
+
+        // Leave out silence when the local VAD is on
+        let mut vad_events = Vec::new();
+        let audio_data = match (audio_data, self.settings.vad.mode) {
+            (Some(samples), VadMode::GateUploads | VadMode::TurnTaking) => {
+                let mut speech = Vec::new();
+                vad_events = self.local_vad.process(&self.settings.vad, &samples, &mut speech);
+                (!speech.is_empty()).then_some(speech)
+            }
+            (audio_data, _) => audio_data,
+        };
+
         if let Some(samples) = audio_data {
-            // Convert f32 samples to PCM16 bytes
-            let pcm16_bytes = self.convert_f32_to_pcm16(&samples);
+            // Convert f32 samples to the session's audio format
+            let audio_bytes = self.codec.encode(&samples);
 
             // Encode as base64 for transmission
-            let base64_audio = general_purpose::STANDARD.encode(&pcm16_bytes);
+            let base64_audio = general_purpose::STANDARD.encode(&audio_bytes);
 
             let message = OpenAIRealtimeMessage::InputAudioBufferAppend {
                 audio: base64_audio,
             };
             self.send_openai_message(message);
 
+            self.latency.audio_sent(samples.len());
+            let duration = Duration::from_secs_f64(samples.len() as f64 / OPENAI_SAMPLE_RATE as f64);
+            self.trace.complete(
+                Track::AudioInput,
+                "audio chunk",
+                Instant::now() - duration,
+                duration,
+                Some(serde_json::json!({ "samples": samples.len() })),
+            );
             self.has_sent_audio = true;
         }
+
+        // After the append, a commit has to include the end of the speech
+        for event in vad_events {
+            self.handle_local_vad_event(cx, event);
+        }
     }
 
-    fn convert_f32_to_pcm16(&self, samples: &[f32]) -> Vec<u8> {
-        let mut pcm16_bytes = Vec::with_capacity(samples.len() * 2);
+    /// While asleep, feeds the detector and sends nothing. Once awake, audio passes through
+    /// until the next response is done or nobody speaks for `listen_timeout_secs`.
+    fn gate_on_wake_word(&mut self, cx: &mut Cx, audio_data: Option<Vec<f32>>) -> Option<Vec<f32>> {
+        if let Some(awake_since) = self.awake_since {
+            let timeout = Duration::from_secs_f64(self.settings.wake_word.listen_timeout_secs);
+            let idle = !self.user_is_speaking && !self.ai_is_responding && !self.local_vad.is_speaking();
+            if idle && awake_since.elapsed() >= timeout {
+                log_info!(Audio, "Nobody spoke after the wake phrase, going back to sleep");
+                self.go_to_sleep(cx);
+                return None;
+            }
+            return audio_data;
+        }
 
-        for &sample in samples {
-            // Clamp to [-1.0, 1.0] and convert to i16
-            let clamped = sample.max(-1.0).min(1.0);
-            let pcm16_sample = (clamped * 32767.0) as i16;
-            pcm16_bytes.extend_from_slice(&pcm16_sample.to_le_bytes());
+        let detector = self.wake_detector.as_mut()?;
+        if !detector.process(&audio_data?) {
+            return None;
         }
 
-        pcm16_bytes
+        log_info!(Audio, "Wake phrase detected");
+        self.trace.instant(Track::State, "wake word", None);
+        self.awake_since = Some(Instant::now());
+        self.local_vad.reset();
+        self.ui.label(id!(wake_word_label)).set_text(cx, "👂 Listening...");
+        // The phrase itself isn't part of the request
+        None
     }
 
-    fn add_audio_to_playback(&mut self, audio_bytes: Vec<u8>) {
-        // Don't add audio if user is currently speaking (to prevent feedback)
-        if !self.ai_is_responding {
-            log!("Skipping AI audio - user is speaking or AI not actively responding");
+    fn go_to_sleep(&mut self, cx: &mut Cx) {
+        self.awake_since = None;
+        if let Some(detector) = self.wake_detector.as_mut() {
+            detector.reset();
+        }
+        self.local_vad.reset();
+        self.ui
+            .label(id!(wake_word_label))
+            .set_text(cx, "💤 Waiting for the wake phrase");
+    }
+
+    fn set_wake_word_enabled(&mut self, cx: &mut Cx, enabled: bool) {
+        self.settings.wake_word.enabled = enabled;
+        self.settings.save();
+        self.awake_since = None;
+        self.wake_detector = None;
+        if !enabled {
+            self.ui.label(id!(wake_word_label)).set_text(cx, "");
             return;
         }
 
-        // Convert PCM16 bytes back to f32 samples
-        let samples = self.convert_pcm16_to_f32(&audio_bytes);
-
-        if let Ok(mut playback) = self.playback_audio.try_lock() {
-            // If we're not currently playing, clear the buffer first to avoid accumulation
-            if let Ok(mut is_playing) = self.is_playing.try_lock() {
-                if !*is_playing {
-                    // Clear old audio data and start fresh playback
-                    playback.clear();
-                    *self.playback_position.lock().unwrap() = 0;
-                    *is_playing = true;
-                    log!(
-                        "Started fresh playback of OpenAI response audio ({} samples)",
-                        samples.len()
-                    );
-                } else {
-                    // log!("Appending to existing playback ({} samples)", samples.len());
+        match TemplateMatcher::load(self.settings.wake_word.threshold) {
+            Some(matcher) => {
+                self.wake_detector = Some(Box::new(matcher));
+                self.go_to_sleep(cx);
+            }
+            None => {
+                // Without a detector nothing would ever be sent
+                self.ui
+                    .label(id!(wake_word_label))
+                    .set_text(cx, "⚠️ Record a wake phrase first, nothing is sent until then");
+            }
+        }
+    }
+
+    /// Turns the recording into a template once enough audio was captured.
+    fn check_wake_word_enrollment(&mut self, cx: &mut Cx) {
+        let samples = {
+            let mut enrollment = self.wake_enrollment.lock().unwrap();
+            match enrollment.as_ref() {
+                Some(samples) if samples.len() as f64 >= ENROLLMENT_SECS * OPENAI_SAMPLE_RATE as f64 => {
+                    enrollment.take().unwrap_or_default()
                 }
+                _ => return,
             }
+        };
 
-            playback.extend_from_slice(&samples);
+        match TemplateMatcher::enroll(&samples, self.settings.wake_word.threshold) {
+            Some(matcher) => {
+                log_info!(Audio, "Wake phrase recorded");
+                if self.settings.wake_word.enabled {
+                    self.wake_detector = Some(Box::new(matcher));
+                    self.go_to_sleep(cx);
+                } else {
+                    self.ui.label(id!(wake_word_label)).set_text(cx, "✅ Wake phrase recorded");
+                }
+            }
+            None => {
+                self.ui
+                    .label(id!(wake_word_label))
+                    .set_text(cx, "⚠️ Couldn't hear a phrase, try again closer to the mic");
+            }
         }
     }
 
-    fn convert_pcm16_to_f32(&self, bytes: &[u8]) -> Vec<f32> {
-        let mut samples = Vec::with_capacity(bytes.len() / 2);
+    /// With local turn-taking, do what the server does on `speech_started`/`speech_stopped`.
+    fn handle_local_vad_event(&mut self, cx: &mut Cx, event: VadEvent) {
+        log_debug!(Audio, "Local VAD: {:?}", event);
+        self.trace.instant(Track::AudioInput, if event == VadEvent::SpeechStarted { "local vad start" } else { "local vad end" }, None);
+        if self.settings.vad.mode != VadMode::TurnTaking {
+            return;
+        }
+
+        match event {
+            VadEvent::SpeechStarted => {
+                self.trace.begin(Track::UserSpeech, "user speech", Instant::now());
+                self.user_speech_started(cx);
+                if self.assistant_is_audible() {
+                    // Barge-in, only possible when the mic stays open during responses
+                    self.cancel_response(cx);
+                }
+                self.ui
+                    .label(id!(status_label))
+                    .set_text(cx, "🎤 User speech detected");
+            }
+            VadEvent::SpeechEnded => {
+                self.trace.end(Track::UserSpeech, "user speech", Instant::now());
+                self.user_is_speaking = false;
+                self.latency.speech_stopped(self.latency.input_audio_ms());
+
+                self.send_openai_message(OpenAIRealtimeMessage::InputAudioBufferCommit);
+                self.send_openai_message(OpenAIRealtimeMessage::ResponseCreate {
+                    response: ResponseConfig {
+                        modalities: vec!["text".to_string(), "audio".to_string()],
+                        instructions: None,
+                        voice: None,
+                        output_audio_format: None,
+                        tools: None,
+                        tool_choice: None,
+                        temperature: None,
+                        max_output_tokens: None,
+                        conversation: None,
+                        metadata: None,
+                        input: None,
+                    },
+                });
+
+                self.ui.label(id!(status_label)).set_text(cx, "🤔 Processing...");
+                if self.conversation_active {
+                    *self.is_recording.lock().unwrap() = false;
+                }
+            }
+        }
+    }
 
-        for chunk in bytes.chunks_exact(2) {
-            let pcm16_sample = i16::from_le_bytes([chunk[0], chunk[1]]);
-            let f32_sample = pcm16_sample as f32 / 32767.0;
-            samples.push(f32_sample);
+    fn add_audio_to_playback(&mut self, audio_bytes: Vec<u8>) {
+        // Don't add audio if user is currently speaking (to prevent feedback)
+        if !self.ai_is_responding {
+            log_debug!(Audio, "Skipping AI audio - user is speaking or AI not actively responding");
+            return;
         }
 
-        samples
+        // Convert the session's audio format back to f32 samples
+        let samples = self.codec.decode(&audio_bytes);
+
+        // Queue into the jitter buffer, playback starts once enough audio is buffered
+        let mut playback = self.playback.lock().unwrap();
+        if playback.is_idle() {
+            log_debug!(
+                Audio,
+                "Started buffering OpenAI response audio ({} samples)",
+                samples.len()
+            );
+        }
+        playback.push(&samples);
     }
 
     fn update_ui_state(&self, cx: &mut Cx) {
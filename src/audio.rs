@@ -0,0 +1,681 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Audio helpers shared between the Makepad audio callbacks and the UI thread.
+
+/// Anything below this amplitude is treated as digital silence, which is what muted
+/// or misbehaving devices (e.g. AirPods used as input) produce instead of real audio
+const DIGITAL_SILENCE_THRESHOLD: f32 = 1.0e-6;
+
+/// Samples at or above this amplitude are counted as clipped
+const CLIPPING_THRESHOLD: f32 = 0.99;
+
+/// Floor used when converting levels to decibels so silence doesn't turn into -inf
+pub const MIN_LEVEL_DB: f32 = -90.0;
+
+/// Input level accumulated by the capture callback between two reads from the UI.
+#[derive(Default)]
+pub struct InputLevel {
+    sum_squares: f64,
+    sample_count: usize,
+    peak: f32,
+    clipped_samples: usize,
+    silent_since: Option<Instant>,
+}
+
+impl InputLevel {
+    /// Accumulate a captured buffer. Called from the audio input callback.
+    pub fn update(&mut self, samples: &[f32]) {
+        let mut buffer_peak = 0.0f32;
+        for &sample in samples {
+            self.sum_squares += (sample as f64) * (sample as f64);
+            buffer_peak = buffer_peak.max(sample.abs());
+            if sample.abs() >= CLIPPING_THRESHOLD {
+                self.clipped_samples += 1;
+            }
+        }
+        self.sample_count += samples.len();
+        self.peak = self.peak.max(buffer_peak);
+
+        if buffer_peak > DIGITAL_SILENCE_THRESHOLD {
+            self.silent_since = None;
+        } else if self.silent_since.is_none() {
+            self.silent_since = Some(Instant::now());
+        }
+    }
+
+    /// Returns the (rms, peak) levels since the last call and starts a new measurement window.
+    pub fn take_levels(&mut self) -> (f32, f32) {
+        let rms = if self.sample_count > 0 {
+            (self.sum_squares / self.sample_count as f64).sqrt() as f32
+        } else {
+            0.0
+        };
+        let peak = self.peak;
+
+        self.sum_squares = 0.0;
+        self.sample_count = 0;
+        self.peak = 0.0;
+
+        (rms, peak)
+    }
+
+    /// Number of clipped samples since the last call, the device gain is too high if this keeps growing.
+    pub fn take_clipped_samples(&mut self) -> usize {
+        std::mem::take(&mut self.clipped_samples)
+    }
+
+    /// How long the captured stream has been digitally silent, zero if there is signal.
+    pub fn silence_duration(&self) -> Duration {
+        self.silent_since
+            .map(|since| since.elapsed())
+            .unwrap_or_default()
+    }
+}
+
+pub fn amplitude_to_db(amplitude: f32) -> f32 {
+    if amplitude <= 0.0 {
+        return MIN_LEVEL_DB;
+    }
+    (20.0 * amplitude.log10()).max(MIN_LEVEL_DB)
+}
+
+/// Length of the window the noise floor is the minimum of
+const NOISE_FLOOR_WINDOW_MS: u32 = 4000;
+
+/// The window is tracked as this many block minima so it slides without keeping every frame
+const NOISE_FLOOR_BLOCKS: usize = 8;
+
+/// Floor assumed until the first window has been observed
+const INITIAL_NOISE_FLOOR_DB: f32 = -60.0;
+
+/// Background noise estimate: the quietest frame level over the last few seconds.
+///
+/// It is fed every frame, speech or not, so a steady noise that starts above the
+/// initial floor is learned within one window instead of being taken for speech forever.
+pub struct NoiseFloor {
+    frames_per_block: u32,
+    block_frames: u32,
+    block_min_db: f32,
+    blocks: VecDeque<f32>,
+}
+
+impl NoiseFloor {
+    pub fn new(frame_ms: u32) -> Self {
+        let window_frames = NOISE_FLOOR_WINDOW_MS / frame_ms.max(1);
+        Self {
+            frames_per_block: (window_frames / NOISE_FLOOR_BLOCKS as u32).max(1),
+            block_frames: 0,
+            block_min_db: f32::MAX,
+            blocks: VecDeque::from([INITIAL_NOISE_FLOOR_DB]),
+        }
+    }
+
+    /// Current estimate in dBFS.
+    pub fn db(&self) -> f32 {
+        self.blocks
+            .iter()
+            .copied()
+            .fold(self.block_min_db, f32::min)
+    }
+
+    /// Account for one frame and return the updated estimate.
+    pub fn update(&mut self, level_db: f32) -> f32 {
+        self.block_min_db = self.block_min_db.min(level_db);
+        self.block_frames += 1;
+        if self.block_frames >= self.frames_per_block {
+            if self.blocks.len() >= NOISE_FLOOR_BLOCKS {
+                self.blocks.pop_front();
+            }
+            self.blocks.push_back(self.block_min_db);
+            self.block_frames = 0;
+            self.block_min_db = f32::MAX;
+        }
+        self.db()
+    }
+}
+
+/// How a multichannel capture buffer is turned into the mono stream sent to OpenAI.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelMix {
+    /// Use a single channel (zero based)
+    Channel(usize),
+    /// Average all channels
+    #[default]
+    Average,
+    /// Follow whichever channel carries the most energy
+    Loudest,
+}
+
+impl ChannelMix {
+    /// Modes offered for a device with the given number of channels.
+    pub fn options(channel_count: usize) -> Vec<ChannelMix> {
+        let mut options = vec![ChannelMix::Average, ChannelMix::Loudest];
+        options.extend((0..channel_count.max(1)).map(ChannelMix::Channel));
+        options
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            ChannelMix::Channel(channel) => format!("Channel {}", channel + 1),
+            ChannelMix::Average => "Average all channels".to_string(),
+            ChannelMix::Loudest => "Loudest channel".to_string(),
+        }
+    }
+}
+
+// The loudest channel has to beat the current one by this factor before we switch,
+// so two similar mics don't make the capture flip-flop between buffers
+const LOUDEST_CHANNEL_HYSTERESIS: f32 = 2.0;
+
+/// Downmixes capture buffers to mono, keeping the state needed by `ChannelMix::Loudest`.
+#[derive(Default)]
+pub struct Downmixer {
+    loudest_channel: usize,
+}
+
+impl Downmixer {
+    /// Appends `frame_count` mono samples to `output`, reading channels through `channel`.
+    pub fn process<'a>(
+        &mut self,
+        mix: ChannelMix,
+        channel_count: usize,
+        frame_count: usize,
+        channel: impl Fn(usize) -> &'a [f32],
+        output: &mut Vec<f32>,
+    ) {
+        if channel_count == 0 {
+            output.extend(std::iter::repeat_n(0.0, frame_count));
+            return;
+        }
+
+        match mix {
+            ChannelMix::Channel(index) if index < channel_count => {
+                output.extend_from_slice(&channel(index)[..frame_count]);
+            }
+            // The selected channel doesn't exist on this device, don't go silent
+            ChannelMix::Channel(_) | ChannelMix::Average => {
+                let start = output.len();
+                output.extend_from_slice(&channel(0)[..frame_count]);
+                for index in 1..channel_count {
+                    for (mixed, &sample) in output[start..].iter_mut().zip(channel(index)) {
+                        *mixed += sample;
+                    }
+                }
+                let scale = 1.0 / channel_count as f32;
+                for mixed in &mut output[start..] {
+                    *mixed *= scale;
+                }
+            }
+            ChannelMix::Loudest => {
+                let energy = |index: usize| -> f32 {
+                    channel(index)[..frame_count].iter().map(|s| s * s).sum()
+                };
+
+                if self.loudest_channel >= channel_count {
+                    self.loudest_channel = 0;
+                }
+                let mut current_energy = energy(self.loudest_channel);
+                for index in 0..channel_count {
+                    let candidate_energy = energy(index);
+                    if candidate_energy > current_energy * LOUDEST_CHANNEL_HYSTERESIS {
+                        self.loudest_channel = index;
+                        current_energy = candidate_energy;
+                    }
+                }
+
+                output.extend_from_slice(&channel(self.loudest_channel)[..frame_count]);
+            }
+        }
+    }
+}
+
+/// OpenAI streams and expects audio at 24kHz, while Makepad runs the devices at 48kHz
+pub const OPENAI_SAMPLE_RATE: usize = 24000;
+
+// Playback runs at twice the rate of the audio OpenAI sends (48kHz vs 24kHz)
+const PLAYBACK_UPSAMPLE_FACTOR: usize = 2;
+
+// Ramps applied when playback starts, resumes after an underrun, or is stopped early.
+// Durations are in samples at 24kHz.
+const FADE_IN_SAMPLES: usize = 120; // 5ms
+const FADE_OUT_SAMPLES: usize = 480; // 20ms
+
+// Per-sample decay applied to the last played sample when the buffer runs dry,
+// so an underrun trails off over a few milliseconds instead of clicking
+const CONCEALMENT_DECAY: f32 = 0.99;
+
+pub const DEFAULT_PLAYBACK_LATENCY_MS: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PlaybackState {
+    /// Nothing to play
+    Idle,
+    /// Waiting until enough audio is queued to absorb bursty deltas
+    Buffering,
+    Playing,
+    /// Ramping down, the remaining queue is dropped once silent and `pending` starts
+    Stopping,
+}
+
+/// Jitter buffer for assistant audio (24kHz mono), rendered by the audio output callback.
+///
+/// Playback only starts once `target_latency` worth of audio is queued (or the stream
+/// ended), underruns are concealed and re-buffered, and every start/stop is ramped.
+///
+/// A stream is the audio of one assistant item, see `begin_stream`. Its bookkeeping
+/// (played samples, start and end time) only counts its own audio, even when it starts
+/// while the previous stream is still playing out or fading.
+pub struct PlaybackBuffer {
+    queue: VecDeque<f32>,
+    /// Audio of the next stream, pushed while the current one fades out
+    pending: VecDeque<f32>,
+    /// Samples at the front of `queue` that belong to the previous stream
+    previous_stream_samples: usize,
+    stream_item_id: Option<String>,
+    state: PlaybackState,
+    target_latency_samples: usize,
+    stream_ended: bool,
+    gain: f32,
+    gain_step: f32,
+    last_sample: f32,
+    current_sample: f32,
+    upsample_phase: usize,
+    stream_played_samples: usize,
+    stream_started_at: Option<Instant>,
+    stream_finished_at: Option<Instant>,
+    underruns: usize,
+}
+
+impl Default for PlaybackBuffer {
+    fn default() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            pending: VecDeque::new(),
+            previous_stream_samples: 0,
+            stream_item_id: None,
+            state: PlaybackState::Idle,
+            target_latency_samples: DEFAULT_PLAYBACK_LATENCY_MS * OPENAI_SAMPLE_RATE / 1000,
+            stream_ended: false,
+            gain: 0.0,
+            gain_step: 0.0,
+            last_sample: 0.0,
+            current_sample: 0.0,
+            upsample_phase: 0,
+            stream_played_samples: 0,
+            stream_started_at: None,
+            stream_finished_at: None,
+            underruns: 0,
+        }
+    }
+}
+
+impl PlaybackBuffer {
+    pub fn set_target_latency_ms(&mut self, latency_ms: usize) {
+        self.target_latency_samples = latency_ms * OPENAI_SAMPLE_RATE / 1000;
+    }
+
+    /// Start the bookkeeping of a new stream, before its first `push`. `item_id` is the
+    /// assistant item the audio belongs to, None for audio that isn't part of the conversation.
+    pub fn begin_stream(&mut self, item_id: Option<&str>) {
+        self.stream_item_id = item_id.map(str::to_string);
+        self.stream_ended = false;
+        self.stream_played_samples = 0;
+        self.stream_started_at = None;
+        self.stream_finished_at = None;
+        self.previous_stream_samples = self.queue.len();
+        self.pending.clear();
+    }
+
+    /// Queue decoded audio of the current stream. While the previous stream fades out
+    /// it waits, and starts once the fade is done.
+    pub fn push(&mut self, samples: &[f32]) {
+        match self.state {
+            PlaybackState::Stopping => {
+                self.pending.extend(samples);
+                return;
+            }
+            PlaybackState::Idle => self.state = PlaybackState::Buffering,
+            PlaybackState::Buffering | PlaybackState::Playing => {}
+        }
+
+        self.queue.extend(samples);
+
+        if self.state == PlaybackState::Buffering && self.queue.len() >= self.target_latency_samples {
+            self.start_playing();
+        }
+    }
+
+    /// No more audio is coming for this stream, play out whatever is queued.
+    pub fn end_stream(&mut self) {
+        self.stream_ended = true;
+        if self.state == PlaybackState::Buffering {
+            if self.queue.is_empty() {
+                self.finish_stream();
+            } else {
+                self.start_playing();
+            }
+        }
+    }
+
+    /// Ramp down to silence and drop everything still queued.
+    pub fn fade_out(&mut self) {
+        self.pending.clear();
+        match self.state {
+            PlaybackState::Playing => {
+                self.state = PlaybackState::Stopping;
+                self.gain_step = -1.0 / FADE_OUT_SAMPLES as f32;
+            }
+            PlaybackState::Buffering => {
+                self.queue.clear();
+                self.previous_stream_samples = 0;
+                self.finish_stream();
+            }
+            PlaybackState::Idle | PlaybackState::Stopping => {}
+        }
+    }
+
+    /// Drop everything immediately, only for when nobody is listening anymore.
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.pending.clear();
+        self.previous_stream_samples = 0;
+        self.finish_stream();
+        self.stream_ended = false;
+        self.gain = 0.0;
+        self.last_sample = 0.0;
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.state == PlaybackState::Idle
+    }
+
+    /// Item of the stream that can still be heard, None once it is idle or fading out.
+    pub fn stream_item_id(&self) -> Option<&str> {
+        match self.state {
+            PlaybackState::Idle => None,
+            PlaybackState::Stopping if self.pending.is_empty() => None,
+            _ => self.stream_item_id.as_deref(),
+        }
+    }
+
+    /// Samples (24kHz) rendered since the current stream started, including any fade out.
+    pub fn stream_played_samples(&self) -> usize {
+        self.stream_played_samples
+    }
+
+    /// When the first sample of the current stream was rendered.
+    pub fn stream_started_at(&self) -> Option<Instant> {
+        self.stream_started_at
+    }
+
+    /// When the current stream went back to idle, None while it is still going.
+    pub fn stream_finished_at(&self) -> Option<Instant> {
+        self.stream_finished_at
+    }
+
+    /// Number of underruns since the last call.
+    pub fn take_underruns(&mut self) -> usize {
+        std::mem::take(&mut self.underruns)
+    }
+
+    /// Fill a mono output buffer at the device rate. Called from the audio output callback.
+    pub fn render(&mut self, output: &mut [f32]) {
+        for frame in output.iter_mut() {
+            if self.upsample_phase == 0 {
+                self.current_sample = self.next_sample();
+            }
+            *frame = self.current_sample;
+            self.upsample_phase = (self.upsample_phase + 1) % PLAYBACK_UPSAMPLE_FACTOR;
+        }
+    }
+
+    fn finish_stream(&mut self) {
+        if self.state != PlaybackState::Idle {
+            self.stream_finished_at = Some(Instant::now());
+        }
+        self.state = PlaybackState::Idle;
+    }
+
+    fn start_playing(&mut self) {
+        self.state = PlaybackState::Playing;
+        self.gain = 0.0;
+        self.gain_step = 1.0 / FADE_IN_SAMPLES as f32;
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        if !matches!(self.state, PlaybackState::Playing | PlaybackState::Stopping) {
+            return self.conceal();
+        }
+
+        let Some(sample) = self.queue.pop_front() else {
+            if self.state == PlaybackState::Stopping {
+                self.fade_out_done();
+            } else if self.stream_ended {
+                self.finish_stream();
+            } else {
+                // Underrun: trail off and wait for the buffer to refill
+                self.underruns += 1;
+                self.state = PlaybackState::Buffering;
+            }
+            return self.conceal();
+        };
+
+        self.gain = (self.gain + self.gain_step).clamp(0.0, 1.0);
+        self.last_sample = sample * self.gain;
+        if self.previous_stream_samples > 0 {
+            self.previous_stream_samples -= 1;
+        } else {
+            if self.stream_played_samples == 0 {
+                self.stream_started_at = Some(Instant::now());
+            }
+            self.stream_played_samples += 1;
+        }
+
+        if self.state == PlaybackState::Stopping && self.gain <= 0.0 {
+            self.fade_out_done();
+        }
+
+        self.last_sample
+    }
+
+    /// Drops what was left of the faded stream and starts the one queued behind it, if any.
+    fn fade_out_done(&mut self) {
+        self.queue.clear();
+        self.previous_stream_samples = 0;
+        if self.pending.is_empty() {
+            self.finish_stream();
+            return;
+        }
+
+        self.queue = std::mem::take(&mut self.pending);
+        self.state = PlaybackState::Buffering;
+        if self.stream_ended || self.queue.len() >= self.target_latency_samples {
+            self.start_playing();
+        }
+    }
+
+    fn conceal(&mut self) -> f32 {
+        self.last_sample *= CONCEALMENT_DECAY;
+        self.last_sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn downmix(downmixer: &mut Downmixer, mix: ChannelMix, channels: &[Vec<f32>]) -> Vec<f32> {
+        let mut output = Vec::new();
+        downmixer.process(mix, channels.len(), channels[0].len(), |index| &channels[index], &mut output);
+        output
+    }
+
+    #[test]
+    fn channel_picks_one_channel() {
+        let channels = vec![vec![0.1; 4], vec![0.2; 4], vec![0.3; 4]];
+        let output = downmix(&mut Downmixer::default(), ChannelMix::Channel(1), &channels);
+        assert_eq!(output, vec![0.2; 4]);
+    }
+
+    #[test]
+    fn missing_channel_falls_back_to_average() {
+        let channels = vec![vec![0.2; 4], vec![0.4; 4]];
+        let output = downmix(&mut Downmixer::default(), ChannelMix::Channel(5), &channels);
+        for sample in output {
+            assert!((sample - 0.3).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn average_mixes_all_channels() {
+        let channels = vec![vec![1.0, -1.0], vec![0.0, 0.0], vec![0.5, 0.5]];
+        let output = downmix(&mut Downmixer::default(), ChannelMix::Average, &channels);
+        assert!((output[0] - 0.5).abs() < 1e-6);
+        assert!((output[1] + 1.0 / 6.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn no_channels_is_silence() {
+        let mut output = Vec::new();
+        Downmixer::default().process(ChannelMix::Average, 0, 3, |_| &[], &mut output);
+        assert_eq!(output, vec![0.0; 3]);
+    }
+
+    #[test]
+    fn loudest_follows_the_loudest_channel() {
+        let mut downmixer = Downmixer::default();
+        let channels = vec![vec![0.01; 8], vec![0.5; 8]];
+        assert_eq!(downmix(&mut downmixer, ChannelMix::Loudest, &channels), vec![0.5; 8]);
+    }
+
+    #[test]
+    fn loudest_holds_on_to_similar_channels() {
+        let mut downmixer = Downmixer::default();
+        // Two mics taking turns at slightly higher levels, neither beats the other by the hysteresis factor
+        let louder_first = vec![vec![0.30; 8], vec![0.25; 8]];
+        let louder_second = vec![vec![0.25; 8], vec![0.30; 8]];
+        for _ in 0..4 {
+            assert_eq!(downmix(&mut downmixer, ChannelMix::Loudest, &louder_first), vec![0.30; 8]);
+            assert_eq!(downmix(&mut downmixer, ChannelMix::Loudest, &louder_second), vec![0.25; 8]);
+        }
+
+        // A clearly louder channel takes over, and keeps it against the same small margin
+        let much_louder_second = vec![vec![0.1; 8], vec![0.5; 8]];
+        assert_eq!(downmix(&mut downmixer, ChannelMix::Loudest, &much_louder_second), vec![0.5; 8]);
+        assert_eq!(downmix(&mut downmixer, ChannelMix::Loudest, &louder_first), vec![0.25; 8]);
+    }
+
+    fn render_samples(playback: &mut PlaybackBuffer, samples: usize) -> Vec<f32> {
+        let mut output = vec![0.0; samples * PLAYBACK_UPSAMPLE_FACTOR];
+        playback.render(&mut output);
+        output.into_iter().step_by(PLAYBACK_UPSAMPLE_FACTOR).collect()
+    }
+
+    fn playing(latency_ms: usize, samples: usize) -> PlaybackBuffer {
+        let mut playback = PlaybackBuffer::default();
+        playback.set_target_latency_ms(latency_ms);
+        playback.begin_stream(Some("item_1"));
+        playback.push(&vec![0.5; samples]);
+        playback
+    }
+
+    #[test]
+    fn playback_waits_for_the_target_latency() {
+        let mut playback = PlaybackBuffer::default();
+        playback.set_target_latency_ms(10);
+        playback.begin_stream(Some("item_1"));
+        playback.push(&[0.5; 100]);
+        assert_eq!(playback.state, PlaybackState::Buffering);
+        assert!(render_samples(&mut playback, 10).iter().all(|&sample| sample == 0.0));
+
+        playback.push(&[0.5; 140]);
+        assert_eq!(playback.state, PlaybackState::Playing);
+        let output = render_samples(&mut playback, FADE_IN_SAMPLES + 10);
+        assert!(output[0] < output[FADE_IN_SAMPLES / 2]);
+        assert_eq!(output[FADE_IN_SAMPLES + 5], 0.5);
+    }
+
+    #[test]
+    fn underrun_conceals_and_rebuffers() {
+        let mut playback = playing(10, 240);
+        render_samples(&mut playback, 240);
+        let tail = render_samples(&mut playback, 10);
+        assert_eq!(playback.state, PlaybackState::Buffering);
+        assert_eq!(playback.take_underruns(), 1);
+        // Trails off instead of dropping to zero
+        assert!(tail[0] > 0.0 && tail[9] < tail[0]);
+
+        playback.push(&[0.5; 240]);
+        assert_eq!(playback.state, PlaybackState::Playing);
+        assert_eq!(playback.stream_played_samples(), 240);
+    }
+
+    #[test]
+    fn ended_stream_plays_out_and_goes_idle() {
+        let mut playback = playing(100, 240);
+        playback.end_stream();
+        assert_eq!(playback.state, PlaybackState::Playing);
+        render_samples(&mut playback, 241);
+        assert!(playback.is_idle());
+        assert_eq!(playback.take_underruns(), 0);
+        assert!(playback.stream_finished_at().is_some());
+    }
+
+    #[test]
+    fn fade_out_ramps_down_and_drops_the_rest() {
+        let mut playback = playing(10, 4800);
+        render_samples(&mut playback, 1000);
+        assert_eq!(playback.stream_item_id(), Some("item_1"));
+
+        playback.fade_out();
+        assert_eq!(playback.stream_item_id(), None);
+        let output = render_samples(&mut playback, FADE_OUT_SAMPLES + 10);
+        assert!(output.windows(2).take(FADE_OUT_SAMPLES).all(|pair| pair[1] <= pair[0]));
+        assert!(playback.is_idle());
+        assert!(playback.queue.is_empty());
+        assert_eq!(playback.stream_played_samples(), 1000 + FADE_OUT_SAMPLES);
+    }
+
+    #[test]
+    fn push_while_stopping_plays_after_the_fade() {
+        let mut playback = playing(10, 4800);
+        render_samples(&mut playback, 1000);
+        playback.fade_out();
+
+        playback.begin_stream(Some("item_2"));
+        playback.push(&[0.25; 480]);
+        playback.end_stream();
+        assert_eq!(playback.stream_item_id(), Some("item_2"));
+
+        render_samples(&mut playback, FADE_OUT_SAMPLES);
+        assert_eq!(playback.state, PlaybackState::Playing);
+        // The fade of the old stream doesn't count towards the new one
+        assert_eq!(playback.stream_played_samples(), 0);
+
+        let output = render_samples(&mut playback, 480);
+        assert_eq!(output[479], 0.25);
+        assert_eq!(playback.stream_played_samples(), 480);
+        render_samples(&mut playback, 1);
+        assert!(playback.is_idle());
+    }
+
+    #[test]
+    fn new_stream_during_the_previous_tail_counts_only_its_own_audio() {
+        let mut playback = playing(10, 1000);
+        playback.end_stream();
+        render_samples(&mut playback, 600);
+
+        playback.begin_stream(Some("item_2"));
+        playback.push(&[0.25; 500]);
+        render_samples(&mut playback, 400);
+        assert_eq!(playback.stream_played_samples(), 0);
+        assert!(playback.stream_started_at().is_none());
+
+        render_samples(&mut playback, 100);
+        assert_eq!(playback.stream_played_samples(), 100);
+        assert!(playback.stream_started_at().is_some());
+    }
+}
@@ -0,0 +1,365 @@
+use crate::audio::OPENAI_SAMPLE_RATE;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::sync::OnceLock;
+
+// Wire formats of the Realtime API. The app works on 24kHz f32 audio everywhere, this is
+// where it is turned into what the session was configured with and back.
+//
+// G.711 runs at 8kHz, so on top of companding the audio is resampled by a factor of 3
+// with a low-pass FIR in both directions (anti-aliasing down, anti-imaging up).
+
+const G711_SAMPLE_RATE: usize = 8000;
+const RESAMPLE_FACTOR: usize = OPENAI_SAMPLE_RATE / G711_SAMPLE_RATE;
+
+const FILTER_TAPS: usize = 47;
+// Just under the 4kHz Nyquist frequency of the 8kHz side
+const FILTER_CUTOFF_HZ: f32 = 3600.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum AudioFormat {
+    #[default]
+    #[serde(rename = "pcm16")]
+    Pcm16,
+    #[serde(rename = "g711_ulaw")]
+    G711Ulaw,
+    #[serde(rename = "g711_alaw")]
+    G711Alaw,
+}
+
+impl AudioFormat {
+    /// In the order of `audio_format_selector`
+    pub const ALL: [AudioFormat; 3] = [AudioFormat::Pcm16, AudioFormat::G711Ulaw, AudioFormat::G711Alaw];
+
+    /// Name used for `input_audio_format`/`output_audio_format`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AudioFormat::Pcm16 => "pcm16",
+            AudioFormat::G711Ulaw => "g711_ulaw",
+            AudioFormat::G711Alaw => "g711_alaw",
+        }
+    }
+
+    pub fn sample_rate(&self) -> usize {
+        match self {
+            AudioFormat::Pcm16 => OPENAI_SAMPLE_RATE,
+            AudioFormat::G711Ulaw | AudioFormat::G711Alaw => G711_SAMPLE_RATE,
+        }
+    }
+}
+
+/// Encodes captured audio and decodes assistant audio for one session.
+pub struct AudioCodec {
+    format: AudioFormat,
+    downsampler: LowPass,
+    downsample_phase: usize,
+    upsampler: LowPass,
+}
+
+impl Default for AudioCodec {
+    fn default() -> Self {
+        Self::new(AudioFormat::default())
+    }
+}
+
+impl AudioCodec {
+    pub fn new(format: AudioFormat) -> Self {
+        Self {
+            format,
+            downsampler: LowPass::default(),
+            downsample_phase: 0,
+            upsampler: LowPass::default(),
+        }
+    }
+
+    pub fn format(&self) -> AudioFormat {
+        self.format
+    }
+
+    /// 24kHz samples to bytes for `input_audio_buffer.append`.
+    pub fn encode(&mut self, samples: &[f32]) -> Vec<u8> {
+        let companded: fn(i16) -> u8 = match self.format {
+            AudioFormat::Pcm16 => return f32_to_pcm16(samples),
+            AudioFormat::G711Ulaw => linear_to_ulaw,
+            AudioFormat::G711Alaw => linear_to_alaw,
+        };
+
+        let mut bytes = Vec::with_capacity(samples.len() / RESAMPLE_FACTOR + 1);
+        for &sample in samples {
+            self.downsampler.push(sample);
+            self.downsample_phase = (self.downsample_phase + 1) % RESAMPLE_FACTOR;
+            if self.downsample_phase == 0 {
+                bytes.push(companded(f32_to_i16(self.downsampler.output())));
+            }
+        }
+        bytes
+    }
+
+    /// Bytes of a `response.audio.delta` to 24kHz samples.
+    pub fn decode(&mut self, bytes: &[u8]) -> Vec<f32> {
+        let expanded: fn(u8) -> i16 = match self.format {
+            AudioFormat::Pcm16 => return pcm16_to_f32(bytes),
+            AudioFormat::G711Ulaw => ulaw_to_linear,
+            AudioFormat::G711Alaw => alaw_to_linear,
+        };
+
+        let mut samples = Vec::with_capacity(bytes.len() * RESAMPLE_FACTOR);
+        for &byte in bytes {
+            // Zero stuffing, the filter fills in the gaps. The gain makes up for the zeros.
+            let sample = expanded(byte) as f32 / 32767.0;
+            for phase in 0..RESAMPLE_FACTOR {
+                let stuffed = if phase == 0 {
+                    sample * RESAMPLE_FACTOR as f32
+                } else {
+                    0.0
+                };
+                self.upsampler.push(stuffed);
+                samples.push(self.upsampler.output());
+            }
+        }
+        samples
+    }
+}
+
+pub fn f32_to_pcm16(samples: &[f32]) -> Vec<u8> {
+    let mut pcm16_bytes = Vec::with_capacity(samples.len() * 2);
+    for &sample in samples {
+        pcm16_bytes.extend_from_slice(&f32_to_i16(sample).to_le_bytes());
+    }
+    pcm16_bytes
+}
+
+pub fn pcm16_to_f32(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(2)
+        .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]) as f32 / 32767.0)
+        .collect()
+}
+
+fn f32_to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * 32767.0) as i16
+}
+
+// G.711 companding, after the public domain reference implementation by Sun Microsystems
+
+const ULAW_BIAS: i32 = 0x84;
+const ULAW_CLIP: i32 = 32635;
+
+pub fn linear_to_ulaw(sample: i16) -> u8 {
+    let mut magnitude = sample as i32;
+    let sign = if magnitude < 0 {
+        magnitude = -magnitude;
+        0x80
+    } else {
+        0x00
+    };
+    let biased = magnitude.min(ULAW_CLIP) + ULAW_BIAS;
+    // Position of the highest set bit above the 8 bit minimum the bias guarantees
+    let exponent = (31 - (biased as u32).leading_zeros()) as i32 - 7;
+    let mantissa = (biased >> (exponent + 3)) & 0x0F;
+    !(sign | (exponent << 4) as u8 | mantissa as u8)
+}
+
+pub fn ulaw_to_linear(byte: u8) -> i16 {
+    let byte = !byte;
+    let exponent = (byte >> 4) & 0x07;
+    let mantissa = (byte & 0x0F) as i32;
+    let magnitude = (((mantissa << 3) + ULAW_BIAS) << exponent) - ULAW_BIAS;
+    if byte & 0x80 != 0 {
+        -magnitude as i16
+    } else {
+        magnitude as i16
+    }
+}
+
+// Upper ends of the A-law segments on the 13 bit scale
+const ALAW_SEGMENT_ENDS: [i32; 8] = [0x1F, 0x3F, 0x7F, 0xFF, 0x1FF, 0x3FF, 0x7FF, 0xFFF];
+
+pub fn linear_to_alaw(sample: i16) -> u8 {
+    let mut magnitude = sample as i32 >> 3;
+    let mask = if magnitude >= 0 {
+        0xD5
+    } else {
+        magnitude = -magnitude - 1;
+        0x55
+    };
+
+    let Some(segment) = ALAW_SEGMENT_ENDS.iter().position(|&end| magnitude <= end) else {
+        return 0x7F ^ mask;
+    };
+    let shift = if segment < 2 { 1 } else { segment };
+    let value = ((segment as i32) << 4) | ((magnitude >> shift) & 0x0F);
+    value as u8 ^ mask
+}
+
+pub fn alaw_to_linear(byte: u8) -> i16 {
+    let byte = byte ^ 0x55;
+    let mut magnitude = ((byte & 0x0F) as i32) << 4;
+    let segment = (byte & 0x70) >> 4;
+    match segment {
+        0 => magnitude += 8,
+        1 => magnitude += 0x108,
+        _ => magnitude = (magnitude + 0x108) << (segment - 1),
+    }
+    if byte & 0x80 != 0 {
+        magnitude as i16
+    } else {
+        -magnitude as i16
+    }
+}
+
+/// Windowed-sinc FIR at 24kHz, shared by the down- and upsampling side.
+struct LowPass {
+    history: [f32; FILTER_TAPS],
+    position: usize,
+}
+
+impl Default for LowPass {
+    fn default() -> Self {
+        Self {
+            history: [0.0; FILTER_TAPS],
+            position: 0,
+        }
+    }
+}
+
+impl LowPass {
+    fn push(&mut self, sample: f32) {
+        self.history[self.position] = sample;
+        self.position = (self.position + 1) % FILTER_TAPS;
+    }
+
+    fn output(&self) -> f32 {
+        let taps = filter_taps();
+        // Oldest sample first, the newest one is just before `position`
+        let (newest, oldest) = self.history.split_at(self.position);
+        oldest
+            .iter()
+            .chain(newest)
+            .zip(taps.iter())
+            .map(|(sample, tap)| sample * tap)
+            .sum()
+    }
+}
+
+fn filter_taps() -> &'static [f32; FILTER_TAPS] {
+    static TAPS: OnceLock<[f32; FILTER_TAPS]> = OnceLock::new();
+    TAPS.get_or_init(|| {
+        let cutoff = FILTER_CUTOFF_HZ / OPENAI_SAMPLE_RATE as f32;
+        let center = (FILTER_TAPS - 1) as f32 / 2.0;
+        let mut taps = [0.0; FILTER_TAPS];
+        for (i, tap) in taps.iter_mut().enumerate() {
+            let x = i as f32 - center;
+            let sinc = if x == 0.0 {
+                2.0 * cutoff
+            } else {
+                (2.0 * PI * cutoff * x).sin() / (PI * x)
+            };
+            let hamming = 0.54 - 0.46 * (2.0 * PI * i as f32 / (FILTER_TAPS - 1) as f32).cos();
+            *tap = sinc * hamming;
+        }
+        // Unity gain at DC
+        let sum: f32 = taps.iter().sum();
+        for tap in &mut taps {
+            *tap /= sum;
+        }
+        taps
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(frequency_hz: f32, amplitude: f32, samples: usize) -> Vec<f32> {
+        (0..samples)
+            .map(|i| {
+                amplitude * (2.0 * PI * frequency_hz * i as f32 / OPENAI_SAMPLE_RATE as f32).sin()
+            })
+            .collect()
+    }
+
+    fn rms(samples: impl Iterator<Item = f32>) -> f32 {
+        let (sum_squares, count) = samples.fold((0.0, 0), |(sum, count), sample| {
+            (sum + sample * sample, count + 1)
+        });
+        (sum_squares / count as f32).sqrt()
+    }
+
+    #[test]
+    fn ulaw_matches_reference_values() {
+        for (linear, byte) in [
+            (0, 0xFF),
+            (-1, 0x7F),
+            (32767, 0x80),
+            (-32767, 0x00),
+            (-32768, 0x00),
+        ] {
+            assert_eq!(linear_to_ulaw(linear), byte, "{}", linear);
+        }
+        for (byte, linear) in [(0xFF, 0), (0x7F, 0), (0x80, 32124), (0x00, -32124)] {
+            assert_eq!(ulaw_to_linear(byte), linear, "{:#04x}", byte);
+        }
+        // Every code decodes to a value that encodes back to it, except negative zero
+        for byte in (0..=255u8).filter(|&byte| byte != 0x7F) {
+            assert_eq!(linear_to_ulaw(ulaw_to_linear(byte)), byte, "{:#04x}", byte);
+        }
+    }
+
+    #[test]
+    fn alaw_matches_reference_values() {
+        for (linear, byte) in [
+            (0, 0xD5),
+            (-1, 0x55),
+            (32767, 0xAA),
+            (-32767, 0x2A),
+            (-32768, 0x2A),
+        ] {
+            assert_eq!(linear_to_alaw(linear), byte, "{}", linear);
+        }
+        for (byte, linear) in [(0xD5, 8), (0x55, -8), (0xAA, 32256), (0x2A, -32256)] {
+            assert_eq!(alaw_to_linear(byte), linear, "{:#04x}", byte);
+        }
+        for byte in 0..=255u8 {
+            assert_eq!(linear_to_alaw(alaw_to_linear(byte)), byte, "{:#04x}", byte);
+        }
+    }
+
+    #[test]
+    fn g711_resamples_by_three_with_unity_dc_gain() {
+        for format in [AudioFormat::G711Ulaw, AudioFormat::G711Alaw] {
+            let mut codec = AudioCodec::new(format);
+            let bytes = codec.encode(&vec![0.5; 2400]);
+            assert_eq!(bytes.len(), 800);
+
+            let samples = codec.decode(&bytes);
+            assert_eq!(samples.len(), 2400);
+            // Past the delay of both filters the level is back to where it started
+            let settled = &samples[2 * FILTER_TAPS..];
+            assert!(
+                settled.iter().all(|sample| (sample - 0.5).abs() < 0.02),
+                "{:?}",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn downsampling_filters_out_what_would_alias() {
+        let expanded = |bytes: Vec<u8>| {
+            bytes
+                .into_iter()
+                .skip(FILTER_TAPS)
+                .map(|byte| ulaw_to_linear(byte) as f32 / 32767.0)
+        };
+
+        let mut codec = AudioCodec::new(AudioFormat::G711Ulaw);
+        let passed = rms(expanded(codec.encode(&tone(1000.0, 0.5, 4800))));
+        assert!((passed / rms(tone(1000.0, 0.5, 4800).into_iter()) - 1.0).abs() < 0.05);
+
+        // 6kHz is above the 4kHz Nyquist frequency and would come out as 2kHz
+        let mut codec = AudioCodec::new(AudioFormat::G711Ulaw);
+        let aliased = rms(expanded(codec.encode(&tone(6000.0, 0.5, 4800))));
+        assert!(aliased < 0.5 * 0.01, "{}", aliased);
+    }
+}
@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+
+// Local copy of the conversation as text, built from transcripts, so it can be carried
+// over to a new session or pruned without asking the server.
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HistoryItem {
+    pub item_id: String,
+    pub role: Role,
+    pub text: String,
+}
+
+#[derive(Default)]
+pub struct ConversationHistory {
+    items: Vec<HistoryItem>,
+}
+
+impl ConversationHistory {
+    /// Set the text of an item, adding it at the end if we haven't seen it yet.
+    pub fn set_text(&mut self, item_id: &str, role: Role, text: &str) {
+        match self.items.iter_mut().find(|item| item.item_id == item_id) {
+            Some(item) => item.text = text.to_string(),
+            None => self.items.push(HistoryItem {
+                item_id: item_id.to_string(),
+                role,
+                text: text.to_string(),
+            }),
+        }
+    }
+
+    /// Track an item from `conversation.item.created`, its text may only arrive later.
+    pub fn insert(&mut self, item_id: &str, role: Role, text: Option<&str>) {
+        match self.items.iter_mut().find(|item| item.item_id == item_id) {
+            Some(item) => {
+                if let Some(text) = text {
+                    item.text = text.to_string();
+                }
+            }
+            None => self.items.push(HistoryItem {
+                item_id: item_id.to_string(),
+                role,
+                text: text.unwrap_or_default().to_string(),
+            }),
+        }
+    }
+
+    /// Adds an item right after `previous_item_id`, at the start if None or unknown.
+    pub fn insert_after(&mut self, previous_item_id: Option<&str>, item: HistoryItem) {
+        let index = previous_item_id
+            .and_then(|previous| self.items.iter().position(|item| item.item_id == previous))
+            .map_or(0, |index| index + 1);
+        self.items.insert(index, item);
+    }
+
+    pub fn remove(&mut self, item_id: &str) -> Option<HistoryItem> {
+        let index = self.items.iter().position(|item| item.item_id == item_id)?;
+        Some(self.items.remove(index))
+    }
+
+    pub fn items(&self) -> &[HistoryItem] {
+        &self.items
+    }
+
+    pub fn replace(&mut self, items: Vec<HistoryItem>) {
+        self.items = items;
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    /// Everything but the most recent `keep_recent` items, oldest first.
+    pub fn oldest(&self, keep_recent: usize) -> &[HistoryItem] {
+        &self.items[..self.items.len().saturating_sub(keep_recent)]
+    }
+
+    /// The most recent `keep_recent` items verbatim, with everything before them folded
+    /// into a single system item (see `summarize`).
+    pub fn compacted(
+        &self,
+        keep_recent: usize,
+        max_summary_chars: usize,
+        summary_id: &str,
+    ) -> Vec<HistoryItem> {
+        let items: Vec<&HistoryItem> = self
+            .items
+            .iter()
+            .filter(|item| !item.text.trim().is_empty())
+            .collect();
+        let (older, recent) = items.split_at(items.len().saturating_sub(keep_recent));
+
+        let mut compacted = Vec::with_capacity(recent.len() + 1);
+        if !older.is_empty() {
+            compacted.push(HistoryItem {
+                item_id: summary_id.to_string(),
+                role: Role::System,
+                text: summarize(older.iter().copied(), max_summary_chars),
+            });
+        }
+        compacted.extend(recent.iter().map(|item| (*item).clone()));
+        compacted
+    }
+}
+
+/// A plain text digest of `items` of at most `max_chars`, the oldest lines are dropped first.
+pub fn summarize<'a>(
+    items: impl DoubleEndedIterator<Item = &'a HistoryItem>,
+    max_chars: usize,
+) -> String {
+    let mut lines = Vec::new();
+    let mut length = 0;
+    for item in items.rev().filter(|item| !item.text.trim().is_empty()) {
+        let line = format!("{}: {}", item.role.as_str(), item.text.trim());
+        length += line.len() + 1;
+        if length > max_chars {
+            break;
+        }
+        lines.push(line);
+    }
+    lines.reverse();
+    format!("Earlier in this conversation:\n{}", lines.join("\n"))
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PruneStrategy {
+    /// Delete the oldest items
+    #[default]
+    Delete,
+    /// Delete the oldest items and put a text summary of them at the start of the conversation
+    Summarize,
+}
+
+/// When and how to shrink the server-side conversation before it outgrows the context window.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ContextPolicy {
+    /// Prune once a response reports more input tokens than this, None disables pruning
+    pub max_input_tokens: Option<u64>,
+    /// Items that are never pruned, counting back from the latest one
+    pub keep_recent_items: usize,
+    pub strategy: PruneStrategy,
+    /// Upper bound for the summary text when summarizing
+    pub max_summary_chars: usize,
+}
+
+impl Default for ContextPolicy {
+    fn default() -> Self {
+        Self {
+            max_input_tokens: Some(24_000),
+            keep_recent_items: 10,
+            strategy: PruneStrategy::default(),
+            max_summary_chars: 4000,
+        }
+    }
+}
+
+impl ContextPolicy {
+    pub fn should_prune(&self, input_tokens: u64) -> bool {
+        self.max_input_tokens.is_some_and(|max| input_tokens > max)
+    }
+}
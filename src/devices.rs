@@ -0,0 +1,136 @@
+use makepad_widgets::*;
+
+// Audio device selection with fallback when the chosen device goes away.
+
+pub const SYSTEM_DEFAULT_LABEL: &str = "System default";
+
+/// Something the user should know about after the device list changed.
+pub enum DeviceNotice {
+    /// The preferred device disappeared, audio moved to the system default
+    Disconnected { device: String, fallback: Option<String> },
+    /// The preferred device came back after a fallback
+    Reconnected { device: String },
+    /// The system default changed while following it
+    NewDefault { device: String },
+}
+
+impl DeviceNotice {
+    pub fn describe(&self, kind: &str) -> String {
+        match self {
+            DeviceNotice::Disconnected { device, fallback: Some(fallback) } => {
+                format!("⚠️ {} '{}' unavailable, using '{}'", kind, device, fallback)
+            }
+            DeviceNotice::Disconnected { device, fallback: None } => {
+                format!("⚠️ {} '{}' unavailable and no default device found", kind, device)
+            }
+            DeviceNotice::Reconnected { device } => {
+                format!("✅ {} '{}' reconnected", kind, device)
+            }
+            DeviceNotice::NewDefault { device } => {
+                format!("🔄 Switched to new default {} '{}'", kind.to_lowercase(), device)
+            }
+        }
+    }
+}
+
+/// Devices for one direction (input or output) and the user's choice among them.
+#[derive(Default)]
+pub struct DeviceSelection {
+    devices: Vec<AudioDeviceDesc>,
+    /// Name of the device picked by the user, None follows the system default.
+    /// Names are used instead of ids because ids are not stable across restarts.
+    preferred: Option<String>,
+    default_name: Option<String>,
+    fell_back: bool,
+}
+
+impl DeviceSelection {
+    pub fn set_preferred(&mut self, preferred: Option<String>) {
+        self.preferred = preferred;
+        self.fell_back = false;
+    }
+
+    pub fn preferred(&self) -> Option<&String> {
+        self.preferred.as_ref()
+    }
+
+    /// Replace the known devices, returns a notice if the active device changed unexpectedly.
+    pub fn update<'a>(&mut self, descs: impl Iterator<Item = &'a AudioDeviceDesc>) -> Option<DeviceNotice> {
+        self.devices = descs.filter(|desc| !desc.has_failed).cloned().collect();
+
+        let previous_default = self.default_name.take();
+        self.default_name = self.default_device().map(|desc| desc.name.clone());
+
+        match &self.preferred {
+            Some(preferred) if self.find(preferred).is_some() => {
+                if std::mem::take(&mut self.fell_back) {
+                    return Some(DeviceNotice::Reconnected { device: preferred.clone() });
+                }
+                None
+            }
+            Some(preferred) => {
+                if self.fell_back {
+                    return None;
+                }
+                self.fell_back = true;
+                Some(DeviceNotice::Disconnected {
+                    device: preferred.clone(),
+                    fallback: self.default_name.clone(),
+                })
+            }
+            None => match (&previous_default, &self.default_name) {
+                (Some(previous), Some(current)) if previous != current => {
+                    Some(DeviceNotice::NewDefault { device: current.clone() })
+                }
+                _ => None,
+            },
+        }
+    }
+
+    /// Dropdown labels, the first entry always follows the system default.
+    pub fn labels(&self) -> Vec<String> {
+        std::iter::once(SYSTEM_DEFAULT_LABEL.to_string())
+            .chain(self.devices.iter().map(|desc| desc.name.clone()))
+            .collect()
+    }
+
+    /// Dropdown index of the preferred device, falls back to the system default entry.
+    pub fn selected_index(&self) -> usize {
+        self.preferred
+            .as_ref()
+            .and_then(|name| self.devices.iter().position(|desc| &desc.name == name))
+            .map(|position| position + 1)
+            .unwrap_or(0)
+    }
+
+    /// Apply a dropdown selection, see `labels`.
+    pub fn select_index(&mut self, index: usize) {
+        let preferred = index
+            .checked_sub(1)
+            .and_then(|position| self.devices.get(position))
+            .map(|desc| desc.name.clone());
+        self.set_preferred(preferred);
+    }
+
+    /// The devices that should be passed to `use_audio_inputs`/`use_audio_outputs`.
+    pub fn active_devices(&self) -> Vec<AudioDeviceId> {
+        self.active_device()
+            .map(|desc| vec![desc.device_id])
+            .unwrap_or_default()
+    }
+
+    pub fn active_device(&self) -> Option<&AudioDeviceDesc> {
+        self.preferred
+            .as_ref()
+            .and_then(|name| self.find(name))
+            .or_else(|| self.default_device())
+    }
+
+    fn find(&self, name: &str) -> Option<&AudioDeviceDesc> {
+        self.devices.iter().find(|desc| desc.name == name)
+    }
+
+    fn default_device(&self) -> Option<&AudioDeviceDesc> {
+        self.devices.iter().find(|desc| desc.is_default)
+    }
+}
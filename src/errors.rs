@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+// Classification of Realtime API errors and what to do about each class.
+
+// How many sent client events are remembered to correlate errors with
+const SENT_EVENTS_HISTORY: usize = 256;
+
+pub const MAX_RETRY_ATTEMPTS: u32 = 3;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    InvalidRequest,
+    RateLimit,
+    SessionExpired,
+    ServerError,
+    Auth,
+    Unknown,
+}
+
+impl ErrorKind {
+    /// Classify from the `type` and `code` fields of the error details.
+    pub fn classify(error_type: Option<&str>, code: Option<&str>) -> ErrorKind {
+        match (error_type.unwrap_or_default(), code.unwrap_or_default()) {
+            (_, "session_expired") => ErrorKind::SessionExpired,
+            (_, "rate_limit_exceeded") | ("rate_limit_error", _) => ErrorKind::RateLimit,
+            (_, "invalid_api_key")
+            | (_, "insufficient_quota")
+            | ("authentication_error", _)
+            | ("permission_error", _)
+            | ("insufficient_quota", _) => ErrorKind::Auth,
+            ("server_error", _) | (_, "server_error") => ErrorKind::ServerError,
+            ("invalid_request_error", _) => ErrorKind::InvalidRequest,
+            _ => ErrorKind::Unknown,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecoveryAction {
+    /// Send the client event that failed again, with backoff
+    Retry,
+    /// Open a new session
+    Reconnect,
+    /// Show the error and keep going
+    SurfaceToUser,
+    /// Show the error and end the session
+    Abort,
+}
+
+/// Recovery action per error class, configurable in the settings file.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RecoveryPolicies {
+    pub actions: BTreeMap<ErrorKind, RecoveryAction>,
+}
+
+impl Default for RecoveryPolicies {
+    fn default() -> Self {
+        Self {
+            actions: BTreeMap::from([
+                (ErrorKind::InvalidRequest, RecoveryAction::SurfaceToUser),
+                (ErrorKind::RateLimit, RecoveryAction::Retry),
+                (ErrorKind::SessionExpired, RecoveryAction::Reconnect),
+                (ErrorKind::ServerError, RecoveryAction::Retry),
+                (ErrorKind::Auth, RecoveryAction::Abort),
+                (ErrorKind::Unknown, RecoveryAction::SurfaceToUser),
+            ]),
+        }
+    }
+}
+
+impl RecoveryPolicies {
+    pub fn action_for(&self, kind: ErrorKind) -> RecoveryAction {
+        self.actions
+            .get(&kind)
+            .copied()
+            .unwrap_or(RecoveryAction::SurfaceToUser)
+    }
+}
+
+/// A client event we sent, kept around in case the server reports an error for it.
+#[derive(Clone, Debug)]
+pub struct SentEvent {
+    pub event_id: String,
+    pub event_type: &'static str,
+    /// The event without its id, None for events that aren't worth retrying (audio appends)
+    pub payload: Option<serde_json::Value>,
+    pub attempt: u32,
+}
+
+/// Hands out client event ids and remembers the most recent events.
+#[derive(Default)]
+pub struct SentEvents {
+    next_id: u64,
+    recent: VecDeque<SentEvent>,
+}
+
+impl SentEvents {
+    pub fn next_event_id(&mut self) -> String {
+        self.next_id += 1;
+        format!("client_evt_{}", self.next_id)
+    }
+
+    pub fn record(&mut self, event: SentEvent) {
+        if self.recent.len() >= SENT_EVENTS_HISTORY {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(event);
+    }
+
+    pub fn find(&self, event_id: &str) -> Option<&SentEvent> {
+        self.recent
+            .iter()
+            .rev()
+            .find(|event| event.event_id == event_id)
+    }
+}
+
+/// A server error, classified and correlated with the client event that caused it.
+#[derive(Debug)]
+pub struct RealtimeError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub code: Option<String>,
+    pub param: Option<String>,
+    pub caused_by: Option<SentEvent>,
+}
+
+impl RealtimeError {
+    /// Errors caused by racing the server, e.g. cancelling a response that just finished.
+    pub fn is_benign(&self) -> bool {
+        matches!(self.code.as_deref(), Some("response_cancel_not_active"))
+    }
+
+    pub fn describe(&self) -> String {
+        match &self.caused_by {
+            Some(event) => format!("{} failed: {}", event.event_type, self.message),
+            None => self.message.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_are_classified_by_type_and_code() {
+        let cases = [
+            (Some("invalid_request_error"), Some("session_expired"), ErrorKind::SessionExpired),
+            (Some("invalid_request_error"), Some("rate_limit_exceeded"), ErrorKind::RateLimit),
+            (Some("rate_limit_error"), None, ErrorKind::RateLimit),
+            (Some("invalid_request_error"), Some("invalid_api_key"), ErrorKind::Auth),
+            (Some("insufficient_quota"), Some("insufficient_quota"), ErrorKind::Auth),
+            (Some("authentication_error"), None, ErrorKind::Auth),
+            (Some("permission_error"), None, ErrorKind::Auth),
+            (Some("server_error"), None, ErrorKind::ServerError),
+            (None, Some("server_error"), ErrorKind::ServerError),
+            (Some("invalid_request_error"), Some("invalid_value"), ErrorKind::InvalidRequest),
+            (Some("invalid_request_error"), Some("conversation_already_has_active_response"), ErrorKind::InvalidRequest),
+            (Some("tokens"), None, ErrorKind::Unknown),
+            (None, None, ErrorKind::Unknown),
+        ];
+        for (error_type, code, kind) in cases {
+            assert_eq!(ErrorKind::classify(error_type, code), kind, "{:?} {:?}", error_type, code);
+        }
+    }
+
+    #[test]
+    fn cancelling_a_finished_response_is_benign() {
+        let error = |code: &str| RealtimeError {
+            kind: ErrorKind::InvalidRequest,
+            message: String::new(),
+            code: Some(code.to_string()),
+            param: None,
+            caused_by: None,
+        };
+        assert!(error("response_cancel_not_active").is_benign());
+        assert!(!error("invalid_value").is_benign());
+    }
+}
@@ -1,2 +1,23 @@
 pub use makepad_widgets;
 pub mod app;
+pub mod audio;
+pub mod codec;
+pub mod conversation;
+pub mod devices;
+pub mod errors;
+pub mod logging;
+pub mod metrics;
+pub mod out_of_band;
+pub mod persona;
+pub mod preprocess;
+pub mod relay;
+pub mod rollover;
+pub mod settings;
+pub mod telephony;
+pub mod token_service;
+pub mod trace;
+pub mod usage;
+pub mod vad;
+pub mod voice_preview;
+pub mod wake_word;
+pub mod ws_server;
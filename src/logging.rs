@@ -0,0 +1,374 @@
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::{LazyLock, RwLock};
+
+// Leveled logging with per-subsystem targets and redaction of secrets and audio.
+//
+// Use the `log_error!`, `log_warn!`, `log_info!`, `log_debug!` and `log_trace!` macros
+// with a `Target` variant, e.g. `log_info!(Transport, "WebSocket closed")`. Verbosity
+// comes from the settings file and can be overridden with the `REALTIME_LOG`
+// environment variable, e.g. `REALTIME_LOG=info,protocol=trace,audio=warn`.
+
+const LOG_ENV_VAR: &str = "REALTIME_LOG";
+
+// Runs of base64 characters at least this long are treated as encoded audio
+const MIN_REDACTED_BASE64_LEN: usize = 64;
+
+// OpenAI API keys look like `sk-...`, anything this long after the prefix gets hidden
+const MIN_REDACTED_KEY_LEN: usize = 16;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn parse(value: &str) -> Option<Level> {
+        match value.trim().to_lowercase().as_str() {
+            "off" => Some(Level::Off),
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Off => "OFF",
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    /// WebSocket connection and network
+    Transport,
+    /// Devices, capture and playback
+    Audio,
+    /// Realtime API events and session state
+    Protocol,
+    /// User interface, settings and exports
+    Ui,
+}
+
+impl Target {
+    fn parse(value: &str) -> Option<Target> {
+        match value.trim().to_lowercase().as_str() {
+            "transport" => Some(Target::Transport),
+            "audio" => Some(Target::Audio),
+            "protocol" => Some(Target::Protocol),
+            "ui" => Some(Target::Ui),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Target::Transport => "transport",
+            Target::Audio => "audio",
+            Target::Protocol => "protocol",
+            Target::Ui => "ui",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct LogConfig {
+    /// Level for targets without an override
+    pub level: Level,
+    /// Per-target overrides
+    pub targets: BTreeMap<Target, Level>,
+    /// Hide what the user and the assistant said
+    pub redact_transcripts: bool,
+    /// Exact strings that must never be logged, like the API key. Not persisted.
+    #[serde(skip)]
+    secrets: Vec<String>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: Level::Info,
+            targets: BTreeMap::new(),
+            redact_transcripts: false,
+            secrets: Vec::new(),
+        }
+    }
+}
+
+impl LogConfig {
+    /// Apply a `REALTIME_LOG` style spec: `<level>,<target>=<level>,...`, returns the
+    /// directives that were ignored.
+    fn apply_spec<'a>(&mut self, spec: &'a str) -> Vec<&'a str> {
+        let mut invalid = Vec::new();
+        for directive in spec
+            .split(',')
+            .filter(|directive| !directive.trim().is_empty())
+        {
+            match directive.split_once('=') {
+                Some((target, level)) => match (Target::parse(target), Level::parse(level)) {
+                    (Some(target), Some(level)) => {
+                        self.targets.insert(target, level);
+                    }
+                    _ => invalid.push(directive),
+                },
+                None => match Level::parse(directive) {
+                    Some(level) => self.level = level,
+                    None => invalid.push(directive),
+                },
+            }
+        }
+        invalid
+    }
+}
+
+static CONFIG: LazyLock<RwLock<LogConfig>> = LazyLock::new(|| RwLock::new(LogConfig::default()));
+
+/// Install the configuration from the settings, `REALTIME_LOG` takes precedence.
+pub fn init(config: &LogConfig) {
+    let mut config = config.clone();
+    let spec = std::env::var(LOG_ENV_VAR).unwrap_or_default();
+    let invalid = config.apply_spec(&spec);
+
+    {
+        let mut current = CONFIG.write().unwrap();
+        config.secrets = std::mem::take(&mut current.secrets);
+        *current = config;
+    }
+    // Only reported now that the config they were meant for is installed
+    for directive in invalid {
+        crate::log_warn!(Ui, "Ignoring invalid {} directive: {}", LOG_ENV_VAR, directive);
+    }
+}
+
+/// Make sure `secret` never shows up in the logs.
+pub fn register_secret(secret: &str) {
+    if secret.is_empty() {
+        return;
+    }
+    let mut config = CONFIG.write().unwrap();
+    if !config.secrets.iter().any(|known| known == secret) {
+        config.secrets.push(secret.to_string());
+    }
+}
+
+pub fn enabled(target: Target, level: Level) -> bool {
+    let config = CONFIG.read().unwrap();
+    let max_level = config.targets.get(&target).copied().unwrap_or(config.level);
+    level != Level::Off && level <= max_level
+}
+
+/// Hide secrets and base64 payloads in a formatted log message.
+pub fn redact(message: &str) -> String {
+    let mut message = message.to_string();
+
+    for secret in &CONFIG.read().unwrap().secrets {
+        if message.contains(secret.as_str()) {
+            message = message.replace(secret.as_str(), "[REDACTED]");
+        }
+    }
+
+    redact_base64(&redact_api_keys(&message))
+}
+
+fn redact_api_keys(message: &str) -> String {
+    let is_key_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+
+    let mut result = String::with_capacity(message.len());
+    let mut rest = message;
+    while let Some(start) = rest.find("sk-") {
+        let key = &rest[start + 3..];
+        let key_len = key.find(|c: char| !is_key_char(c)).unwrap_or(key.len());
+
+        result.push_str(&rest[..start]);
+        if key_len >= MIN_REDACTED_KEY_LEN {
+            result.push_str("sk-[REDACTED]");
+        } else {
+            result.push_str(&rest[start..start + 3 + key_len]);
+        }
+        rest = &key[key_len..];
+    }
+    result.push_str(rest);
+    result
+}
+
+fn redact_base64(message: &str) -> String {
+    let is_base64_char = |c: char| c.is_ascii_alphanumeric() || c == '+' || c == '/' || c == '=';
+
+    let mut result = String::with_capacity(message.len());
+    let mut rest = message;
+    while let Some(start) = rest.find(is_base64_char) {
+        let run = &rest[start..];
+        let run_len = run.find(|c: char| !is_base64_char(c)).unwrap_or(run.len());
+
+        result.push_str(&rest[..start]);
+        if run_len >= MIN_REDACTED_BASE64_LEN {
+            result.push_str(&format!("[{} bytes of base64]", run_len * 3 / 4));
+        } else {
+            result.push_str(&run[..run_len]);
+        }
+        rest = &run[run_len..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// What the user or the assistant said, hidden when `redact_transcripts` is on.
+pub fn transcript(text: &str) -> Cow<'_, str> {
+    if CONFIG.read().unwrap().redact_transcripts {
+        Cow::Owned(format!(
+            "[transcript redacted, {} chars]",
+            text.chars().count()
+        ))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// A raw protocol message, with transcript fields hidden when `redact_transcripts` is on.
+/// Audio and secrets are taken care of by `redact` when the message gets logged.
+pub fn payload(data: &str) -> Cow<'_, str> {
+    if !CONFIG.read().unwrap().redact_transcripts {
+        return Cow::Borrowed(data);
+    }
+
+    let Ok(mut value) = serde_json::from_str::<serde_json::Value>(data) else {
+        return Cow::Borrowed(data);
+    };
+    let is_text_event = value
+        .get("type")
+        .and_then(|event_type| event_type.as_str())
+        .is_some_and(|event_type| event_type.contains("text") || event_type.contains("transcript"));
+    redact_transcript_fields(&mut value, is_text_event);
+    Cow::Owned(value.to_string())
+}
+
+fn redact_transcript_fields(value: &mut serde_json::Value, is_text_event: bool) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                let is_transcript = matches!(key.as_str(), "transcript" | "text" | "instructions")
+                    || (is_text_event && key == "delta");
+                if let (true, Some(text)) = (is_transcript, field.as_str()) {
+                    *field = serde_json::Value::String(transcript(text).into_owned());
+                } else {
+                    redact_transcript_fields(field, is_text_event);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                redact_transcript_fields(item, is_text_event);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[macro_export]
+macro_rules! log_event {
+    ($level:ident, $target:ident, $($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::Target::$target, $crate::logging::Level::$level) {
+            $crate::makepad_widgets::log!(
+                "[{} {}] {}",
+                $crate::logging::Level::$level.as_str(),
+                $crate::logging::Target::$target.as_str(),
+                $crate::logging::redact(&format!($($arg)*))
+            );
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($target:ident, $($arg:tt)*) => { $crate::log_event!(Error, $target, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($target:ident, $($arg:tt)*) => { $crate::log_event!(Warn, $target, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($target:ident, $($arg:tt)*) => { $crate::log_event!(Info, $target, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($target:ident, $($arg:tt)*) => { $crate::log_event!(Debug, $target, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! log_trace {
+    ($target:ident, $($arg:tt)*) => { $crate::log_event!(Trace, $target, $($arg)*) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spec_sets_levels_and_returns_invalid_directives() {
+        let mut config = LogConfig::default();
+        let invalid = config.apply_spec("debug, protocol=trace,audio=loud,verbose,");
+        assert_eq!(config.level, Level::Debug);
+        assert_eq!(config.targets.get(&Target::Protocol), Some(&Level::Trace));
+        assert_eq!(config.targets.get(&Target::Audio), None);
+        assert_eq!(invalid, vec!["audio=loud", "verbose"]);
+    }
+
+    #[test]
+    fn api_keys_are_redacted() {
+        assert_eq!(
+            redact_api_keys("Bearer sk-proj-abcdefghijklmnop1234, done"),
+            "Bearer sk-[REDACTED], done"
+        );
+        // Too short to be a key
+        assert_eq!(redact_api_keys("task-list sk-short"), "task-list sk-short");
+        assert_eq!(redact_api_keys("sk-abcdefghijklmnop"), "sk-[REDACTED]");
+    }
+
+    #[test]
+    fn base64_runs_from_64_chars_are_redacted() {
+        let below = "A".repeat(MIN_REDACTED_BASE64_LEN - 1);
+        let at = "A".repeat(MIN_REDACTED_BASE64_LEN);
+        assert_eq!(redact_base64(&format!("\"{}\"", below)), format!("\"{}\"", below));
+        assert_eq!(redact_base64(&format!("\"{}\"", at)), "\"[48 bytes of base64]\"");
+        assert_eq!(redact_base64("plain words stay"), "plain words stay");
+    }
+
+    #[test]
+    fn payload_hides_transcripts_when_asked() {
+        let data = r#"{"type":"response.audio_transcript.delta","delta":"hello there","item_id":"item_1"}"#;
+        CONFIG.write().unwrap().redact_transcripts = true;
+        let redacted = payload(data).into_owned();
+        let nested = payload(r#"{"type":"conversation.item.created","item":{"content":[{"transcript":"hi"}]}}"#)
+            .into_owned();
+        CONFIG.write().unwrap().redact_transcripts = false;
+
+        let value: serde_json::Value = serde_json::from_str(&redacted).unwrap();
+        assert_eq!(value["delta"], "[transcript redacted, 11 chars]");
+        assert_eq!(value["item_id"], "item_1");
+        let value: serde_json::Value = serde_json::from_str(&nested).unwrap();
+        assert_eq!(value["item"]["content"][0]["transcript"], "[transcript redacted, 2 chars]");
+        assert_eq!(payload(data), data);
+    }
+}
@@ -0,0 +1,256 @@
+use crate::audio::OPENAI_SAMPLE_RATE;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::time::Instant;
+
+// Voice-to-voice latency measurement.
+//
+// There is no server clock to compare against, so the breakdown is based on local
+// receive times. The first leg runs from the moment the last chunk of user speech was
+// sent (found via `audio_end_ms`) until `speech_stopped` arrives, which covers the
+// upload, the server VAD silence window and the way back.
+
+// How much history of sent audio chunks is kept to resolve `audio_end_ms`
+const SENT_AUDIO_HISTORY: usize = 1000;
+
+#[derive(Default)]
+struct TurnTimeline {
+    speech_end_sent: Option<Instant>,
+    speech_stopped: Option<Instant>,
+    response_created: Option<Instant>,
+    first_audio_delta: Option<Instant>,
+    first_audio_rendered: Option<Instant>,
+    response_done: Option<Instant>,
+}
+
+/// Latency breakdown of one turn, in milliseconds.
+#[derive(Clone, Debug)]
+pub struct TurnLatency {
+    /// Last speech chunk sent -> `input_audio_buffer.speech_stopped` (upload, VAD, download)
+    pub network_and_vad_ms: Option<f64>,
+    /// `speech_stopped` -> `response.created`
+    pub response_start_ms: Option<f64>,
+    /// `speech_stopped` -> first `response.audio.delta`
+    pub server_ms: Option<f64>,
+    /// First audio delta -> first sample rendered by the output callback
+    pub local_buffering_ms: Option<f64>,
+    /// Last speech chunk sent (or `speech_stopped`) -> first sample rendered
+    pub voice_to_voice_ms: Option<f64>,
+    /// `response.created` -> `response.done`
+    pub response_ms: Option<f64>,
+}
+
+impl TurnLatency {
+    const COLUMNS: [&'static str; 6] = [
+        "network_and_vad_ms",
+        "response_start_ms",
+        "server_ms",
+        "local_buffering_ms",
+        "voice_to_voice_ms",
+        "response_ms",
+    ];
+
+    fn values(&self) -> [Option<f64>; 6] {
+        [
+            self.network_and_vad_ms,
+            self.response_start_ms,
+            self.server_ms,
+            self.local_buffering_ms,
+            self.voice_to_voice_ms,
+            self.response_ms,
+        ]
+    }
+}
+
+fn millis_between(from: Option<Instant>, to: Option<Instant>) -> Option<f64> {
+    Some(to?.checked_duration_since(from?)?.as_secs_f64() * 1000.0)
+}
+
+impl TurnTimeline {
+    fn latency(&self) -> TurnLatency {
+        TurnLatency {
+            network_and_vad_ms: millis_between(self.speech_end_sent, self.speech_stopped),
+            response_start_ms: millis_between(self.speech_stopped, self.response_created),
+            server_ms: millis_between(self.speech_stopped, self.first_audio_delta),
+            local_buffering_ms: millis_between(self.first_audio_delta, self.first_audio_rendered),
+            voice_to_voice_ms: millis_between(
+                self.speech_end_sent.or(self.speech_stopped),
+                self.first_audio_rendered,
+            ),
+            response_ms: millis_between(self.response_created, self.response_done),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p95: f64,
+}
+
+/// Nearest-rank percentiles, None when there are no samples.
+pub fn percentiles(values: &mut [f64]) -> Option<Percentiles> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let rank =
+        |p: f64| values[((p * values.len() as f64).ceil() as usize).clamp(1, values.len()) - 1];
+    Some(Percentiles {
+        p50: rank(0.50),
+        p90: rank(0.90),
+        p95: rank(0.95),
+    })
+}
+
+/// Collects per-turn timestamps and the resulting latency breakdowns for a session.
+#[derive(Default)]
+pub struct LatencyTracker {
+    current: Option<TurnTimeline>,
+    turns: Vec<TurnLatency>,
+    sent_audio_ms: f64,
+    /// (input audio buffer position in ms at the end of the chunk, when it was sent)
+    sent_audio: VecDeque<(f64, Instant)>,
+}
+
+impl LatencyTracker {
+    /// Track how far into the input audio buffer we are, `audio_end_ms` is relative to it.
+    pub fn audio_sent(&mut self, sample_count: usize) {
+        self.sent_audio_ms += sample_count as f64 * 1000.0 / OPENAI_SAMPLE_RATE as f64;
+        self.sent_audio
+            .push_back((self.sent_audio_ms, Instant::now()));
+        if self.sent_audio.len() > SENT_AUDIO_HISTORY {
+            self.sent_audio.pop_front();
+        }
+    }
+
+    /// Position in the input audio buffer, for turns ended locally.
+    pub fn input_audio_ms(&self) -> u32 {
+        self.sent_audio_ms as u32
+    }
+
+    /// A new session starts with an empty input audio buffer.
+    pub fn reset_input_audio(&mut self) {
+        self.sent_audio_ms = 0.0;
+        self.sent_audio.clear();
+    }
+
+    /// Starts a new turn, any unfinished previous turn is recorded as is.
+    pub fn speech_stopped(&mut self, audio_end_ms: u32) {
+        self.finish_turn();
+
+        let audio_end_ms = audio_end_ms as f64;
+        let speech_end_sent = self
+            .sent_audio
+            .iter()
+            .find(|(end_ms, _)| *end_ms >= audio_end_ms)
+            .map(|(_, sent_at)| *sent_at);
+
+        self.current = Some(TurnTimeline {
+            speech_end_sent,
+            speech_stopped: Some(Instant::now()),
+            ..Default::default()
+        });
+    }
+
+    pub fn response_created(&mut self) {
+        if let Some(turn) = &mut self.current {
+            turn.response_created.get_or_insert_with(Instant::now);
+        }
+    }
+
+    pub fn audio_delta_received(&mut self) {
+        if let Some(turn) = &mut self.current {
+            turn.first_audio_delta.get_or_insert_with(Instant::now);
+        }
+    }
+
+    /// Whether the current turn still waits for its first rendered sample.
+    pub fn awaiting_first_render(&self) -> bool {
+        self.current.as_ref().is_some_and(|turn| {
+            turn.first_audio_delta.is_some() && turn.first_audio_rendered.is_none()
+        })
+    }
+
+    /// Returns the finished turn if the response was already done.
+    pub fn audio_rendered(&mut self, rendered_at: Instant) -> Option<&TurnLatency> {
+        let turn = self.current.as_mut()?;
+        turn.first_audio_rendered.get_or_insert(rendered_at);
+        if turn.response_done.is_some() {
+            return self.finish_turn();
+        }
+        None
+    }
+
+    /// Returns the finished turn unless it is still waiting for audio to be rendered.
+    pub fn response_done(&mut self) -> Option<&TurnLatency> {
+        let turn = self.current.as_mut()?;
+        turn.response_done.get_or_insert_with(Instant::now);
+        if turn.first_audio_delta.is_some() && turn.first_audio_rendered.is_none() {
+            return None;
+        }
+        self.finish_turn()
+    }
+
+    fn finish_turn(&mut self) -> Option<&TurnLatency> {
+        let turn = self.current.take()?;
+        self.turns.push(turn.latency());
+        self.turns.last()
+    }
+
+    pub fn last_turn(&self) -> Option<&TurnLatency> {
+        self.turns.last()
+    }
+
+    pub fn turn_count(&self) -> usize {
+        self.turns.len()
+    }
+
+    /// Percentiles of the voice-to-voice latency over all turns.
+    pub fn voice_to_voice_percentiles(&self) -> Option<Percentiles> {
+        let mut values: Vec<f64> = self
+            .turns
+            .iter()
+            .filter_map(|turn| turn.voice_to_voice_ms)
+            .collect();
+        percentiles(&mut values)
+    }
+
+    fn column(&self, index: usize) -> Vec<f64> {
+        self.turns
+            .iter()
+            .filter_map(|turn| turn.values()[index])
+            .collect()
+    }
+
+    /// CSV with one row per turn followed by p50/p90/p95 summary rows.
+    pub fn to_report(&self) -> String {
+        let mut report = format!("turn,{}\n", TurnLatency::COLUMNS.join(","));
+
+        let format_value =
+            |value: Option<f64>| value.map(|ms| format!("{:.1}", ms)).unwrap_or_default();
+
+        for (index, turn) in self.turns.iter().enumerate() {
+            let values: Vec<String> = turn.values().into_iter().map(format_value).collect();
+            let _ = writeln!(report, "{},{}", index + 1, values.join(","));
+        }
+
+        let summaries: Vec<Option<Percentiles>> = (0..TurnLatency::COLUMNS.len())
+            .map(|index| percentiles(&mut self.column(index)))
+            .collect();
+        for (name, pick) in [
+            ("p50", (|p: Percentiles| p.p50) as fn(Percentiles) -> f64),
+            ("p90", |p| p.p90),
+            ("p95", |p| p.p95),
+        ] {
+            let values: Vec<String> = summaries
+                .iter()
+                .map(|summary| format_value(summary.map(pick)))
+                .collect();
+            let _ = writeln!(report, "{},{}", name, values.join(","));
+        }
+
+        report
+    }
+}
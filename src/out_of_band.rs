@@ -0,0 +1,341 @@
+use std::collections::{BTreeMap, HashMap};
+
+// Out-of-band responses run next to the spoken dialogue. They are created with
+// `conversation: "none"` so their output never lands in the default conversation, and
+// tagged with metadata so their events can be told apart from the assistant's turns.
+
+const PURPOSE_KEY: &str = "purpose";
+const REQUEST_KEY: &str = "out_of_band_id";
+
+const SUMMARY_INSTRUCTIONS: &str = "Summarize the conversation so far as meeting notes: \
+the topics discussed, decisions made and open questions. Be concise and use short bullet points.";
+
+const CONTEXT_SUMMARY_INSTRUCTIONS: &str = "Summarize this part of a conversation so it can replace \
+it as context for the rest of the conversation. Keep names, facts, decisions and anything the user \
+asked to remember. Write plain prose, no preamble";
+
+/// Request id of an out-of-band `response.create` event, see `OutOfBandResponses::start`.
+pub fn request_id(event: &serde_json::Value) -> Option<&str> {
+    event["response"]["metadata"][REQUEST_KEY].as_str()
+}
+
+/// Schema used by the "Extract action items" button.
+pub fn action_items_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "action_items": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "task": { "type": "string" },
+                        "owner": { "type": ["string", "null"] },
+                        "due": { "type": ["string", "null"] }
+                    },
+                    "required": ["task"]
+                }
+            }
+        },
+        "required": ["action_items"]
+    })
+}
+
+#[derive(Clone, Debug)]
+pub enum OutOfBandTask {
+    /// Meeting-notes style summary of the conversation so far
+    Summary,
+    /// Data pulled out of the conversation as JSON matching `schema`
+    Extraction {
+        name: String,
+        schema: serde_json::Value,
+    },
+    /// Replacement for the pruned items `item_ids`, see `App::prune_context`
+    ContextSummary {
+        item_ids: Vec<String>,
+        max_chars: usize,
+    },
+}
+
+impl OutOfBandTask {
+    pub fn name(&self) -> &str {
+        match self {
+            OutOfBandTask::Summary => "summary",
+            OutOfBandTask::Extraction { name, .. } => name,
+            OutOfBandTask::ContextSummary { .. } => "context summary",
+        }
+    }
+
+    /// The Realtime API has no structured output mode, extraction relies on instructions.
+    pub fn instructions(&self) -> String {
+        match self {
+            OutOfBandTask::Summary => SUMMARY_INSTRUCTIONS.to_string(),
+            OutOfBandTask::Extraction { schema, .. } => format!(
+                "Extract information from the conversation so far. Reply with a single JSON value \
+                 that validates against this JSON schema and nothing else, no markdown:\n{}",
+                schema
+            ),
+            OutOfBandTask::ContextSummary { max_chars, .. } => format!(
+                "{}, at most {} characters.",
+                CONTEXT_SUMMARY_INSTRUCTIONS, max_chars
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct OutOfBandResult {
+    pub task: OutOfBandTask,
+    /// `response.done` status, anything but "completed" means `text` may be partial or empty
+    pub status: Option<String>,
+    pub text: String,
+    /// Parsed and validated `text` for extractions
+    pub json: Option<Result<serde_json::Value, String>>,
+}
+
+/// Keeps track of the out-of-band responses in flight.
+#[derive(Default)]
+pub struct OutOfBandResponses {
+    next_id: u64,
+    /// Requested but not created yet, by request id
+    requested: HashMap<String, OutOfBandTask>,
+    /// Created, by response id
+    running: HashMap<String, OutOfBandTask>,
+}
+
+impl OutOfBandResponses {
+    /// Registers a request and returns the metadata to send along with its `response.create`.
+    pub fn start(&mut self, task: OutOfBandTask) -> BTreeMap<String, String> {
+        self.next_id += 1;
+        let request_id = format!("oob_{}", self.next_id);
+        let metadata = BTreeMap::from([
+            (PURPOSE_KEY.to_string(), task.name().to_string()),
+            (REQUEST_KEY.to_string(), request_id.clone()),
+        ]);
+        self.requested.insert(request_id, task);
+        metadata
+    }
+
+    /// Returns true if the response is one of ours, from then on it is known by its id.
+    pub fn response_created(
+        &mut self,
+        response_id: &str,
+        metadata: Option<&BTreeMap<String, String>>,
+    ) -> bool {
+        let task = metadata
+            .and_then(|metadata| metadata.get(REQUEST_KEY))
+            .and_then(|request_id| self.requested.remove(request_id));
+        match task {
+            Some(task) => {
+                self.running.insert(response_id.to_string(), task);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forgets a request that failed before a response was created for it.
+    pub fn fail(&mut self, request_id: &str) -> Option<OutOfBandTask> {
+        self.requested.remove(request_id)
+    }
+
+    pub fn is_out_of_band(&self, response_id: &str) -> bool {
+        self.running.contains_key(response_id)
+    }
+
+    /// True while a context summary is requested or running, only one at a time.
+    pub fn summarizing_context(&self) -> bool {
+        self.requested
+            .values()
+            .chain(self.running.values())
+            .any(|task| matches!(task, OutOfBandTask::ContextSummary { .. }))
+    }
+
+    /// Collects the text of a finished out-of-band response from its `output` items.
+    pub fn finish(
+        &mut self,
+        response_id: &str,
+        status: Option<String>,
+        output: &[serde_json::Value],
+    ) -> Option<OutOfBandResult> {
+        let task = self.running.remove(response_id)?;
+        let text = output
+            .iter()
+            .filter_map(|item| item["content"].as_array())
+            .flatten()
+            .filter_map(|part| part["text"].as_str().or(part["transcript"].as_str()))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let json = match task {
+            OutOfBandTask::Extraction { ref schema, .. } => Some(
+                parse_json_reply(&text).and_then(|json| validate(&json, schema, "$").map(|_| json)),
+            ),
+            OutOfBandTask::Summary | OutOfBandTask::ContextSummary { .. } => None,
+        };
+
+        Some(OutOfBandResult {
+            task,
+            status,
+            text,
+            json,
+        })
+    }
+
+    pub fn clear(&mut self) {
+        self.requested.clear();
+        self.running.clear();
+    }
+}
+
+// Models sometimes wrap JSON in a markdown code fence despite being told not to
+fn parse_json_reply(text: &str) -> Result<serde_json::Value, String> {
+    let trimmed = text.trim();
+    let unfenced = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.strip_suffix("```"))
+        .unwrap_or(trimmed);
+    serde_json::from_str(unfenced.trim()).map_err(|e| e.to_string())
+}
+
+/// Checks the parts of JSON schema the extraction schemas use: `type`, `required`,
+/// `properties` and `items`. Anything else in the schema is not enforced.
+fn validate(value: &serde_json::Value, schema: &serde_json::Value, path: &str) -> Result<(), String> {
+    let types: Vec<&str> = match &schema["type"] {
+        serde_json::Value::String(name) => vec![name.as_str()],
+        serde_json::Value::Array(names) => names.iter().filter_map(|name| name.as_str()).collect(),
+        _ => Vec::new(),
+    };
+    if !types.is_empty() && !types.iter().any(|name| has_type(value, name)) {
+        return Err(format!("{} should be {}, got {}", path, types.join(" or "), type_name(value)));
+    }
+
+    if let Some(object) = value.as_object() {
+        for key in schema["required"].as_array().into_iter().flatten().filter_map(|key| key.as_str()) {
+            if !object.contains_key(key) {
+                return Err(format!("{} is missing required key '{}'", path, key));
+            }
+        }
+        if let Some(properties) = schema["properties"].as_object() {
+            for (key, property) in properties {
+                if let Some(value) = object.get(key) {
+                    validate(value, property, &format!("{}.{}", path, key))?;
+                }
+            }
+        }
+    }
+    if let (Some(items), Some(item_schema)) = (value.as_array(), schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            validate(item, item_schema, &format!("{}[{}]", path, i))?;
+        }
+    }
+    Ok(())
+}
+
+fn has_type(value: &serde_json::Value, name: &str) -> bool {
+    match name {
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        _ => type_name(value) == name,
+    }
+}
+
+fn type_name(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "boolean",
+        serde_json::Value::Number(_) => "number",
+        serde_json::Value::String(_) => "string",
+        serde_json::Value::Array(_) => "array",
+        serde_json::Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_context_summary_no_longer_blocks_pruning() {
+        let mut responses = OutOfBandResponses::default();
+        let metadata = responses.start(OutOfBandTask::ContextSummary {
+            item_ids: vec!["item_1".to_string()],
+            max_chars: 100,
+        });
+        assert!(responses.summarizing_context());
+
+        let event = serde_json::json!({ "type": "response.create", "response": { "metadata": metadata } });
+        let request_id = request_id(&event).unwrap();
+        assert!(matches!(responses.fail(request_id), Some(OutOfBandTask::ContextSummary { .. })));
+        assert!(!responses.summarizing_context());
+    }
+
+    #[test]
+    fn replies_are_unfenced_before_parsing() {
+        assert_eq!(parse_json_reply("```json\n{\"a\": 1}\n```"), Ok(serde_json::json!({ "a": 1 })));
+        assert_eq!(parse_json_reply("```\n[1]\n```"), Ok(serde_json::json!([1])));
+        assert_eq!(parse_json_reply("  {\"a\": 1}  "), Ok(serde_json::json!({ "a": 1 })));
+        assert!(parse_json_reply("Here you go: {}").is_err());
+    }
+
+    #[test]
+    fn action_items_are_validated_against_the_schema() {
+        let schema = action_items_schema();
+        let valid = serde_json::json!({
+            "action_items": [
+                { "task": "Send the notes", "owner": "Sam", "due": null },
+                { "task": "Book a room" }
+            ]
+        });
+        assert_eq!(validate(&valid, &schema, "$"), Ok(()));
+
+        let missing = serde_json::json!({ "items": [] });
+        assert_eq!(
+            validate(&missing, &schema, "$"),
+            Err("$ is missing required key 'action_items'".to_string())
+        );
+        let not_an_array = serde_json::json!({ "action_items": "none" });
+        assert_eq!(
+            validate(&not_an_array, &schema, "$"),
+            Err("$.action_items should be array, got string".to_string())
+        );
+        let nested_missing = serde_json::json!({ "action_items": [{ "task": "a" }, { "owner": "Sam" }] });
+        assert_eq!(
+            validate(&nested_missing, &schema, "$"),
+            Err("$.action_items[1] is missing required key 'task'".to_string())
+        );
+        let nested_type = serde_json::json!({ "action_items": [{ "task": "a", "due": 3 }] });
+        assert_eq!(
+            validate(&nested_type, &schema, "$"),
+            Err("$.action_items[0].due should be string or null, got number".to_string())
+        );
+        assert_eq!(
+            validate(&serde_json::json!([]), &schema, "$"),
+            Err("$ should be object, got array".to_string())
+        );
+    }
+
+    #[test]
+    fn integers_are_numbers_but_not_the_other_way_round() {
+        let schema = serde_json::json!({ "type": "object", "properties": { "count": { "type": "integer" }, "score": { "type": "number" } } });
+        assert!(validate(&serde_json::json!({ "count": 3, "score": 3 }), &schema, "$").is_ok());
+        assert!(validate(&serde_json::json!({ "count": 3.5 }), &schema, "$").is_err());
+    }
+
+    #[test]
+    fn extraction_results_carry_validation_errors() {
+        let mut responses = OutOfBandResponses::default();
+        let metadata = responses.start(OutOfBandTask::Extraction {
+            name: "action items".to_string(),
+            schema: action_items_schema(),
+        });
+        assert!(responses.response_created("resp_1", Some(&metadata)));
+        let output = [serde_json::json!({ "content": [{ "type": "text", "text": "```json\n{\"action_items\": [{}]}\n```" }] })];
+        let result = responses.finish("resp_1", Some("completed".to_string()), &output).unwrap();
+        assert_eq!(
+            result.json,
+            Some(Err("$.action_items[0] is missing required key 'task'".to_string()))
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use crate::settings::app_data_dir;
+use crate::{log_error, log_warn};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Named personas: the instructions, voice and behaviour the assistant starts a session with.
+// Stored next to the settings so they can be edited by hand as well.
+
+const PERSONAS_FILE_NAME: &str = "personas.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PersonaProfile {
+    pub name: String,
+    /// System prompt, may contain template variables (see `render_template`)
+    pub instructions: String,
+    pub voice: String,
+    pub temperature: f32,
+    /// Tool definitions passed as is in `session.update`
+    pub tools: Vec<serde_json::Value>,
+    /// How the assistant opens the conversation
+    pub greeting: GreetingConfig,
+}
+
+impl Default for PersonaProfile {
+    fn default() -> Self {
+        Self {
+            name: "Assistant".to_string(),
+            instructions: "You are a helpful AI assistant. Respond naturally and conversationally. Always respond in the same language as the user."
+                .to_string(),
+            voice: "alloy".to_string(),
+            temperature: 0.8,
+            tools: vec![],
+            greeting: GreetingConfig::default(),
+        }
+    }
+}
+
+impl PersonaProfile {
+    fn defaults() -> Vec<Self> {
+        vec![
+            Self::default(),
+            Self {
+                name: "Language tutor".to_string(),
+                instructions: "You are a patient language tutor talking with {user_name}. Today is {weekday}, {date}. \
+                    Speak slowly and simply, gently correct mistakes and ask follow-up questions to keep the conversation going."
+                    .to_string(),
+                voice: "coral".to_string(),
+                temperature: 0.7,
+                tools: vec![],
+                greeting: GreetingConfig {
+                    text: "Greet {user_name} briefly and ask which language they would like to practice today.".to_string(),
+                    ..GreetingConfig::default()
+                },
+            },
+        ]
+    }
+
+    /// The profiles on disk, or the built-in ones when there are none yet.
+    pub fn load_all() -> Vec<Self> {
+        let Some(path) = app_data_dir().map(|dir| dir.join(PERSONAS_FILE_NAME)) else {
+            return Self::defaults();
+        };
+
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => match serde_json::from_str::<Vec<Self>>(&contents) {
+                Ok(profiles) if !profiles.is_empty() => profiles,
+                Ok(_) => Self::defaults(),
+                Err(e) => {
+                    log_warn!(Ui, "Ignoring invalid personas file {}: {}", path.display(), e);
+                    Self::defaults()
+                }
+            },
+            Err(_) => Self::defaults(),
+        }
+    }
+
+    pub fn save_all(profiles: &[Self]) {
+        let Some(dir) = app_data_dir() else {
+            log_warn!(Ui, "No home directory, personas won't be saved");
+            return;
+        };
+
+        let result = std::fs::create_dir_all(&dir).and_then(|_| {
+            let contents = serde_json::to_string_pretty(profiles).map_err(std::io::Error::other)?;
+            std::fs::write(dir.join(PERSONAS_FILE_NAME), contents)
+        });
+        if let Err(e) = result {
+            log_error!(Ui, "Failed to save personas: {}", e);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum GreetingMode {
+    /// Wait for the user to speak first
+    Off,
+    /// The assistant says `text` word for word
+    Fixed,
+    /// The model writes its own greeting following `text`
+    #[default]
+    Generated,
+}
+
+impl GreetingMode {
+    /// In the order of `greeting_selector`
+    pub const ALL: [GreetingMode; 3] = [GreetingMode::Off, GreetingMode::Fixed, GreetingMode::Generated];
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum GreetingTiming {
+    /// As soon as the session is configured
+    #[default]
+    OnConnect,
+    /// Only if the user stays silent for `silence_secs` after connecting
+    AfterSilence,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct GreetingConfig {
+    pub mode: GreetingMode,
+    /// The line to say, or instructions for a generated greeting, may contain template variables
+    pub text: String,
+    /// Language to greet in, None leaves it to the model
+    pub language: Option<String>,
+    pub timing: GreetingTiming,
+    pub silence_secs: f64,
+}
+
+impl Default for GreetingConfig {
+    fn default() -> Self {
+        Self {
+            mode: GreetingMode::default(),
+            text: "Start with a very short but enthusiastic and playful greeting, the greeting must not exceed 3 words."
+                .to_string(),
+            language: None,
+            timing: GreetingTiming::default(),
+            silence_secs: 5.0,
+        }
+    }
+}
+
+impl GreetingConfig {
+    /// Instructions for the greeting response, None when the greeting is off.
+    pub fn instructions(&self, vars: &TemplateVars) -> Option<String> {
+        let text = render_template(&self.text, vars);
+        let mut instructions = match self.mode {
+            GreetingMode::Off => return None,
+            GreetingMode::Fixed => format!(
+                "Say exactly the following, word for word, and nothing else: \"{}\"",
+                text
+            ),
+            GreetingMode::Generated => text,
+        };
+        if let Some(language) = &self.language {
+            instructions.push_str(&format!(" Greet in {}.", language));
+        }
+        Some(instructions)
+    }
+}
+
+/// Values for the template variables, taken when a session is configured.
+pub struct TemplateVars {
+    pub user_name: String,
+    /// Seconds since the unix epoch, dates and times are rendered in UTC
+    pub now: u64,
+}
+
+impl TemplateVars {
+    pub fn new(user_name: Option<&str>) -> Self {
+        let user_name = user_name
+            .map(str::to_string)
+            .or_else(|| std::env::var("USER").ok())
+            .or_else(|| std::env::var("USERNAME").ok())
+            .unwrap_or_else(|| "there".to_string());
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        Self { user_name, now }
+    }
+}
+
+/// Replaces `{date}`, `{time}`, `{weekday}` and `{user_name}`, anything else is left as is.
+pub fn render_template(template: &str, vars: &TemplateVars) -> String {
+    const WEEKDAYS: [&str; 7] = [
+        "Thursday", "Friday", "Saturday", "Sunday", "Monday", "Tuesday", "Wednesday",
+    ];
+
+    let days = vars.now / 86_400;
+    let seconds_of_day = vars.now % 86_400;
+    let (year, month, day) = civil_from_days(days as i64);
+
+    template
+        .replace("{date}", &format!("{:04}-{:02}-{:02}", year, month, day))
+        .replace(
+            "{time}",
+            &format!("{:02}:{:02} UTC", seconds_of_day / 3600, seconds_of_day % 3600 / 60),
+        )
+        .replace("{weekday}", WEEKDAYS[(days % 7) as usize])
+        .replace("{user_name}", &vars.user_name)
+}
+
+// Days since 1970-01-01 to a proleptic Gregorian (year, month, day), after Howard Hinnant
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
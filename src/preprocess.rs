@@ -0,0 +1,346 @@
+use crate::audio::{NoiseFloor, OPENAI_SAMPLE_RATE, amplitude_to_db};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+// Local cleanup of the captured 24kHz stream before it is analysed or uploaded:
+// a high-pass filter (which also removes DC offset), noise suppression and automatic
+// gain control. Clipping is detected earlier, on the raw device signal (see `InputLevel`).
+//
+// Noise suppression is a downward expander rather than spectral subtraction: steady
+// background noise is attenuated between words, not underneath them. That is what keeps
+// the server VAD and transcription from picking up fans and hum while nobody talks.
+
+const FRAME_MS: usize = 10;
+const FRAME_SAMPLES: usize = OPENAI_SAMPLE_RATE * FRAME_MS / 1000;
+
+// How fast the suppression closes after speech, it opens immediately (dB per frame)
+const SUPPRESSION_RELEASE_DB: f32 = 1.0;
+
+// Fraction of the remaining gain error corrected per speech frame. Turning the gain down
+// is fast so loud speech doesn't clip for long, turning it up is slow so it doesn't pump.
+const AGC_ATTACK: f32 = 0.3;
+const AGC_RELEASE: f32 = 0.02;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerNoiseReduction {
+    /// Near field for headsets, far field for everything else, from the input device name
+    #[default]
+    Auto,
+    NearField,
+    FarField,
+    Off,
+}
+
+impl ServerNoiseReduction {
+    /// The `input_audio_noise_reduction` type to request, None to turn it off.
+    pub fn resolve(&self, input_device: Option<&str>) -> Option<&'static str> {
+        // Mics that sit close to the mouth, anything else is assumed to be laptop or room mics
+        const NEAR_FIELD_HINTS: [&str; 7] = [
+            "headset", "headphone", "airpods", "earbuds", "buds", "hands-free", "handsfree",
+        ];
+
+        match self {
+            ServerNoiseReduction::Auto => {
+                let name = input_device.unwrap_or_default().to_lowercase();
+                if NEAR_FIELD_HINTS.iter().any(|hint| name.contains(hint)) {
+                    Some("near_field")
+                } else {
+                    Some("far_field")
+                }
+            }
+            ServerNoiseReduction::NearField => Some("near_field"),
+            ServerNoiseReduction::FarField => Some("far_field"),
+            ServerNoiseReduction::Off => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct NoiseSuppressionConfig {
+    pub enabled: bool,
+    /// How much quieter background noise gets between words
+    pub reduction_db: f32,
+    /// Frames quieter than this are never speech
+    pub threshold_db: f32,
+    /// How far above the noise floor a frame has to be to count as speech
+    pub margin_db: f32,
+}
+
+impl Default for NoiseSuppressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            reduction_db: 12.0,
+            threshold_db: -50.0,
+            margin_db: 10.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct AgcConfig {
+    pub enabled: bool,
+    /// Speech level to aim for
+    pub target_db: f32,
+    /// Upper bound for the gain, so quiet rooms don't get their noise amplified
+    pub max_gain_db: f32,
+}
+
+impl Default for AgcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target_db: -20.0,
+            max_gain_db: 20.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct PreprocessConfig {
+    /// Cutoff of the high-pass filter in Hz, None disables it
+    pub high_pass_hz: Option<f32>,
+    pub noise_suppression: NoiseSuppressionConfig,
+    pub agc: AgcConfig,
+    /// Server-side `input_audio_noise_reduction`
+    pub server_noise_reduction: ServerNoiseReduction,
+}
+
+impl Default for PreprocessConfig {
+    fn default() -> Self {
+        Self {
+            high_pass_hz: Some(80.0),
+            noise_suppression: NoiseSuppressionConfig::default(),
+            agc: AgcConfig::default(),
+            server_noise_reduction: ServerNoiseReduction::default(),
+        }
+    }
+}
+
+/// Runs the chain in place. Lives in the audio input callback.
+pub struct Preprocessor {
+    high_pass: Biquad,
+    high_pass_hz: Option<f32>,
+    noise_floor: NoiseFloor,
+    suppression_db: f32,
+    agc_gain_db: f32,
+    /// Gain applied at the end of the previous frame, ramped from to avoid zipper noise
+    gain: f32,
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Self {
+            high_pass: Biquad::default(),
+            high_pass_hz: None,
+            noise_floor: NoiseFloor::new(FRAME_MS as u32),
+            suppression_db: 0.0,
+            agc_gain_db: 0.0,
+            gain: 1.0,
+        }
+    }
+}
+
+impl Preprocessor {
+    pub fn process(&mut self, config: &PreprocessConfig, samples: &mut [f32]) {
+        if let Some(cutoff_hz) = config.high_pass_hz {
+            if self.high_pass_hz != Some(cutoff_hz) {
+                self.high_pass = Biquad::high_pass(cutoff_hz);
+                self.high_pass_hz = Some(cutoff_hz);
+            }
+            for sample in samples.iter_mut() {
+                *sample = self.high_pass.process(*sample);
+            }
+        }
+
+        let suppression = &config.noise_suppression;
+        let agc = &config.agc;
+        if !suppression.enabled && !agc.enabled {
+            return;
+        }
+
+        for frame in samples.chunks_mut(FRAME_SAMPLES) {
+            let sum_squares: f32 = frame.iter().map(|sample| sample * sample).sum();
+            let level_db = amplitude_to_db((sum_squares / frame.len() as f32).sqrt());
+            let noise_floor_db = self.noise_floor.db();
+            self.noise_floor.update(level_db);
+            let is_speech = level_db
+                > suppression
+                    .threshold_db
+                    .max(noise_floor_db + suppression.margin_db);
+
+            self.suppression_db = if !suppression.enabled || is_speech {
+                0.0
+            } else {
+                (self.suppression_db - SUPPRESSION_RELEASE_DB).max(-suppression.reduction_db)
+            };
+
+            if !agc.enabled {
+                self.agc_gain_db = 0.0;
+            } else if is_speech {
+                let wanted_db = (agc.target_db - level_db).min(agc.max_gain_db);
+                let rate = if wanted_db < self.agc_gain_db {
+                    AGC_ATTACK
+                } else {
+                    AGC_RELEASE
+                };
+                self.agc_gain_db += (wanted_db - self.agc_gain_db) * rate;
+            }
+
+            let target_gain = 10f32.powf((self.suppression_db + self.agc_gain_db) / 20.0);
+            let step = (target_gain - self.gain) / frame.len() as f32;
+            for sample in frame.iter_mut() {
+                self.gain += step;
+                *sample = (*sample * self.gain).clamp(-1.0, 1.0);
+            }
+        }
+    }
+}
+
+/// Second-order IIR section (transposed direct form II).
+#[derive(Default)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    /// Butterworth high-pass, coefficients from the Audio EQ Cookbook
+    fn high_pass(cutoff_hz: f32) -> Self {
+        let w0 = 2.0 * PI * cutoff_hz / OPENAI_SAMPLE_RATE as f32;
+        let alpha = w0.sin() / (2.0 * std::f32::consts::FRAC_1_SQRT_2);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha;
+        Self {
+            b0: (1.0 + cos) / 2.0 / a0,
+            b1: -(1.0 + cos) / a0,
+            b2: (1.0 + cos) / 2.0 / a0,
+            a1: -2.0 * cos / a0,
+            a2: (1.0 - alpha) / a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let output = self.b0 * input + self.z1;
+        self.z1 = self.b1 * input - self.a1 * output + self.z2;
+        self.z2 = self.b2 * input - self.a2 * output;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(frequency_hz: f32, amplitude: f32, ms: usize) -> Vec<f32> {
+        (0..OPENAI_SAMPLE_RATE * ms / 1000)
+            .map(|i| {
+                amplitude * (i as f32 * frequency_hz * 2.0 * PI / OPENAI_SAMPLE_RATE as f32).sin()
+            })
+            .collect()
+    }
+
+    fn level_db(samples: &[f32]) -> f32 {
+        let sum_squares: f32 = samples.iter().map(|sample| sample * sample).sum();
+        amplitude_to_db((sum_squares / samples.len() as f32).sqrt())
+    }
+
+    /// Level change of the last `tail_ms` of a tone run through the chain
+    fn gain_db(
+        config: &PreprocessConfig,
+        frequency_hz: f32,
+        amplitude: f32,
+        ms: usize,
+        tail_ms: usize,
+    ) -> f32 {
+        let mut preprocessor = Preprocessor::default();
+        let input = tone(frequency_hz, amplitude, ms);
+        let mut output = input.clone();
+        preprocessor.process(config, &mut output);
+        let tail = input.len() - OPENAI_SAMPLE_RATE * tail_ms / 1000;
+        level_db(&output[tail..]) - level_db(&input[tail..])
+    }
+
+    #[test]
+    fn high_pass_removes_rumble_and_dc() {
+        let config = PreprocessConfig::default();
+        assert!(gain_db(&config, 1000.0, 0.5, 1000, 500).abs() < 0.1);
+        // Two octaves below the 80Hz cutoff, a second order filter takes off 24dB
+        assert!(gain_db(&config, 20.0, 0.5, 1000, 500) < -20.0);
+
+        let mut preprocessor = Preprocessor::default();
+        let mut dc = vec![0.5; OPENAI_SAMPLE_RATE];
+        preprocessor.process(&config, &mut dc);
+        assert!(dc[dc.len() - 1].abs() < 1.0e-3);
+    }
+
+    #[test]
+    fn expander_attenuates_steady_noise_but_not_speech() {
+        let mut config = PreprocessConfig::default();
+        config.noise_suppression.enabled = true;
+
+        // A steady tone above the absolute threshold is learned as noise and turned down
+        let mut preprocessor = Preprocessor::default();
+        let mut samples = tone(1000.0, 0.01, 6000);
+        preprocessor.process(&config, &mut samples);
+        let hum_gain =
+            level_db(&samples[samples.len() - 4800..]) - level_db(&tone(1000.0, 0.01, 200));
+        assert!(
+            (hum_gain + config.noise_suppression.reduction_db).abs() < 0.1,
+            "{}",
+            hum_gain
+        );
+
+        // Speech over it opens the expander within a frame
+        let speech = tone(1000.0, 0.1, 200);
+        let mut samples = speech.clone();
+        preprocessor.process(&config, &mut samples);
+        assert!(
+            (level_db(&samples[FRAME_SAMPLES..]) - level_db(&speech[FRAME_SAMPLES..])).abs() < 0.1
+        );
+
+        // Quiet noise after digital silence is below the absolute threshold, not speech
+        let mut preprocessor = Preprocessor::default();
+        let mut samples = vec![0.0; OPENAI_SAMPLE_RATE];
+        samples.extend(tone(1000.0, 0.0005, 1000));
+        preprocessor.process(&config, &mut samples);
+        let noise_gain =
+            level_db(&samples[samples.len() - 4800..]) - level_db(&tone(1000.0, 0.0005, 200));
+        assert!(
+            (noise_gain + config.noise_suppression.reduction_db).abs() < 0.1,
+            "{}",
+            noise_gain
+        );
+    }
+
+    #[test]
+    fn agc_reaches_the_target_within_its_gain_limit() {
+        let mut config = PreprocessConfig::default();
+        config.agc.enabled = true;
+
+        // Loud speech is turned down to the target quickly
+        let loud = gain_db(&config, 1000.0, 0.9, 1000, 200);
+        let loud_level = level_db(&tone(1000.0, 0.9, 200));
+        assert!(
+            (loud_level + loud - config.agc.target_db).abs() < 0.5,
+            "{}",
+            loud
+        );
+
+        // Quiet speech would need 23dB but gets no more than the maximum
+        let quiet = gain_db(&config, 1000.0, 0.01, 2500, 200);
+        assert!(quiet <= config.agc.max_gain_db + 0.01, "{}", quiet);
+        assert!(quiet > config.agc.max_gain_db - 0.5, "{}", quiet);
+    }
+}
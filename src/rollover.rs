@@ -0,0 +1,58 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Session rollover.
+//
+// The server closes a Realtime session once it reaches its maximum duration. Shortly
+// before that a standby session is opened, configured like the current one and seeded
+// with a compacted copy of the conversation, then swapped in between two turns.
+
+// Used when `session.created` doesn't say when the session expires
+pub const DEFAULT_MAX_SESSION_DURATION: Duration = Duration::from_secs(30 * 60);
+
+// How long before expiry the standby session is opened
+pub const ROLLOVER_LEAD_TIME: Duration = Duration::from_secs(120);
+
+// Wait this long before opening another standby session after one failed
+pub const ROLLOVER_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+// Items carried over verbatim, anything older is folded into a summary item
+pub const ROLLOVER_HISTORY_ITEMS: usize = 20;
+pub const ROLLOVER_SUMMARY_CHARS: usize = 4000;
+
+pub struct SessionLifetime {
+    created_at: Instant,
+    expires_at: Instant,
+}
+
+impl SessionLifetime {
+    /// Reads `expires_at` (unix seconds) from the `session.created` payload.
+    pub fn from_session(session: &serde_json::Value) -> Self {
+        let created_at = Instant::now();
+        let now_unix = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs())
+            .unwrap_or(0);
+        let remaining = session["expires_at"]
+            .as_u64()
+            .filter(|expires_at| *expires_at > now_unix)
+            .map(|expires_at| Duration::from_secs(expires_at - now_unix))
+            .unwrap_or(DEFAULT_MAX_SESSION_DURATION);
+
+        Self {
+            created_at,
+            expires_at: created_at + remaining,
+        }
+    }
+
+    pub fn age(&self) -> Duration {
+        self.created_at.elapsed()
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.expires_at.saturating_duration_since(Instant::now())
+    }
+
+    pub fn rollover_due(&self) -> bool {
+        self.remaining() <= ROLLOVER_LEAD_TIME
+    }
+}
@@ -0,0 +1,145 @@
+use crate::audio::{ChannelMix, DEFAULT_PLAYBACK_LATENCY_MS};
+use crate::codec::AudioFormat;
+use crate::conversation::ContextPolicy;
+use crate::errors::RecoveryPolicies;
+use crate::logging::LogConfig;
+use crate::preprocess::PreprocessConfig;
+use crate::relay::RelayConfig;
+use crate::telephony::TelephonyConfig;
+use crate::token_service::{RealtimeAuth, TokenServiceConfig};
+use crate::usage::{BudgetLimits, PriceTable};
+use crate::vad::VadConfig;
+use crate::wake_word::WakeWordConfig;
+use crate::{log_error, log_warn};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// User preferences that survive restarts, stored as JSON under the user's home directory.
+
+const APP_DIR_NAME: &str = ".makepad-realtime";
+const SETTINGS_FILE_NAME: &str = "settings.json";
+const EXPORTS_DIR_NAME: &str = "exports";
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct Settings {
+    /// Name of the preferred input device, None follows the system default
+    pub input_device: Option<String>,
+    /// Name of the preferred output device, None follows the system default
+    pub output_device: Option<String>,
+    /// How multichannel input devices are mixed down before streaming
+    pub input_channel_mix: ChannelMix,
+    /// How much assistant audio is buffered before playback starts
+    pub playback_latency_ms: usize,
+    /// Wire format of new sessions, G.711 for telephony
+    pub audio_format: AudioFormat,
+    /// Used to turn token usage into cost
+    pub prices: PriceTable,
+    /// Session time, token and spend limits
+    pub budget: BudgetLimits,
+    /// Log verbosity per subsystem and redaction
+    pub logging: LogConfig,
+    /// What to do for each class of server error
+    pub error_recovery: RecoveryPolicies,
+    /// When to prune the conversation to stay within the context window
+    pub context: ContextPolicy,
+    /// Name of the persona profile to start sessions with
+    pub persona: Option<String>,
+    /// Fills `{user_name}` in persona templates, defaults to the OS user name
+    pub user_name: Option<String>,
+    /// Client-side voice activity detection
+    pub vad: VadConfig,
+    /// Local cleanup of the captured audio, and the server-side noise reduction
+    pub preprocessing: PreprocessConfig,
+    /// SIP/RTP bridge that answers phone calls
+    pub telephony: TelephonyConfig,
+    /// WebSocket relay that lets other clients use the API without the key
+    pub relay: RelayConfig,
+    /// How to authenticate to the Realtime API
+    pub auth: RealtimeAuth,
+    /// HTTP endpoint that hands out client secrets, locked to the relay policy
+    pub token_service: TokenServiceConfig,
+    /// Hands-free activation
+    pub wake_word: WakeWordConfig,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            input_device: None,
+            output_device: None,
+            input_channel_mix: ChannelMix::default(),
+            playback_latency_ms: DEFAULT_PLAYBACK_LATENCY_MS,
+            audio_format: AudioFormat::default(),
+            prices: PriceTable::default(),
+            budget: BudgetLimits::default(),
+            logging: LogConfig::default(),
+            error_recovery: RecoveryPolicies::default(),
+            context: ContextPolicy::default(),
+            persona: None,
+            user_name: None,
+            vad: VadConfig::default(),
+            preprocessing: PreprocessConfig::default(),
+            telephony: TelephonyConfig::default(),
+            relay: RelayConfig::default(),
+            auth: RealtimeAuth::default(),
+            token_service: TokenServiceConfig::default(),
+            wake_word: WakeWordConfig::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        let Some(path) = app_data_dir().map(|dir| dir.join(SETTINGS_FILE_NAME)) else {
+            return Self::default();
+        };
+
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                log_warn!(Ui, "Ignoring invalid settings file {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        let Some(dir) = app_data_dir() else {
+            log_warn!(Ui, "No home directory, settings won't be saved");
+            return;
+        };
+
+        let result = std::fs::create_dir_all(&dir).and_then(|_| {
+            let contents = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+            std::fs::write(dir.join(SETTINGS_FILE_NAME), contents)
+        });
+        if let Err(e) = result {
+            log_error!(Ui, "Failed to save settings: {}", e);
+        }
+    }
+}
+
+/// Directory where the app keeps its settings and other local data.
+pub fn app_data_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(APP_DIR_NAME))
+}
+
+/// Write a report to the exports directory as `<prefix>-<unix time>.<extension>`.
+pub fn write_export(prefix: &str, extension: &str, contents: &str) -> std::io::Result<PathBuf> {
+    let dir = app_data_dir()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no home directory"))?
+        .join(EXPORTS_DIR_NAME);
+    std::fs::create_dir_all(&dir)?;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let path = dir.join(format!("{}-{}.{}", prefix, timestamp, extension));
+    std::fs::write(&path, contents)?;
+    Ok(path)
+}
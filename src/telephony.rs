@@ -0,0 +1,940 @@
+use crate::audio::{InputLevel, PlaybackBuffer};
+use crate::codec::{AudioCodec, AudioFormat};
+use crate::{log_debug, log_error, log_info, log_warn};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Telephony bridge: puts the assistant on a phone line. A background thread answers SIP
+// calls over UDP and, while a call is up, takes the place of the device callbacks: RTP
+// audio from the caller goes into `recorded_audio` and the playback buffer is sent back
+// as RTP. Everything downstream (VAD, wake word, session codec) works as with a mic.
+//
+// The SIP side is deliberately minimal: one call at a time, INVITE with an SDP offer
+// (PCMU or PCMA), ACK, BYE, CANCEL treated as BYE, OPTIONS for health checks. No
+// registration, authentication, retransmissions or re-negotiation of the codec.
+
+const PACKET_MS: usize = 20;
+const G711_SAMPLE_RATE: usize = 8000;
+const PACKET_SAMPLES: usize = G711_SAMPLE_RATE * PACKET_MS / 1000;
+// `PlaybackBuffer::render` produces device rate (48kHz) samples
+const RENDER_SAMPLES: usize = 48000 * PACKET_MS / 1000;
+const PACKET_INTERVAL: Duration = Duration::from_millis(PACKET_MS as u64);
+// Sending stops catching up when it falls this far behind, a burst would only add latency
+const MAX_SEND_LAG: Duration = Duration::from_millis(100);
+
+pub const PAYLOAD_TYPE_PCMU: u8 = 0;
+pub const PAYLOAD_TYPE_PCMA: u8 = 8;
+
+const RTP_VERSION: u8 = 2;
+const RTP_HEADER_LEN: usize = 12;
+const MAX_DATAGRAM_LEN: usize = 8192;
+
+const USER_AGENT: &str = "makepad-realtime";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TelephonyConfig {
+    /// Answer calls instead of waiting for the connect button
+    pub enabled: bool,
+    /// Local address SIP and RTP listen on
+    pub bind_address: String,
+    pub sip_port: u16,
+    pub rtp_port: u16,
+    /// Address put in SDP answers, None uses the interface that faces the caller
+    pub advertised_address: Option<String>,
+    /// Source IPs or From URIs (`sip:alice@example.com`) that may call, empty lets
+    /// everyone who can reach `bind_address` in. From headers are easily forged,
+    /// prefer addresses when the bridge is reachable from outside.
+    pub allowed_callers: Vec<String>,
+}
+
+impl Default for TelephonyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_address: "127.0.0.1".to_string(),
+            sip_port: 5060,
+            rtp_port: 40000,
+            advertised_address: None,
+            allowed_callers: Vec::new(),
+        }
+    }
+}
+
+/// The audio state shared with the device callbacks, which the bridge reads and writes instead.
+#[derive(Clone)]
+pub struct BridgeAudio {
+    pub recorded_audio: Arc<Mutex<Vec<f32>>>,
+    pub is_recording: Arc<Mutex<bool>>,
+    pub is_muted: Arc<Mutex<bool>>,
+    pub input_level: Arc<Mutex<InputLevel>>,
+    pub playback: Arc<Mutex<PlaybackBuffer>>,
+    /// True while a call is up, the device callbacks stand down
+    pub active: Arc<Mutex<bool>>,
+}
+
+#[derive(Debug)]
+pub enum BridgeEvent {
+    CallStarted { caller: String, format: AudioFormat },
+    CallEnded { reason: &'static str },
+}
+
+enum BridgeCommand {
+    HangUp,
+    Stop,
+}
+
+/// Handle to the bridge thread, which stops (hanging up any call) when this is dropped.
+pub struct TelephonyBridge {
+    events: Receiver<BridgeEvent>,
+    commands: Sender<BridgeCommand>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TelephonyBridge {
+    pub fn start(config: &TelephonyConfig, audio: BridgeAudio) -> std::io::Result<Self> {
+        let bind_address: IpAddr = config
+            .bind_address
+            .parse()
+            .map_err(std::io::Error::other)?;
+        let advertised_address = match &config.advertised_address {
+            Some(address) => Some(address.parse().map_err(std::io::Error::other)?),
+            None => None,
+        };
+
+        let sip = UdpSocket::bind((bind_address, config.sip_port))?;
+        sip.set_nonblocking(true)?;
+        let rtp = UdpSocket::bind((bind_address, config.rtp_port))?;
+        log_info!(
+            Transport,
+            "Telephony bridge listening for SIP on {} and RTP on {}",
+            sip.local_addr()?,
+            rtp.local_addr()?
+        );
+
+        let (event_sender, events) = mpsc::channel();
+        let (commands, command_receiver) = mpsc::channel();
+        let mut worker = BridgeWorker {
+            sip,
+            rtp,
+            sip_port: config.sip_port,
+            rtp_port: config.rtp_port,
+            advertised_address,
+            allowed_callers: config.allowed_callers.clone(),
+            audio,
+            events: event_sender,
+            call: None,
+        };
+        let thread = std::thread::Builder::new()
+            .name("telephony-bridge".to_string())
+            .spawn(move || worker.run(command_receiver))?;
+
+        Ok(Self {
+            events,
+            commands,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn poll_events(&self) -> Vec<BridgeEvent> {
+        self.events.try_iter().collect()
+    }
+
+    /// Ends the current call from our side, if there is one.
+    pub fn hang_up(&self) {
+        let _ = self.commands.send(BridgeCommand::HangUp);
+    }
+}
+
+impl Drop for TelephonyBridge {
+    fn drop(&mut self) {
+        let _ = self.commands.send(BridgeCommand::Stop);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct Call {
+    dialog: Dialog,
+    remote_rtp: SocketAddr,
+    payload_type: u8,
+    /// Kept to answer INVITE retransmissions the same way
+    answer: String,
+    codec: AudioCodec,
+    ssrc: u32,
+    sequence: u16,
+    timestamp: u32,
+    last_received_sequence: Option<u16>,
+    next_send: Instant,
+}
+
+/// What is needed to send BYE for a call we answered.
+struct Dialog {
+    peer: SocketAddr,
+    call_id: String,
+    /// The caller's From, our To
+    remote: String,
+    /// The caller's To with our tag, our From
+    local: String,
+    /// Where in-dialog requests go, the caller's Contact
+    remote_target: String,
+    local_address: IpAddr,
+}
+
+struct BridgeWorker {
+    sip: UdpSocket,
+    rtp: UdpSocket,
+    sip_port: u16,
+    rtp_port: u16,
+    advertised_address: Option<IpAddr>,
+    allowed_callers: Vec<String>,
+    audio: BridgeAudio,
+    events: Sender<BridgeEvent>,
+    call: Option<Call>,
+}
+
+impl BridgeWorker {
+    fn run(&mut self, commands: Receiver<BridgeCommand>) {
+        let mut buffer = vec![0u8; MAX_DATAGRAM_LEN];
+        loop {
+            for command in commands.try_iter() {
+                match command {
+                    BridgeCommand::HangUp => self.hang_up("hung up"),
+                    BridgeCommand::Stop => {
+                        self.hang_up("bridge stopped");
+                        return;
+                    }
+                }
+            }
+
+            loop {
+                match self.sip.recv_from(&mut buffer) {
+                    Ok((len, from)) => match SipMessage::parse(&buffer[..len]) {
+                        Some(message) => self.handle_sip(message, from),
+                        None => log_debug!(Transport, "Ignoring malformed SIP datagram from {}", from),
+                    },
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                    Err(e) => {
+                        log_error!(Transport, "SIP socket error: {}", e);
+                        break;
+                    }
+                }
+            }
+
+            self.send_due_packets();
+
+            // Wait for RTP until the next packet is due, or a short while between calls
+            let wait = match &self.call {
+                Some(call) => call
+                    .next_send
+                    .saturating_duration_since(Instant::now())
+                    .max(Duration::from_millis(1)),
+                None => PACKET_INTERVAL,
+            };
+            let _ = self.rtp.set_read_timeout(Some(wait));
+            match self.rtp.recv_from(&mut buffer) {
+                Ok((len, from)) => self.handle_rtp(&buffer[..len], from),
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) => {}
+                Err(e) => {
+                    log_warn!(Transport, "RTP socket error: {}", e);
+                    std::thread::sleep(PACKET_INTERVAL);
+                }
+            }
+        }
+    }
+
+    fn handle_sip(&mut self, message: SipMessage, from: SocketAddr) {
+        let Some(method) = message.method().map(str::to_string) else {
+            // Responses to our BYE, nothing to do
+            log_debug!(Transport, "SIP response from {}: {}", from, message.start_line);
+            return;
+        };
+        log_debug!(Transport, "SIP {} from {}", method, from);
+
+        let same_call = self
+            .call
+            .as_ref()
+            .is_some_and(|call| Some(call.dialog.call_id.as_str()) == message.header("Call-ID"));
+        match method.as_str() {
+            "INVITE" => self.handle_invite(message, from, same_call),
+            "ACK" => {}
+            "BYE" | "CANCEL" => {
+                self.send_sip(&message.response(200, "OK"), from);
+                if same_call {
+                    self.end_call("caller hung up");
+                }
+            }
+            "OPTIONS" => {
+                let response = message
+                    .response(200, "OK")
+                    .with_header("Allow", "INVITE, ACK, BYE, CANCEL, OPTIONS");
+                self.send_sip(&response, from);
+            }
+            _ => self.send_sip(&message.response(501, "Not Implemented"), from),
+        }
+    }
+
+    fn handle_invite(&mut self, message: SipMessage, from: SocketAddr, same_call: bool) {
+        if self.call.is_some() && !same_call {
+            self.send_sip(&message.response(486, "Busy Here"), from);
+            return;
+        }
+        if !self.accepts_caller(&message, from) {
+            log_warn!(Transport, "Rejecting call from {}, not an allowed caller", from);
+            self.send_sip(&message.response(403, "Forbidden"), from);
+            return;
+        }
+
+        let offer = SdpAudio::parse(&message.body);
+        let payload_type = offer.as_ref().and_then(|offer| {
+            offer
+                .payload_types
+                .iter()
+                .copied()
+                .find(|&payload_type| payload_format(payload_type).is_some())
+        });
+        let (Some(offer), Some(payload_type)) = (offer, payload_type) else {
+            log_warn!(Transport, "Rejecting call from {}, no PCMU/PCMA offer", from);
+            self.send_sip(&message.response(488, "Not Acceptable Here"), from);
+            return;
+        };
+        let remote_rtp = SocketAddr::new(offer.address, offer.port);
+
+        // Retransmitted INVITE or re-INVITE, answer like the first time
+        if let Some(call) = self.call.as_mut().filter(|_| same_call) {
+            call.remote_rtp = remote_rtp;
+            let response = message
+                .response(200, "OK")
+                .with_to(&call.dialog.local)
+                .with_contact(call.dialog.local_address, self.sip_port)
+                .with_body("application/sdp", &call.answer);
+            self.send_sip(&response, from);
+            return;
+        }
+
+        let local_address = self
+            .advertised_address
+            .or_else(|| local_address_towards(from))
+            .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let local_tag = format!("{:08x}", random_u32());
+        let local = match message.header("To") {
+            Some(to) => format!("{};tag={}", to, local_tag),
+            None => format!("<sip:{}@{}>;tag={}", USER_AGENT, local_address, local_tag),
+        };
+        let dialog = Dialog {
+            peer: from,
+            call_id: message.header("Call-ID").unwrap_or_default().to_string(),
+            remote: message.header("From").unwrap_or_default().to_string(),
+            local,
+            remote_target: message
+                .header("Contact")
+                .and_then(uri_of)
+                .unwrap_or_else(|| format!("sip:{}", from)),
+            local_address,
+        };
+        let answer = sdp(local_address, self.rtp_port, &[payload_type]);
+        let response = message
+            .response(200, "OK")
+            .with_to(&dialog.local)
+            .with_contact(local_address, self.sip_port)
+            .with_body("application/sdp", &answer);
+        self.send_sip(&response, from);
+
+        let format = payload_format(payload_type).unwrap_or_default();
+        let caller = uri_of(&dialog.remote).unwrap_or_else(|| from.to_string());
+        log_info!(
+            Transport,
+            "Answered call from {} ({}), sending RTP to {}",
+            caller,
+            format.as_str(),
+            remote_rtp
+        );
+        self.call = Some(Call {
+            dialog,
+            remote_rtp,
+            payload_type,
+            answer,
+            codec: AudioCodec::new(format),
+            ssrc: random_u32(),
+            sequence: random_u32() as u16,
+            timestamp: random_u32(),
+            last_received_sequence: None,
+            next_send: Instant::now(),
+        });
+        self.audio.playback.lock().unwrap().clear();
+        *self.audio.active.lock().unwrap() = true;
+        let _ = self.events.send(BridgeEvent::CallStarted { caller, format });
+    }
+
+    fn accepts_caller(&self, message: &SipMessage, from: SocketAddr) -> bool {
+        if self.allowed_callers.is_empty() {
+            return true;
+        }
+        let address = from.ip().to_string();
+        let uri = message.header("From").and_then(uri_of);
+        self.allowed_callers
+            .iter()
+            .any(|allowed| *allowed == address || uri.as_deref() == Some(allowed.as_str()))
+    }
+
+    fn hang_up(&mut self, reason: &'static str) {
+        let Some(call) = &self.call else {
+            return;
+        };
+        let dialog = &call.dialog;
+        let bye = SipMessage::request("BYE", &dialog.remote_target)
+            .with_header(
+                "Via",
+                &format!(
+                    "SIP/2.0/UDP {};branch=z9hG4bK{:08x}",
+                    SocketAddr::new(dialog.local_address, self.sip_port),
+                    random_u32()
+                ),
+            )
+            .with_header("Max-Forwards", "70")
+            .with_header("From", &dialog.local)
+            .with_header("To", &dialog.remote)
+            .with_header("Call-ID", &dialog.call_id)
+            .with_header("CSeq", "1 BYE");
+        self.send_sip(&bye, dialog.peer);
+        self.end_call(reason);
+    }
+
+    fn end_call(&mut self, reason: &'static str) {
+        if self.call.take().is_none() {
+            return;
+        }
+        log_info!(Transport, "Call ended: {}", reason);
+        *self.audio.active.lock().unwrap() = false;
+        self.audio.playback.lock().unwrap().clear();
+        let _ = self.events.send(BridgeEvent::CallEnded { reason });
+    }
+
+    fn handle_rtp(&mut self, datagram: &[u8], from: SocketAddr) {
+        let Some(call) = self.call.as_mut() else {
+            return;
+        };
+        // Anyone can reach the RTP port, only take audio from the caller's side of the call
+        if from.ip() != call.remote_rtp.ip() && from.ip() != call.dialog.peer.ip() {
+            log_debug!(Transport, "Ignoring RTP from {}, not part of the call", from);
+            return;
+        }
+        let Some(packet) = RtpPacket::parse(datagram) else {
+            log_debug!(Transport, "Ignoring malformed RTP packet from {}", from);
+            return;
+        };
+        // DTMF events and comfort noise have their own payload types
+        if packet.payload_type != call.payload_type {
+            return;
+        }
+        // Late or duplicated, there is no reordering buffer
+        if call
+            .last_received_sequence
+            .is_some_and(|last| (packet.sequence.wrapping_sub(last) as i16) <= 0)
+        {
+            return;
+        }
+        call.last_received_sequence = Some(packet.sequence);
+
+        let samples = call.codec.decode(packet.payload);
+        self.audio.input_level.lock().unwrap().update(&samples);
+        if *self.audio.is_muted.lock().unwrap() || !*self.audio.is_recording.lock().unwrap() {
+            return;
+        }
+        self.audio
+            .recorded_audio
+            .lock()
+            .unwrap()
+            .extend_from_slice(&samples);
+    }
+
+    fn send_due_packets(&mut self) {
+        let Some(call) = self.call.as_mut() else {
+            return;
+        };
+        let now = Instant::now();
+        if now.saturating_duration_since(call.next_send) > MAX_SEND_LAG {
+            call.next_send = now;
+        }
+
+        let mut rendered = [0.0f32; RENDER_SAMPLES];
+        let mut datagram = Vec::with_capacity(RTP_HEADER_LEN + PACKET_SAMPLES);
+        while call.next_send <= now {
+            rendered.fill(0.0);
+            self.audio.playback.lock().unwrap().render(&mut rendered);
+            // Back to 24kHz, the codec takes it down to 8kHz
+            let samples: Vec<f32> = rendered.iter().step_by(2).copied().collect();
+            let payload = call.codec.encode(&samples);
+
+            datagram.clear();
+            RtpPacket {
+                payload_type: call.payload_type,
+                sequence: call.sequence,
+                timestamp: call.timestamp,
+                ssrc: call.ssrc,
+                payload: &payload,
+            }
+            .write(&mut datagram);
+            if let Err(e) = self.rtp.send_to(&datagram, call.remote_rtp) {
+                log_debug!(Transport, "Failed to send RTP to {}: {}", call.remote_rtp, e);
+            }
+
+            call.sequence = call.sequence.wrapping_add(1);
+            call.timestamp = call.timestamp.wrapping_add(payload.len() as u32);
+            call.next_send += PACKET_INTERVAL;
+        }
+    }
+
+    fn send_sip(&self, message: &SipMessage, to: SocketAddr) {
+        if let Err(e) = self.sip.send_to(&message.to_bytes(), to) {
+            log_error!(Transport, "Failed to send SIP message to {}: {}", to, e);
+        }
+    }
+}
+
+pub fn payload_format(payload_type: u8) -> Option<AudioFormat> {
+    match payload_type {
+        PAYLOAD_TYPE_PCMU => Some(AudioFormat::G711Ulaw),
+        PAYLOAD_TYPE_PCMA => Some(AudioFormat::G711Alaw),
+        _ => None,
+    }
+}
+
+/// A SIP request or response, headers kept in order as received.
+#[derive(Debug, Clone)]
+pub struct SipMessage {
+    pub start_line: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl SipMessage {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let text = std::str::from_utf8(data).ok()?;
+        let (head, body) = text.split_once("\r\n\r\n").unwrap_or((text, ""));
+        let mut lines = head.split("\r\n");
+        let start_line = lines.next()?.trim().to_string();
+        if start_line.is_empty() {
+            return None;
+        }
+
+        let mut headers = Vec::new();
+        for line in lines {
+            let (name, value) = line.split_once(':')?;
+            headers.push((canonical_header_name(name.trim()), value.trim().to_string()));
+        }
+
+        let mut message = Self {
+            start_line,
+            headers,
+            body: body.to_string(),
+        };
+        if let Some(length) = message.header("Content-Length").and_then(|length| length.parse::<usize>().ok()) {
+            // Content-Length counts bytes, a cut inside a character is a malformed message
+            if length < message.body.len() {
+                if !message.body.is_char_boundary(length) {
+                    return None;
+                }
+                message.body.truncate(length);
+            }
+        }
+        Some(message)
+    }
+
+    pub fn request(method: &str, uri: &str) -> Self {
+        Self {
+            start_line: format!("{} {} SIP/2.0", method, uri),
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+
+    /// A response copying the headers that tie it to `self`.
+    pub fn response(&self, status: u16, reason: &str) -> Self {
+        let mut response = Self {
+            start_line: format!("SIP/2.0 {} {}", status, reason),
+            headers: Vec::new(),
+            body: String::new(),
+        };
+        for (name, value) in &self.headers {
+            if ["Via", "From", "To", "Call-ID", "CSeq"].contains(&name.as_str()) {
+                response.headers.push((name.clone(), value.clone()));
+            }
+        }
+        response.with_header("User-Agent", USER_AGENT)
+    }
+
+    /// None for responses.
+    pub fn method(&self) -> Option<&str> {
+        if self.start_line.starts_with("SIP/2.0") {
+            return None;
+        }
+        self.start_line.split(' ').next()
+    }
+
+    /// None for requests.
+    pub fn status(&self) -> Option<u16> {
+        self.start_line
+            .strip_prefix("SIP/2.0 ")?
+            .split(' ')
+            .next()?
+            .parse()
+            .ok()
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_body(mut self, content_type: &str, body: &str) -> Self {
+        self.body = body.to_string();
+        self.with_header("Content-Type", content_type)
+    }
+
+    fn with_to(mut self, to: &str) -> Self {
+        for (name, value) in &mut self.headers {
+            if name == "To" {
+                *value = to.to_string();
+            }
+        }
+        self
+    }
+
+    fn with_contact(self, address: IpAddr, port: u16) -> Self {
+        let contact = format!("<sip:{}@{}>", USER_AGENT, SocketAddr::new(address, port));
+        self.with_header("Contact", &contact)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut text = format!("{}\r\n", self.start_line);
+        for (name, value) in &self.headers {
+            if !name.eq_ignore_ascii_case("Content-Length") {
+                text.push_str(&format!("{}: {}\r\n", name, value));
+            }
+        }
+        text.push_str(&format!("Content-Length: {}\r\n\r\n", self.body.len()));
+        text.push_str(&self.body);
+        text.into_bytes()
+    }
+}
+
+// Header names are case-insensitive and have compact forms (RFC 3261), store one spelling
+fn canonical_header_name(name: &str) -> String {
+    match name.to_ascii_lowercase().as_str() {
+        "v" | "via" => "Via",
+        "f" | "from" => "From",
+        "t" | "to" => "To",
+        "i" | "call-id" => "Call-ID",
+        "m" | "contact" => "Contact",
+        "l" | "content-length" => "Content-Length",
+        "c" | "content-type" => "Content-Type",
+        "cseq" => "CSeq",
+        _ => name,
+    }
+    .to_string()
+}
+
+// The URI inside a From/To/Contact value, with or without angle brackets
+fn uri_of(value: &str) -> Option<String> {
+    let uri = match (value.find('<'), value.find('>')) {
+        (Some(start), Some(end)) if start < end => &value[start + 1..end],
+        _ => value.split(';').next()?,
+    };
+    Some(uri.trim().to_string()).filter(|uri| !uri.is_empty())
+}
+
+/// The audio part of an SDP offer.
+pub struct SdpAudio {
+    pub address: IpAddr,
+    pub port: u16,
+    /// In order of preference
+    pub payload_types: Vec<u8>,
+}
+
+impl SdpAudio {
+    pub fn parse(body: &str) -> Option<Self> {
+        let mut session_address = None;
+        let mut media_address = None;
+        let mut media = None;
+        // Whether the lines being read belong to the first audio stream
+        let mut in_audio = false;
+        let mut in_media = false;
+        for line in body.lines() {
+            let line = line.trim();
+            if let Some(connection) = line.strip_prefix("c=") {
+                // Session level unless inside a media section, where it only applies to that stream
+                let address = connection.split_whitespace().nth(2).and_then(|ip| ip.parse().ok());
+                if !in_media {
+                    session_address = address;
+                } else if in_audio {
+                    media_address = address;
+                }
+            } else if let Some(description) = line.strip_prefix("m=") {
+                in_media = true;
+                in_audio = false;
+                let Some(description) = description.strip_prefix("audio ") else {
+                    continue;
+                };
+                if media.is_some() {
+                    continue;
+                }
+                in_audio = true;
+                let mut fields = description.split_whitespace();
+                let port = fields.next()?.parse().ok()?;
+                let payload_types = fields.skip(1).filter_map(|pt| pt.parse().ok()).collect();
+                media = Some((port, payload_types));
+            }
+        }
+        let (port, payload_types) = media?;
+        Some(Self {
+            address: media_address.or(session_address)?,
+            port,
+            payload_types,
+        })
+    }
+}
+
+/// An SDP offer or answer for a sendrecv G.711 stream.
+pub fn sdp(address: IpAddr, port: u16, payload_types: &[u8]) -> String {
+    let family = if address.is_ipv4() { "IP4" } else { "IP6" };
+    let session_id = random_u32();
+    let mut sdp = format!(
+        "v=0\r\no=- {session_id} {session_id} IN {family} {address}\r\ns={USER_AGENT}\r\n\
+         c=IN {family} {address}\r\nt=0 0\r\nm=audio {port} RTP/AVP"
+    );
+    for payload_type in payload_types {
+        sdp.push_str(&format!(" {}", payload_type));
+    }
+    sdp.push_str("\r\n");
+    for &payload_type in payload_types {
+        let name = match payload_format(payload_type) {
+            Some(AudioFormat::G711Alaw) => "PCMA",
+            _ => "PCMU",
+        };
+        sdp.push_str(&format!("a=rtpmap:{} {}/{}\r\n", payload_type, name, G711_SAMPLE_RATE));
+    }
+    sdp.push_str(&format!("a=ptime:{}\r\na=sendrecv\r\n", PACKET_MS));
+    sdp
+}
+
+pub struct RtpPacket<'a> {
+    pub payload_type: u8,
+    pub sequence: u16,
+    pub timestamp: u32,
+    pub ssrc: u32,
+    pub payload: &'a [u8],
+}
+
+impl<'a> RtpPacket<'a> {
+    pub fn parse(datagram: &'a [u8]) -> Option<Self> {
+        if datagram.len() < RTP_HEADER_LEN || datagram[0] >> 6 != RTP_VERSION {
+            return None;
+        }
+        let has_padding = datagram[0] & 0x20 != 0;
+        let has_extension = datagram[0] & 0x10 != 0;
+        let csrc_count = (datagram[0] & 0x0F) as usize;
+
+        let mut start = RTP_HEADER_LEN + csrc_count * 4;
+        if has_extension {
+            let words = u16::from_be_bytes([*datagram.get(start + 2)?, *datagram.get(start + 3)?]);
+            start += 4 + words as usize * 4;
+        }
+        let mut end = datagram.len();
+        if has_padding {
+            end = end.checked_sub(*datagram.last()? as usize)?;
+        }
+
+        Some(Self {
+            payload_type: datagram[1] & 0x7F,
+            sequence: u16::from_be_bytes([datagram[2], datagram[3]]),
+            timestamp: u32::from_be_bytes([datagram[4], datagram[5], datagram[6], datagram[7]]),
+            ssrc: u32::from_be_bytes([datagram[8], datagram[9], datagram[10], datagram[11]]),
+            payload: datagram.get(start..end)?,
+        })
+    }
+
+    pub fn write(&self, output: &mut Vec<u8>) {
+        output.push(RTP_VERSION << 6);
+        output.push(self.payload_type & 0x7F);
+        output.extend_from_slice(&self.sequence.to_be_bytes());
+        output.extend_from_slice(&self.timestamp.to_be_bytes());
+        output.extend_from_slice(&self.ssrc.to_be_bytes());
+        output.extend_from_slice(self.payload);
+    }
+}
+
+// The OS picks the interface a datagram to `peer` would leave through, nothing is sent
+fn local_address_towards(peer: SocketAddr) -> Option<IpAddr> {
+    let unspecified = if peer.is_ipv4() {
+        IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+    } else {
+        IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+    };
+    let probe = UdpSocket::bind((unspecified, 0)).ok()?;
+    probe.connect(peer).ok()?;
+    Some(probe.local_addr().ok()?.ip())
+}
+
+/// Tags, branches and RTP identifiers only need to be unlikely to collide.
+pub fn random_u32() -> u32 {
+    let mut hasher = RandomState::new().build_hasher();
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default();
+    hasher.write_u128(nanos);
+    hasher.finish() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sip_parse_expands_compact_headers() {
+        let data = "INVITE sip:bot@10.0.0.1 SIP/2.0\r\n\
+                    v: SIP/2.0/UDP 10.0.0.2:5060;branch=z9hG4bK1\r\n\
+                    f: \"Alice\" <sip:alice@10.0.0.2>;tag=1\r\n\
+                    t: <sip:bot@10.0.0.1>\r\n\
+                    i: call-1\r\n\
+                    m: <sip:alice@10.0.0.2:5060>\r\n\
+                    c: application/sdp\r\n\
+                    CSeq: 1 INVITE\r\n\
+                    l: 3\r\n\r\nv=0";
+        let message = SipMessage::parse(data.as_bytes()).unwrap();
+        assert_eq!(message.method(), Some("INVITE"));
+        assert_eq!(message.status(), None);
+        assert_eq!(message.header("call-id"), Some("call-1"));
+        assert_eq!(message.header("From"), Some("\"Alice\" <sip:alice@10.0.0.2>;tag=1"));
+        assert_eq!(message.header("Contact"), Some("<sip:alice@10.0.0.2:5060>"));
+        assert_eq!(message.header("Content-Type"), Some("application/sdp"));
+        assert_eq!(message.body, "v=0");
+
+        let response = SipMessage::parse(b"SIP/2.0 486 Busy Here\r\nCall-ID: call-1\r\n\r\n").unwrap();
+        assert_eq!(response.status(), Some(486));
+        assert_eq!(response.method(), None);
+
+        assert!(SipMessage::parse(b"INVITE sip:bot SIP/2.0\r\nnot a header\r\n\r\n").is_none());
+        assert!(SipMessage::parse(b"").is_none());
+    }
+
+    #[test]
+    fn sip_parse_truncates_body_to_content_length() {
+        let message = SipMessage::parse(b"BYE sip:bot SIP/2.0\r\nContent-Length: 4\r\n\r\nbodyINVITE").unwrap();
+        assert_eq!(message.body, "body");
+
+        // A longer Content-Length than what arrived keeps what there is
+        let message = SipMessage::parse(b"BYE sip:bot SIP/2.0\r\nContent-Length: 100\r\n\r\nbody").unwrap();
+        assert_eq!(message.body, "body");
+
+        // Cutting inside a character
+        assert!(SipMessage::parse("BYE sip:bot SIP/2.0\r\nl: 1\r\n\r\né".as_bytes()).is_none());
+    }
+
+    #[test]
+    fn uri_of_handles_name_addr_and_addr_spec() {
+        assert_eq!(uri_of("\"Alice\" <sip:alice@example.com>;tag=1").as_deref(), Some("sip:alice@example.com"));
+        assert_eq!(uri_of("sip:bob@example.com;tag=2").as_deref(), Some("sip:bob@example.com"));
+        assert_eq!(uri_of(" sip:carol@example.com ").as_deref(), Some("sip:carol@example.com"));
+        assert_eq!(uri_of("<>"), None);
+        assert_eq!(uri_of(""), None);
+    }
+
+    #[test]
+    fn sdp_parse_takes_the_first_audio_stream() {
+        let offer = "v=0\r\n\
+                     o=- 1 1 IN IP4 10.0.0.2\r\n\
+                     c=IN IP4 10.0.0.2\r\n\
+                     t=0 0\r\n\
+                     m=audio 4000 RTP/AVP 8 0 101\r\n\
+                     a=rtpmap:101 telephone-event/8000\r\n\
+                     m=video 5000 RTP/AVP 96\r\n\
+                     c=IN IP4 10.0.0.9\r\n\
+                     m=audio 6000 RTP/AVP 0\r\n";
+        let audio = SdpAudio::parse(offer).unwrap();
+        assert_eq!(audio.address, "10.0.0.2".parse::<IpAddr>().unwrap());
+        assert_eq!(audio.port, 4000);
+        assert_eq!(audio.payload_types, vec![8, 0, 101]);
+
+        // A media level connection overrides the session level one for its stream only
+        let offer = "v=0\r\nc=IN IP4 10.0.0.2\r\nm=video 5000 RTP/AVP 96\r\nc=IN IP4 10.0.0.9\r\n\
+                     m=audio 4000 RTP/AVP 0\r\nc=IN IP4 10.0.0.3\r\n";
+        assert_eq!(SdpAudio::parse(offer).unwrap().address, "10.0.0.3".parse::<IpAddr>().unwrap());
+
+        assert!(SdpAudio::parse("v=0\r\nc=IN IP4 10.0.0.2\r\nm=video 5000 RTP/AVP 96\r\n").is_none());
+        assert!(SdpAudio::parse("v=0\r\nm=audio 4000 RTP/AVP 0\r\n").is_none());
+    }
+
+    #[test]
+    fn rtp_write_and_parse_round_trip() {
+        let packet = RtpPacket {
+            payload_type: PAYLOAD_TYPE_PCMA,
+            sequence: 0xFFFF,
+            timestamp: 0x01020304,
+            ssrc: 0xDEADBEEF,
+            payload: &[1, 2, 3, 4],
+        };
+        let mut datagram = Vec::new();
+        packet.write(&mut datagram);
+        assert_eq!(datagram.len(), RTP_HEADER_LEN + 4);
+
+        let parsed = RtpPacket::parse(&datagram).unwrap();
+        assert_eq!(parsed.payload_type, PAYLOAD_TYPE_PCMA);
+        assert_eq!(parsed.sequence, 0xFFFF);
+        assert_eq!(parsed.timestamp, 0x01020304);
+        assert_eq!(parsed.ssrc, 0xDEADBEEF);
+        assert_eq!(parsed.payload, &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn rtp_parse_skips_csrcs_extension_and_padding() {
+        let mut datagram = Vec::new();
+        RtpPacket {
+            payload_type: PAYLOAD_TYPE_PCMU,
+            sequence: 1,
+            timestamp: 160,
+            ssrc: 7,
+            payload: &[],
+        }
+        .write(&mut datagram);
+        // Padding, extension and two CSRCs
+        datagram[0] |= 0x20 | 0x10 | 2;
+        datagram.extend_from_slice(&[0; 8]);
+        datagram.extend_from_slice(&[0xBE, 0xDE, 0, 1, 9, 9, 9, 9]);
+        datagram.extend_from_slice(&[5, 6, 7]);
+        datagram.extend_from_slice(&[0, 0, 3]);
+
+        let parsed = RtpPacket::parse(&datagram).unwrap();
+        assert_eq!(parsed.payload_type, PAYLOAD_TYPE_PCMU);
+        assert_eq!(parsed.payload, &[5, 6, 7]);
+
+        // Padding longer than the packet, an extension running past the end, a wrong version
+        let mut padded = datagram.clone();
+        *padded.last_mut().unwrap() = 255;
+        assert!(RtpPacket::parse(&padded).is_none());
+        assert!(RtpPacket::parse(&datagram[..RTP_HEADER_LEN + 10]).is_none());
+        let mut version_1 = datagram.clone();
+        version_1[0] = (version_1[0] & 0x3F) | 0x40;
+        assert!(RtpPacket::parse(&version_1).is_none());
+        assert!(RtpPacket::parse(&datagram[..RTP_HEADER_LEN - 1]).is_none());
+    }
+}
@@ -0,0 +1,490 @@
+use crate::app::SessionConfig;
+use crate::relay::tokens_match;
+use crate::{log_debug, log_info};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::ws_server::{fill_buf_before, read_line_before};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+// Ephemeral client secrets: short-lived tokens that open exactly one Realtime session,
+// minted with the long-lived key through the sessions endpoint. The session they open
+// starts from the config they were minted with.
+//
+// The token service hands them out over plain HTTP (`POST /token`) so clients never see
+// the key. Parsing happens on a background thread, the minting itself is an HTTPS request
+// made by the app, see `App::mint_client_secret`.
+
+pub const SESSIONS_URL: &str = "https://api.openai.com/v1/realtime/sessions";
+
+const TOKEN_PATH: &str = "/token";
+const MAX_REQUEST_LEN: usize = 16 * 1024;
+// Requests being read at once, one thread each, more connections are dropped
+const MAX_CONCURRENT_REQUESTS: usize = 16;
+// For the whole request, not each read
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How `connect_to_openai` authenticates.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RealtimeAuth {
+    /// Sends OPENAI_API_KEY with the WebSocket upgrade
+    #[default]
+    ApiKey,
+    /// Mints a client secret with OPENAI_API_KEY first and connects with that
+    Ephemeral,
+    /// Fetches a client secret from a token service, no API key needed on this machine
+    TokenService {
+        url: String,
+        access_token: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TokenServiceConfig {
+    /// Serve `POST /token` on `bind_address`
+    pub enabled: bool,
+    pub bind_address: String,
+    /// Clients have to send `Authorization: Bearer <access_token>`, generated on first start
+    pub access_token: Option<String>,
+    /// Origins of web pages that may ask for tokens, clients outside a browser send none
+    pub allowed_origins: Vec<String>,
+    /// Lifetime of minted secrets, the API accepts 10 to 7200
+    pub expires_after_secs: u32,
+}
+
+impl Default for TokenServiceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_address: "127.0.0.1:8788".to_string(),
+            access_token: None,
+            allowed_origins: Vec::new(),
+            expires_after_secs: 60,
+        }
+    }
+}
+
+/// Body of a sessions endpoint request.
+#[derive(Serialize)]
+pub struct SessionsRequest<'a> {
+    pub model: &'a str,
+    #[serde(flatten)]
+    pub session: SessionConfig,
+    pub client_secret: ClientSecretConfig,
+}
+
+#[derive(Serialize)]
+pub struct ClientSecretConfig {
+    pub expires_after: ExpiresAfter,
+}
+
+#[derive(Serialize)]
+pub struct ExpiresAfter {
+    pub anchor: &'static str,
+    pub seconds: u32,
+}
+
+impl ClientSecretConfig {
+    pub fn expiring_after(seconds: u32) -> Self {
+        Self {
+            expires_after: ExpiresAfter {
+                anchor: "created_at",
+                seconds,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClientSecret {
+    pub value: String,
+    /// Unix time
+    pub expires_at: u64,
+}
+
+/// What the sessions endpoint and the token service answer with, both carry `client_secret`.
+#[derive(Deserialize, Debug)]
+pub struct SessionsResponse {
+    pub client_secret: ClientSecret,
+}
+
+/// Answer of the token service, the sessions response without the session details.
+pub fn token_response(secret: &ClientSecret, model: &str) -> String {
+    json!({ "client_secret": secret, "model": model }).to_string()
+}
+
+/// A token request waiting for the app to mint a secret.
+pub struct TokenRequest {
+    /// Voice the client asked for, checked against the policy by the app
+    pub voice: Option<String>,
+    /// Allowed origin of the requesting page, echoed for CORS
+    origin: Option<String>,
+    stream: TcpStream,
+}
+
+impl TokenRequest {
+    pub fn respond(mut self, status: u16, body: &str) {
+        let _ = write_response(&mut self.stream, status, body, self.origin.as_deref());
+    }
+}
+
+/// Accepts token requests on a background thread.
+pub struct TokenService {
+    requests: Receiver<TokenRequest>,
+    local_addr: SocketAddr,
+}
+
+impl TokenService {
+    /// Refuses to start without an access token, see `relay::generate_access_token`.
+    pub fn start(config: &TokenServiceConfig) -> std::io::Result<Self> {
+        let access_token = match &config.access_token {
+            Some(access_token) if !access_token.is_empty() => access_token.clone(),
+            _ => return Err(std::io::Error::other("the token service needs an access_token")),
+        };
+        let allowed_origins = config.allowed_origins.clone();
+        let listener = TcpListener::bind(&config.bind_address)?;
+        let local_addr = listener.local_addr()?;
+        let (requests, receiver) = mpsc::channel();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+
+        std::thread::Builder::new()
+            .name("token-service".to_string())
+            .spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    if in_flight.load(Ordering::SeqCst) >= MAX_CONCURRENT_REQUESTS {
+                        log_debug!(Transport, "Dropping a token request, {} are being read", MAX_CONCURRENT_REQUESTS);
+                        continue;
+                    }
+                    in_flight.fetch_add(1, Ordering::SeqCst);
+                    let requests = requests.clone();
+                    let access_token = access_token.clone();
+                    let allowed_origins = allowed_origins.clone();
+                    // A slow client must not hold up the others
+                    let spawned = std::thread::Builder::new()
+                        .name("token-request".to_string())
+                        .spawn({
+                            let in_flight = in_flight.clone();
+                            move || {
+                                serve_request(stream, &access_token, &allowed_origins, requests);
+                                in_flight.fetch_sub(1, Ordering::SeqCst);
+                            }
+                        });
+                    if spawned.is_err() {
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                    }
+                }
+            })?;
+
+        log_info!(Transport, "Token service listening on http://{}{}", local_addr, TOKEN_PATH);
+        Ok(Self {
+            requests: receiver,
+            local_addr,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn poll_requests(&self) -> Vec<TokenRequest> {
+        self.requests.try_iter().collect()
+    }
+}
+
+fn serve_request(
+    mut stream: TcpStream,
+    access_token: &str,
+    allowed_origins: &[String],
+    requests: Sender<TokenRequest>,
+) {
+    let mut reader = match stream.try_clone() {
+        Ok(stream) => BufReader::new(stream),
+        Err(_) => return,
+    };
+    let request = match read_request(&mut reader, Instant::now() + REQUEST_TIMEOUT) {
+        Ok(request) => request,
+        Err(e) => {
+            log_debug!(Transport, "Bad token service request: {}", e);
+            let _ = write_response(&mut stream, 400, &error_body(&e), None);
+            return;
+        }
+    };
+
+    // Pages from other origins get no CORS headers, so browsers keep the answer from them
+    let origin = request.origin.clone();
+    if let Some(origin) = origin.as_ref().filter(|origin| !allowed_origins.contains(origin)) {
+        log_debug!(Transport, "Token request from origin {} refused", origin);
+        let _ = write_response(&mut stream, 403, &error_body("origin not allowed"), None);
+        return;
+    }
+    let respond = |stream: &mut TcpStream, status, body: &str| {
+        let _ = write_response(stream, status, body, origin.as_deref());
+    };
+
+    // Browsers ask before sending an Authorization header cross-origin
+    if request.method == "OPTIONS" {
+        respond(&mut stream, 204, "");
+        return;
+    }
+    if request.path.split('?').next() != Some(TOKEN_PATH) {
+        respond(&mut stream, 404, &error_body("not found"));
+        return;
+    }
+    if request.method != "POST" {
+        respond(&mut stream, 405, &error_body("use POST"));
+        return;
+    }
+    if !request
+        .bearer
+        .as_deref()
+        .is_some_and(|bearer| tokens_match(access_token, bearer))
+    {
+        respond(&mut stream, 401, &error_body("invalid access token"));
+        return;
+    }
+
+    // An empty body is fine, otherwise it may pick a voice
+    let voice = if request.body.iter().all(u8::is_ascii_whitespace) {
+        None
+    } else {
+        match serde_json::from_slice::<serde_json::Value>(&request.body) {
+            Ok(body) => body["voice"].as_str().map(str::to_string),
+            Err(e) => {
+                respond(&mut stream, 400, &error_body(&format!("invalid JSON: {}", e)));
+                return;
+            }
+        }
+    };
+
+    let _ = requests.send(TokenRequest { voice, origin, stream });
+}
+
+struct ParsedRequest {
+    method: String,
+    path: String,
+    bearer: Option<String>,
+    origin: Option<String>,
+    body: Vec<u8>,
+}
+
+fn read_request(reader: &mut BufReader<TcpStream>, deadline: Instant) -> Result<ParsedRequest, String> {
+    // Bounds every line and the body too, not just their sum
+    let mut remaining_len = MAX_REQUEST_LEN;
+    let request_line = read_line_before(reader, deadline, &mut remaining_len)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(format!("unexpected request line {:?}", request_line.trim()));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+
+    let mut bearer = None;
+    let mut origin = None;
+    let mut content_length = 0;
+    loop {
+        let line = read_line_before(reader, deadline, &mut remaining_len)?;
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("Content-Length") {
+            content_length = value.parse().map_err(|_| "invalid Content-Length")?;
+        } else if name.eq_ignore_ascii_case("Authorization") {
+            bearer = value.strip_prefix("Bearer ").map(str::to_string);
+        } else if name.eq_ignore_ascii_case("Origin") {
+            origin = Some(value.to_string());
+        }
+    }
+    if content_length > remaining_len {
+        return Err("too large".to_string());
+    }
+
+    let mut body = Vec::with_capacity(content_length);
+    while body.len() < content_length {
+        let available = fill_buf_before(reader, deadline)?;
+        if available.is_empty() {
+            return Err("cut short".to_string());
+        }
+        let length = available.len().min(content_length - body.len());
+        body.extend_from_slice(&available[..length]);
+        reader.consume(length);
+    }
+    Ok(ParsedRequest {
+        method,
+        path,
+        bearer,
+        origin,
+        body,
+    })
+}
+
+pub fn error_body(message: &str) -> String {
+    json!({ "error": { "message": message } }).to_string()
+}
+
+/// `origin` is the allowed origin to grant CORS access to, None sends no CORS headers.
+fn write_response(stream: &mut TcpStream, status: u16, body: &str, origin: Option<&str>) -> std::io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Bad Gateway",
+    };
+    let cors = match origin {
+        Some(origin) => format!(
+            "Access-Control-Allow-Origin: {}\r\n\
+             Access-Control-Allow-Methods: POST, OPTIONS\r\n\
+             Access-Control-Allow-Headers: Authorization, Content-Type\r\n\
+             Vary: Origin\r\n",
+            origin
+        ),
+        None => String::new(),
+    };
+    let response = format!(
+        "HTTP/1.1 {} {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Cache-Control: no-store\r\n\
+         {}\
+         Connection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        cors,
+        body
+    );
+    stream.write_all(response.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    /// A connected pair, the server side wrapped like `serve_request` does
+    fn connect() -> (TcpStream, BufReader<TcpStream>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (client, BufReader::new(server))
+    }
+
+    #[test]
+    fn slow_requests_give_up_at_the_deadline() {
+        let (mut client, mut reader) = connect();
+        // Every read gets a byte in time, only an overall deadline ends this
+        std::thread::spawn(move || {
+            let _ = client.write_all(b"POST /token HTTP/1.1\r\nContent-Length: 100\r\n\r\n");
+            for _ in 0..100 {
+                std::thread::sleep(Duration::from_millis(20));
+                if client.write_all(b" ").is_err() {
+                    break;
+                }
+            }
+        });
+
+        let started = Instant::now();
+        let result = read_request(&mut reader, started + Duration::from_millis(200));
+        assert_eq!(result.err(), Some("timed out".to_string()));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    fn request(data: &[u8]) -> Result<ParsedRequest, String> {
+        let (mut client, mut reader) = connect();
+        client.write_all(data).unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+        read_request(&mut reader, Instant::now() + REQUEST_TIMEOUT)
+    }
+
+    /// What `serve_request` makes of `data`: the response, or the request handed to the app
+    fn serve(data: &[u8], allowed_origins: &[String]) -> (String, Option<TokenRequest>) {
+        let (mut client, reader) = connect();
+        client.write_all(data).unwrap();
+        let (requests, receiver) = mpsc::channel();
+        serve_request(reader.into_inner(), "secret", allowed_origins, requests);
+        let request = receiver.try_recv().ok();
+        if let Some(request) = request {
+            return (String::new(), Some(request));
+        }
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        (response, None)
+    }
+
+    #[test]
+    fn requests_are_parsed() {
+        let parsed = request(
+            b"POST /token HTTP/1.1\r\nauthorization: Bearer abc\r\nOrigin: https://app.example\r\n\
+              Content-Length: 17\r\n\r\n{\"voice\":\"coral\"}",
+        )
+        .unwrap();
+        assert_eq!(parsed.method, "POST");
+        assert_eq!(parsed.path, "/token");
+        assert_eq!(parsed.bearer.as_deref(), Some("abc"));
+        assert_eq!(parsed.origin.as_deref(), Some("https://app.example"));
+        assert_eq!(parsed.body, b"{\"voice\":\"coral\"}");
+    }
+
+    #[test]
+    fn malformed_requests_are_refused() {
+        let long_header = format!("POST /token HTTP/1.1\r\nX-Long: {}\r\n\r\n", "a".repeat(MAX_REQUEST_LEN));
+        assert_eq!(request(long_header.as_bytes()).err().as_deref(), Some("too large"));
+
+        let long_body = format!("POST /token HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_REQUEST_LEN);
+        assert_eq!(request(long_body.as_bytes()).err().as_deref(), Some("too large"));
+
+        let bad_length = b"POST /token HTTP/1.1\r\nContent-Length: -1\r\n\r\n";
+        assert_eq!(request(bad_length).err().as_deref(), Some("invalid Content-Length"));
+
+        let short_body = b"POST /token HTTP/1.1\r\nContent-Length: 10\r\n\r\n{}";
+        assert_eq!(request(short_body).err().as_deref(), Some("cut short"));
+
+        assert!(request(b"\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn token_requests_need_the_access_token() {
+        let (response, request) = serve(b"POST /token HTTP/1.1\r\n\r\n", &[]);
+        assert!(request.is_none());
+        assert!(response.starts_with("HTTP/1.1 401 "), "{}", response);
+
+        let (response, _) = serve(b"POST /token HTTP/1.1\r\nAuthorization: Bearer secreT\r\n\r\n", &[]);
+        assert!(response.starts_with("HTTP/1.1 401 "), "{}", response);
+
+        let (_, request) = serve(
+            b"POST /token HTTP/1.1\r\nAuthorization: Bearer secret\r\nContent-Length: 17\r\n\r\n{\"voice\":\"coral\"}",
+            &[],
+        );
+        assert_eq!(request.unwrap().voice.as_deref(), Some("coral"));
+    }
+
+    #[test]
+    fn preflights_are_answered_for_allowed_origins_only() {
+        let allowed = ["https://app.example".to_string()];
+        let preflight = |origin: &str| {
+            format!("OPTIONS /token HTTP/1.1\r\nOrigin: {}\r\nAccess-Control-Request-Method: POST\r\n\r\n", origin)
+        };
+
+        let (response, _) = serve(preflight("https://app.example").as_bytes(), &allowed);
+        assert!(response.starts_with("HTTP/1.1 204 "), "{}", response);
+        assert!(response.contains("Access-Control-Allow-Origin: https://app.example\r\n"));
+        assert!(response.contains("Access-Control-Allow-Headers: Authorization, Content-Type\r\n"));
+
+        let (response, _) = serve(preflight("https://evil.example").as_bytes(), &allowed);
+        assert!(response.starts_with("HTTP/1.1 403 "), "{}", response);
+        assert!(!response.contains("Access-Control-Allow-Origin"));
+    }
+}
@@ -0,0 +1,174 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Session timeline recorded as Chrome Trace Event JSON, open the export in
+// https://ui.perfetto.dev or chrome://tracing to inspect overlapping activity.
+
+// Oldest events are dropped past this, roughly a few hours of conversation
+const MAX_TRACE_EVENTS: usize = 500_000;
+
+const TRACE_PID: u32 = 1;
+
+/// Rows of the timeline, each one is shown as a separate thread in the viewer.
+#[derive(Clone, Copy, Debug)]
+pub enum Track {
+    ServerEvents = 1,
+    ClientEvents,
+    AudioInput,
+    Playback,
+    UserSpeech,
+    AssistantResponse,
+    State,
+}
+
+impl Track {
+    const ALL: [Track; 7] = [
+        Track::ServerEvents,
+        Track::ClientEvents,
+        Track::AudioInput,
+        Track::Playback,
+        Track::UserSpeech,
+        Track::AssistantResponse,
+        Track::State,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Track::ServerEvents => "Server events",
+            Track::ClientEvents => "Client events",
+            Track::AudioInput => "Audio sent",
+            Track::Playback => "Playback",
+            Track::UserSpeech => "User speech",
+            Track::AssistantResponse => "Assistant response",
+            Track::State => "State",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TraceEvent {
+    name: String,
+    ph: &'static str,
+    /// Microseconds since the recording started
+    ts: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dur: Option<f64>,
+    pid: u32,
+    tid: u32,
+    /// Scope of instant events, "t" keeps them on their own track
+    #[serde(skip_serializing_if = "Option::is_none")]
+    s: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    args: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct TraceFile<'a> {
+    #[serde(rename = "traceEvents")]
+    trace_events: Vec<&'a TraceEvent>,
+    #[serde(rename = "displayTimeUnit")]
+    display_time_unit: &'static str,
+}
+
+pub struct TraceRecorder {
+    start: Instant,
+    events: VecDeque<TraceEvent>,
+}
+
+impl Default for TraceRecorder {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            events: VecDeque::new(),
+        }
+    }
+}
+
+impl TraceRecorder {
+    pub fn instant(&mut self, track: Track, name: &str, args: Option<serde_json::Value>) {
+        self.push(track, name, "i", Instant::now(), None, Some("t"), args);
+    }
+
+    pub fn begin(&mut self, track: Track, name: &str, at: Instant) {
+        self.push(track, name, "B", at, None, None, None);
+    }
+
+    pub fn end(&mut self, track: Track, name: &str, at: Instant) {
+        self.push(track, name, "E", at, None, None, None);
+    }
+
+    /// A span whose duration is already known, e.g. an audio chunk.
+    pub fn complete(
+        &mut self,
+        track: Track,
+        name: &str,
+        start: Instant,
+        duration: Duration,
+        args: Option<serde_json::Value>,
+    ) {
+        let dur = duration.as_secs_f64() * 1_000_000.0;
+        self.push(track, name, "X", start, Some(dur), None, args);
+    }
+
+    /// Counter values are drawn as a graph, used for the app state flags.
+    pub fn counter(&mut self, name: &str, values: serde_json::Value) {
+        self.push(
+            Track::State,
+            name,
+            "C",
+            Instant::now(),
+            None,
+            None,
+            Some(values),
+        );
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn push(
+        &mut self,
+        track: Track,
+        name: &str,
+        ph: &'static str,
+        at: Instant,
+        dur: Option<f64>,
+        s: Option<&'static str>,
+        args: Option<serde_json::Value>,
+    ) {
+        if self.events.len() >= MAX_TRACE_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(TraceEvent {
+            name: name.to_string(),
+            ph,
+            ts: at.saturating_duration_since(self.start).as_secs_f64() * 1_000_000.0,
+            dur,
+            pid: TRACE_PID,
+            tid: track as u32,
+            s,
+            args,
+        });
+    }
+
+    pub fn to_json(&self) -> String {
+        let track_names: Vec<TraceEvent> = Track::ALL
+            .iter()
+            .map(|track| TraceEvent {
+                name: "thread_name".to_string(),
+                ph: "M",
+                ts: 0.0,
+                dur: None,
+                pid: TRACE_PID,
+                tid: *track as u32,
+                s: None,
+                args: Some(serde_json::json!({ "name": track.name() })),
+            })
+            .collect();
+
+        let file = TraceFile {
+            trace_events: track_names.iter().chain(self.events.iter()).collect(),
+            display_time_unit: "ms",
+        };
+        serde_json::to_string(&file).unwrap_or_default()
+    }
+}
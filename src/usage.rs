@@ -0,0 +1,291 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::time::{Duration, Instant};
+
+// Token usage reported by `response.done`, aggregated per turn and per session.
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(default)]
+pub struct Usage {
+    pub total_tokens: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub input_token_details: InputTokenDetails,
+    pub output_token_details: OutputTokenDetails,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(default)]
+pub struct InputTokenDetails {
+    pub cached_tokens: u64,
+    pub text_tokens: u64,
+    pub audio_tokens: u64,
+    pub cached_tokens_details: CachedTokenDetails,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(default)]
+pub struct CachedTokenDetails {
+    pub text_tokens: u64,
+    pub audio_tokens: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(default)]
+pub struct OutputTokenDetails {
+    pub text_tokens: u64,
+    pub audio_tokens: u64,
+}
+
+impl Usage {
+    pub fn add(&mut self, other: &Usage) {
+        self.total_tokens += other.total_tokens;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+
+        let input = &mut self.input_token_details;
+        input.cached_tokens += other.input_token_details.cached_tokens;
+        input.text_tokens += other.input_token_details.text_tokens;
+        input.audio_tokens += other.input_token_details.audio_tokens;
+        input.cached_tokens_details.text_tokens +=
+            other.input_token_details.cached_tokens_details.text_tokens;
+        input.cached_tokens_details.audio_tokens +=
+            other.input_token_details.cached_tokens_details.audio_tokens;
+
+        self.output_token_details.text_tokens += other.output_token_details.text_tokens;
+        self.output_token_details.audio_tokens += other.output_token_details.audio_tokens;
+    }
+}
+
+/// USD per million tokens. Defaults match gpt-4o-realtime-preview pricing,
+/// override them in the settings file when using another model or price list.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PriceTable {
+    pub text_input: f64,
+    pub cached_text_input: f64,
+    pub audio_input: f64,
+    pub cached_audio_input: f64,
+    pub text_output: f64,
+    pub audio_output: f64,
+}
+
+impl Default for PriceTable {
+    fn default() -> Self {
+        Self {
+            text_input: 5.0,
+            cached_text_input: 2.5,
+            audio_input: 40.0,
+            cached_audio_input: 2.5,
+            text_output: 20.0,
+            audio_output: 80.0,
+        }
+    }
+}
+
+impl PriceTable {
+    /// Cost of the given usage in USD. Cached tokens are billed at the cached rate only.
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        let input = &usage.input_token_details;
+        let cached = &input.cached_tokens_details;
+
+        let per_token =
+            |tokens: u64, price_per_million: f64| tokens as f64 * price_per_million / 1_000_000.0;
+
+        per_token(
+            input.text_tokens.saturating_sub(cached.text_tokens),
+            self.text_input,
+        ) + per_token(cached.text_tokens, self.cached_text_input)
+            + per_token(
+                input.audio_tokens.saturating_sub(cached.audio_tokens),
+                self.audio_input,
+            )
+            + per_token(cached.audio_tokens, self.cached_audio_input)
+            + per_token(usage.output_token_details.text_tokens, self.text_output)
+            + per_token(usage.output_token_details.audio_tokens, self.audio_output)
+    }
+}
+
+pub struct TurnUsage {
+    pub response_id: String,
+    /// Seconds since the session started
+    pub elapsed_secs: f64,
+    pub usage: Usage,
+    pub cost: f64,
+}
+
+/// Usage of every response since the user connected. Reconnects and rollovers keep adding
+/// to it, so a new session doesn't come with a fresh budget.
+pub struct UsageTracker {
+    /// Set when the first session is created
+    session_start: Option<Instant>,
+    turns: Vec<TurnUsage>,
+    totals: Usage,
+    total_cost: f64,
+}
+
+impl Default for UsageTracker {
+    fn default() -> Self {
+        Self {
+            session_start: None,
+            turns: Vec::new(),
+            totals: Usage::default(),
+            total_cost: 0.0,
+        }
+    }
+}
+
+impl UsageTracker {
+    pub fn record(&mut self, response_id: String, usage: Usage, prices: &PriceTable) -> &TurnUsage {
+        let cost = prices.cost(&usage);
+        self.totals.add(&usage);
+        self.total_cost += cost;
+
+        self.turns.push(TurnUsage {
+            response_id,
+            elapsed_secs: self.session_elapsed().as_secs_f64(),
+            usage,
+            cost,
+        });
+        self.turns.last().unwrap()
+    }
+
+    pub fn totals(&self) -> &Usage {
+        &self.totals
+    }
+
+    pub fn total_cost(&self) -> f64 {
+        self.total_cost
+    }
+
+    /// Starts the clock for the session time limit, later sessions keep it running.
+    pub fn start_clock(&mut self) {
+        self.session_start.get_or_insert_with(Instant::now);
+    }
+
+    pub fn session_elapsed(&self) -> Duration {
+        self.session_start
+            .map(|start| start.elapsed())
+            .unwrap_or_default()
+    }
+
+    pub fn last_turn(&self) -> Option<&TurnUsage> {
+        self.turns.last()
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "turn,response_id,elapsed_secs,input_text_tokens,input_audio_tokens,cached_text_tokens,\
+             cached_audio_tokens,output_text_tokens,output_audio_tokens,total_tokens,cost_usd\n",
+        );
+
+        let mut write_row =
+            |turn: &str, response_id: &str, elapsed_secs: f64, usage: &Usage, cost: f64| {
+                let input = &usage.input_token_details;
+                let _ = writeln!(
+                    csv,
+                    "{},{},{:.3},{},{},{},{},{},{},{},{:.6}",
+                    turn,
+                    response_id,
+                    elapsed_secs,
+                    input.text_tokens,
+                    input.audio_tokens,
+                    input.cached_tokens_details.text_tokens,
+                    input.cached_tokens_details.audio_tokens,
+                    usage.output_token_details.text_tokens,
+                    usage.output_token_details.audio_tokens,
+                    usage.total_tokens,
+                    cost,
+                );
+            };
+
+        for (index, turn) in self.turns.iter().enumerate() {
+            write_row(
+                &(index + 1).to_string(),
+                &turn.response_id,
+                turn.elapsed_secs,
+                &turn.usage,
+                turn.cost,
+            );
+        }
+        write_row(
+            "total",
+            "",
+            self.session_elapsed().as_secs_f64(),
+            &self.totals,
+            self.total_cost,
+        );
+
+        csv
+    }
+}
+
+/// Limits that end a session before it gets expensive, all disabled by default.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BudgetLimits {
+    pub max_session_minutes: Option<f64>,
+    pub max_tokens: Option<u64>,
+    pub max_spend_usd: Option<f64>,
+    /// Fraction of any limit at which the user gets warned
+    pub warning_threshold: f64,
+}
+
+impl Default for BudgetLimits {
+    fn default() -> Self {
+        Self {
+            max_session_minutes: None,
+            max_tokens: None,
+            max_spend_usd: None,
+            warning_threshold: 0.8,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BudgetStatus {
+    WithinLimits,
+    NearLimit(String),
+    Exceeded(String),
+}
+
+impl BudgetLimits {
+    pub fn check(&self, usage: &UsageTracker) -> BudgetStatus {
+        let minutes = usage.session_elapsed().as_secs_f64() / 60.0;
+        let checks = [
+            self.max_session_minutes.map(|max| {
+                (
+                    minutes / max,
+                    format!("{:.0} of {:.0} session minutes", minutes, max),
+                )
+            }),
+            self.max_tokens.map(|max| {
+                let used = usage.totals().total_tokens;
+                (
+                    used as f64 / max as f64,
+                    format!("{} of {} tokens", used, max),
+                )
+            }),
+            self.max_spend_usd.map(|max| {
+                let spent = usage.total_cost();
+                (spent / max, format!("${:.2} of ${:.2}", spent, max))
+            }),
+        ];
+
+        let Some((fraction, description)) = checks
+            .into_iter()
+            .flatten()
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        else {
+            return BudgetStatus::WithinLimits;
+        };
+
+        if fraction >= 1.0 {
+            BudgetStatus::Exceeded(description)
+        } else if fraction >= self.warning_threshold {
+            BudgetStatus::NearLimit(description)
+        } else {
+            BudgetStatus::WithinLimits
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use crate::audio::{NoiseFloor, OPENAI_SAMPLE_RATE, amplitude_to_db};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+// Client-side voice activity detection on the 24kHz stream before it is uploaded.
+//
+// A frame counts as speech when its energy is clearly above both a fixed threshold and the
+// noise floor (the quietest level of the last few seconds), and its zero-crossing rate is
+// low enough to rule out hiss and other broadband noise. Speech has to last `min_speech_ms`
+// to start a segment, which then includes `pre_roll_ms` of audio from before the onset, and
+// ends after `hangover_ms` of non-speech frames.
+
+const FRAME_MS: usize = 20;
+const FRAME_SAMPLES: usize = OPENAI_SAMPLE_RATE * FRAME_MS / 1000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum VadMode {
+    /// Stream everything, the server decides on turns
+    #[default]
+    Off,
+    /// Only stream speech, the server still decides on turns
+    GateUploads,
+    /// Only stream speech and end turns locally, server turn detection is disabled
+    TurnTaking,
+}
+
+impl VadMode {
+    /// In the order of `vad_selector`
+    pub const ALL: [VadMode; 3] = [VadMode::Off, VadMode::GateUploads, VadMode::TurnTaking];
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct VadConfig {
+    pub mode: VadMode,
+    /// Frames quieter than this are never speech
+    pub threshold_db: f32,
+    /// How far above the noise floor speech has to be
+    pub noise_margin_db: f32,
+    /// Fraction of sign changes per sample above which a frame is treated as noise
+    pub max_zero_crossing_rate: f32,
+    pub min_speech_ms: usize,
+    /// Audio from before the onset that is sent along, covers soft word starts
+    pub pre_roll_ms: usize,
+    /// Silence after speech that is still sent, and that ends the turn when turn-taking.
+    /// Must be longer than the server VAD's `silence_duration_ms` when gating uploads.
+    pub hangover_ms: usize,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            mode: VadMode::default(),
+            threshold_db: -50.0,
+            noise_margin_db: 10.0,
+            max_zero_crossing_rate: 0.35,
+            min_speech_ms: 100,
+            pre_roll_ms: 300,
+            hangover_ms: 600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VadEvent {
+    SpeechStarted,
+    SpeechEnded,
+}
+
+pub struct LocalVad {
+    /// Samples that don't make up a full frame yet
+    pending: Vec<f32>,
+    pre_roll: VecDeque<f32>,
+    noise_floor: NoiseFloor,
+    speech_ms: usize,
+    silence_ms: usize,
+    speaking: bool,
+}
+
+impl Default for LocalVad {
+    fn default() -> Self {
+        Self {
+            pending: Vec::with_capacity(FRAME_SAMPLES),
+            pre_roll: VecDeque::new(),
+            noise_floor: NoiseFloor::new(FRAME_MS as u32),
+            speech_ms: 0,
+            silence_ms: 0,
+            speaking: false,
+        }
+    }
+}
+
+impl LocalVad {
+    /// Appends the samples that should be uploaded to `output` and returns the segment
+    /// boundaries found in this chunk.
+    pub fn process(
+        &mut self,
+        config: &VadConfig,
+        samples: &[f32],
+        output: &mut Vec<f32>,
+    ) -> Vec<VadEvent> {
+        let mut events = Vec::new();
+        self.pending.extend_from_slice(samples);
+
+        let frame_count = self.pending.len() / FRAME_SAMPLES;
+        let frames: Vec<f32> = self.pending.drain(..frame_count * FRAME_SAMPLES).collect();
+        for frame in frames.chunks_exact(FRAME_SAMPLES) {
+            let is_speech = self.classify(config, frame);
+
+            if self.speaking {
+                output.extend_from_slice(frame);
+                self.silence_ms = if is_speech {
+                    0
+                } else {
+                    self.silence_ms + FRAME_MS
+                };
+                if self.silence_ms >= config.hangover_ms {
+                    self.speaking = false;
+                    self.speech_ms = 0;
+                    events.push(VadEvent::SpeechEnded);
+                }
+                continue;
+            }
+
+            self.pre_roll.extend(frame);
+            let max_pre_roll =
+                (config.pre_roll_ms + config.min_speech_ms) * OPENAI_SAMPLE_RATE / 1000;
+            while self.pre_roll.len() > max_pre_roll {
+                self.pre_roll.pop_front();
+            }
+
+            self.speech_ms = if is_speech {
+                self.speech_ms + FRAME_MS
+            } else {
+                0
+            };
+            if self.speech_ms >= config.min_speech_ms {
+                self.speaking = true;
+                self.silence_ms = 0;
+                output.extend(self.pre_roll.drain(..));
+                events.push(VadEvent::SpeechStarted);
+            }
+        }
+
+        events
+    }
+
+    pub fn is_speaking(&self) -> bool {
+        self.speaking
+    }
+
+    /// Forget the current segment, e.g. when the mic gets muted mid-sentence.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.pre_roll.clear();
+        self.speech_ms = 0;
+        self.silence_ms = 0;
+        self.speaking = false;
+    }
+
+    fn classify(&mut self, config: &VadConfig, frame: &[f32]) -> bool {
+        let sum_squares: f32 = frame.iter().map(|sample| sample * sample).sum();
+        let level_db = amplitude_to_db((sum_squares / frame.len() as f32).sqrt());
+        let zero_crossings = frame
+            .windows(2)
+            .filter(|pair| (pair[0] >= 0.0) != (pair[1] >= 0.0))
+            .count();
+        let zero_crossing_rate = zero_crossings as f32 / frame.len() as f32;
+
+        let noise_floor_db = self.noise_floor.db();
+        self.noise_floor.update(level_db);
+
+        level_db
+            > config
+                .threshold_db
+                .max(noise_floor_db + config.noise_margin_db)
+            && zero_crossing_rate <= config.max_zero_crossing_rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(amplitude: f32, ms: usize) -> Vec<f32> {
+        (0..OPENAI_SAMPLE_RATE * ms / 1000)
+            .map(|i| {
+                amplitude
+                    * (i as f32 * 200.0 * std::f32::consts::TAU / OPENAI_SAMPLE_RATE as f32).sin()
+            })
+            .collect()
+    }
+
+    /// Feeds the samples frame by frame, returning the events with the index of the frame that caused them
+    fn run(vad: &mut LocalVad, config: &VadConfig, samples: &[f32]) -> Vec<(usize, VadEvent)> {
+        let mut output = Vec::new();
+        samples
+            .chunks(FRAME_SAMPLES)
+            .enumerate()
+            .flat_map(|(index, frame)| {
+                vad.process(config, frame, &mut output)
+                    .into_iter()
+                    .map(move |event| (index, event))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn speech_starts_after_min_speech_and_ends_after_hangover() {
+        let config = VadConfig::default();
+        let mut vad = LocalVad::default();
+        let mut samples = tone(0.0, 1000);
+        samples.extend(tone(0.1, 500));
+        samples.extend(tone(0.0, 1000));
+
+        let events = run(&mut vad, &config, &samples);
+        // Onset at frame 50, 5 frames of speech make 100ms; speech stops at frame 75, 30 frames make 600ms
+        assert_eq!(
+            events,
+            vec![(54, VadEvent::SpeechStarted), (104, VadEvent::SpeechEnded)]
+        );
+        assert!(!vad.is_speaking());
+    }
+
+    #[test]
+    fn steady_noise_is_rejected_once_learned() {
+        let config = VadConfig::default();
+        let mut vad = LocalVad::default();
+        // A hum well above the fixed threshold that is present from the start
+        let hum = tone(0.01, 6000);
+
+        let events = run(&mut vad, &config, &hum);
+        assert_eq!(events.first(), Some(&(4, VadEvent::SpeechStarted)));
+        assert_eq!(
+            events.last().map(|(_, event)| *event),
+            Some(VadEvent::SpeechEnded)
+        );
+        assert!(!vad.is_speaking());
+
+        assert!(run(&mut vad, &config, &tone(0.01, 2000)).is_empty());
+        // Speech over the hum is still picked up
+        let events = run(&mut vad, &config, &tone(0.1, 500));
+        assert_eq!(events, vec![(4, VadEvent::SpeechStarted)]);
+    }
+
+    #[test]
+    fn segment_starts_with_pre_roll() {
+        let config = VadConfig::default();
+        let mut vad = LocalVad::default();
+        let mut output = Vec::new();
+
+        vad.process(&config, &tone(0.0, 1000), &mut output);
+        assert!(output.is_empty());
+
+        let events = vad.process(&config, &tone(0.1, config.min_speech_ms), &mut output);
+        assert_eq!(events, vec![VadEvent::SpeechStarted]);
+        let expected_ms = config.pre_roll_ms + config.min_speech_ms;
+        assert_eq!(output.len(), expected_ms * OPENAI_SAMPLE_RATE / 1000);
+        // Everything before the onset is silence
+        let pre_roll = config.pre_roll_ms * OPENAI_SAMPLE_RATE / 1000;
+        assert!(output[..pre_roll].iter().all(|&sample| sample == 0.0));
+
+        // While speaking, audio passes straight through
+        output.clear();
+        vad.process(&config, &tone(0.1, 100), &mut output);
+        assert_eq!(output.len(), 100 * OPENAI_SAMPLE_RATE / 1000);
+    }
+}
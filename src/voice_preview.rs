@@ -0,0 +1,59 @@
+use crate::settings::app_data_dir;
+use crate::{log_error, log_warn};
+use std::path::PathBuf;
+
+// Voice previews: a sample sentence spoken by each voice, generated once in a throwaway
+// session and cached on disk as raw 24kHz mono PCM16, so later previews are instant and
+// work offline.
+
+const PREVIEWS_DIR_NAME: &str = "voice_previews";
+
+pub const SAMPLE_SENTENCE: &str =
+    "Hi! This is how I sound. I'd be happy to help you with whatever you need today.";
+
+/// Response instructions for the preview session, the model has to stick to the sentence.
+pub fn instructions() -> String {
+    format!(
+        "Read the following sentence aloud exactly as written, in a warm and natural tone, and say nothing else: \"{}\"",
+        SAMPLE_SENTENCE
+    )
+}
+
+fn cache_path(voice: &str) -> Option<PathBuf> {
+    let file_name: String = voice
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect();
+    if file_name.is_empty() {
+        return None;
+    }
+    Some(
+        app_data_dir()?
+            .join(PREVIEWS_DIR_NAME)
+            .join(format!("{}.pcm", file_name)),
+    )
+}
+
+pub fn load_cached(voice: &str) -> Option<Vec<u8>> {
+    let pcm = std::fs::read(cache_path(voice)?).ok()?;
+    (!pcm.is_empty()).then_some(pcm)
+}
+
+pub fn save_cached(voice: &str, pcm: &[u8]) {
+    let Some(path) = cache_path(voice) else {
+        log_warn!(
+            Ui,
+            "No home directory, the {} preview won't be cached",
+            voice
+        );
+        return;
+    };
+
+    let result = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&path, pcm));
+    if let Err(e) = result {
+        log_error!(Ui, "Failed to cache the {} preview: {}", voice, e);
+    }
+}
@@ -0,0 +1,275 @@
+use crate::audio::OPENAI_SAMPLE_RATE;
+use crate::settings::app_data_dir;
+use crate::{log_debug, log_error, log_warn};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::OnceLock;
+
+// Local wake word detection. While waiting for the wake phrase nothing is streamed, the
+// captured audio only goes through a `WakeWordDetector`.
+//
+// `TemplateMatcher` is the built-in detector: the user records the phrase once, and the
+// incoming audio is compared against that recording with dynamic time warping over
+// log band energies. It is speaker dependent and easily fooled, but needs no model.
+
+const TEMPLATE_FILE_NAME: &str = "wake_word.json";
+
+const FRAME_SAMPLES: usize = OPENAI_SAMPLE_RATE / 50;
+const BAND_COUNT: usize = 12;
+// Band edges in Hz, roughly log spaced over the range that matters for speech
+const BAND_EDGES: [f32; BAND_COUNT + 1] = [
+    100.0, 200.0, 300.0, 450.0, 650.0, 900.0, 1200.0, 1600.0, 2100.0, 2700.0, 3400.0, 4300.0,
+    5500.0,
+];
+// Only compare every few frames, a wake phrase doesn't need 20ms resolution
+const CHECK_EVERY_FRAMES: usize = 5;
+// Frames quieter than this don't count as part of the phrase
+const MIN_FRAME_ENERGY: f32 = 1e-6;
+// How long enrollment records for
+pub const ENROLLMENT_SECS: f64 = 2.0;
+
+type Features = [f32; BAND_COUNT];
+
+/// Plug-in point for wake word engines.
+pub trait WakeWordDetector: Send {
+    /// Feed captured 24kHz mono audio, returns true when the wake phrase was just heard.
+    fn process(&mut self, samples: &[f32]) -> bool;
+    /// Forget buffered audio, called when going back to sleep.
+    fn reset(&mut self);
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct WakeWordConfig {
+    pub enabled: bool,
+    /// Highest DTW distance that still counts as a match, lower is stricter
+    pub threshold: f32,
+    /// Go back to sleep when nobody speaks this long after waking up
+    pub listen_timeout_secs: f64,
+}
+
+impl Default for WakeWordConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 1.5,
+            listen_timeout_secs: 8.0,
+        }
+    }
+}
+
+/// Matches incoming audio against a recording of the wake phrase.
+pub struct TemplateMatcher {
+    template: Vec<Features>,
+    threshold: f32,
+    pending: Vec<f32>,
+    window: VecDeque<(Features, f32)>,
+    frames_since_check: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredTemplate {
+    features: Vec<Features>,
+}
+
+impl TemplateMatcher {
+    pub fn new(template: Vec<Features>, threshold: f32) -> Self {
+        Self {
+            template,
+            threshold,
+            pending: Vec::new(),
+            window: VecDeque::new(),
+            frames_since_check: 0,
+        }
+    }
+
+    /// The template stored by `enroll`, if there is one.
+    pub fn load(threshold: f32) -> Option<Self> {
+        let path = app_data_dir()?.join(TEMPLATE_FILE_NAME);
+        let contents = std::fs::read_to_string(&path).ok()?;
+        match serde_json::from_str::<StoredTemplate>(&contents) {
+            Ok(stored) if !stored.features.is_empty() => {
+                Some(Self::new(stored.features, threshold))
+            }
+            Ok(_) => None,
+            Err(e) => {
+                log_warn!(
+                    Audio,
+                    "Ignoring invalid wake word template {}: {}",
+                    path.display(),
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Build and store a template from a recording of the phrase, silence around it is trimmed.
+    pub fn enroll(samples: &[f32], threshold: f32) -> Option<Self> {
+        let frames: Vec<(Features, f32)> = samples
+            .chunks_exact(FRAME_SAMPLES)
+            .map(frame_features)
+            .collect();
+        let loudest = frames.iter().map(|(_, energy)| *energy).fold(0.0, f32::max);
+        // Keep what is within 30 dB of the loudest frame
+        let floor = (loudest * 1e-3).max(MIN_FRAME_ENERGY);
+        let first = frames.iter().position(|(_, energy)| *energy >= floor)?;
+        let last = frames.iter().rposition(|(_, energy)| *energy >= floor)?;
+        let template = normalize(frames[first..=last].iter().map(|(features, _)| *features));
+        if template.len() < 10 {
+            log_warn!(
+                Audio,
+                "Wake phrase recording too short ({} frames)",
+                template.len()
+            );
+            return None;
+        }
+
+        if let Some(dir) = app_data_dir() {
+            let stored = StoredTemplate {
+                features: template.clone(),
+            };
+            let result = std::fs::create_dir_all(&dir).and_then(|_| {
+                let contents = serde_json::to_string(&stored).map_err(std::io::Error::other)?;
+                std::fs::write(dir.join(TEMPLATE_FILE_NAME), contents)
+            });
+            if let Err(e) = result {
+                log_error!(Audio, "Failed to save the wake word template: {}", e);
+            }
+        }
+        Some(Self::new(template, threshold))
+    }
+}
+
+impl WakeWordDetector for TemplateMatcher {
+    fn process(&mut self, samples: &[f32]) -> bool {
+        self.pending.extend_from_slice(samples);
+        let frame_count = self.pending.len() / FRAME_SAMPLES;
+        let frames: Vec<f32> = self.pending.drain(..frame_count * FRAME_SAMPLES).collect();
+
+        for frame in frames.chunks_exact(FRAME_SAMPLES) {
+            self.window.push_back(frame_features(frame));
+            // Allow the phrase to be said up to 50% slower than when it was recorded
+            while self.window.len() > self.template.len() * 3 / 2 {
+                self.window.pop_front();
+            }
+
+            self.frames_since_check += 1;
+            if self.frames_since_check < CHECK_EVERY_FRAMES
+                || self.window.len() < self.template.len() / 2
+            {
+                continue;
+            }
+            self.frames_since_check = 0;
+
+            // Only the audible part of the window, like the template
+            let Some(first) = self
+                .window
+                .iter()
+                .position(|(_, energy)| *energy >= MIN_FRAME_ENERGY)
+            else {
+                continue;
+            };
+            let candidate = normalize(
+                self.window
+                    .iter()
+                    .skip(first)
+                    .map(|(features, _)| *features),
+            );
+            if candidate.len() < self.template.len() / 2 {
+                continue;
+            }
+
+            let distance = dtw_distance(&self.template, &candidate);
+            log_debug!(
+                Audio,
+                "Wake word distance {:.2} (threshold {:.2})",
+                distance,
+                self.threshold
+            );
+            if distance <= self.threshold {
+                self.reset();
+                return true;
+            }
+        }
+        false
+    }
+
+    fn reset(&mut self) {
+        self.pending.clear();
+        self.window.clear();
+        self.frames_since_check = 0;
+    }
+}
+
+/// Log band energies of one frame, plus its mean energy.
+fn frame_features(frame: &[f32]) -> (Features, f32) {
+    static TWIDDLES: OnceLock<Vec<(f32, f32)>> = OnceLock::new();
+    let twiddles = TWIDDLES.get_or_init(|| {
+        (0..FRAME_SAMPLES)
+            .map(|i| (2.0 * PI * i as f32 / FRAME_SAMPLES as f32).sin_cos())
+            .collect()
+    });
+
+    let n = frame.len() as f32;
+    let bin_hz = OPENAI_SAMPLE_RATE as f32 / n;
+    let mut features = [0.0; BAND_COUNT];
+    for (band, feature) in features.iter_mut().enumerate() {
+        let first_bin = (BAND_EDGES[band] / bin_hz).ceil() as usize;
+        let last_bin = (BAND_EDGES[band + 1] / bin_hz).floor() as usize;
+        let mut energy = 0.0;
+        for bin in first_bin..=last_bin.max(first_bin) {
+            let (mut re, mut im) = (0.0, 0.0);
+            for (i, sample) in frame.iter().enumerate() {
+                let (sin, cos) = twiddles[bin * i % FRAME_SAMPLES];
+                re += sample * cos;
+                im -= sample * sin;
+            }
+            energy += (re * re + im * im) / n;
+        }
+        *feature = (energy + 1e-9).ln();
+    }
+    let frame_energy = frame.iter().map(|sample| sample * sample).sum::<f32>() / n;
+    (features, frame_energy)
+}
+
+/// Remove the per-band mean so the match doesn't depend on loudness or mic coloration.
+fn normalize(frames: impl Iterator<Item = Features>) -> Vec<Features> {
+    let mut frames: Vec<Features> = frames.collect();
+    if frames.is_empty() {
+        return frames;
+    }
+    let mut mean = [0.0; BAND_COUNT];
+    for frame in &frames {
+        for (sum, value) in mean.iter_mut().zip(frame) {
+            *sum += value / frames.len() as f32;
+        }
+    }
+    for frame in &mut frames {
+        for (value, mean) in frame.iter_mut().zip(&mean) {
+            *value -= mean;
+        }
+    }
+    frames
+}
+
+/// Dynamic time warping distance, normalized by the length of both sequences.
+fn dtw_distance(a: &[Features], b: &[Features]) -> f32 {
+    let frame_distance = |x: &Features, y: &Features| {
+        (x.iter().zip(y).map(|(x, y)| (x - y) * (x - y)).sum::<f32>() / BAND_COUNT as f32).sqrt()
+    };
+
+    let mut previous = vec![f32::INFINITY; b.len() + 1];
+    let mut current = vec![f32::INFINITY; b.len() + 1];
+    previous[0] = 0.0;
+    for x in a {
+        current[0] = f32::INFINITY;
+        for (j, y) in b.iter().enumerate() {
+            let best = previous[j].min(previous[j + 1]).min(current[j]);
+            current[j + 1] = frame_distance(x, y) + best;
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()] / (a.len() + b.len()) as f32 * 2.0
+}
@@ -0,0 +1,578 @@
+use crate::{log_debug, log_info, log_warn};
+use base64::{Engine as _, engine::general_purpose};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+// Just enough of a WebSocket server (RFC 6455) for the relay: the opening handshake, text
+// messages (fragmented or not), ping/pong and close. Every connection gets a reader and a
+// writer thread, the UI thread only sees `ServerEvent`s and sends through `WsSender`.
+
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+// Larger messages close the connection, audio appends are well below this
+const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+const MAX_HANDSHAKE_LEN: usize = 16 * 1024;
+// Clients that don't finish the handshake by then are dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+pub enum ServerEvent {
+    Connected {
+        id: u64,
+        peer: SocketAddr,
+        /// Path and query of the upgrade request
+        target: String,
+        sender: WsSender,
+    },
+    Text {
+        id: u64,
+        text: String,
+    },
+    Closed {
+        id: u64,
+    },
+}
+
+enum Outgoing {
+    Frame(u8, Vec<u8>),
+    Close,
+}
+
+/// Sends to one client, dropping it closes nothing, `close` does.
+#[derive(Clone)]
+pub struct WsSender {
+    outgoing: Sender<Outgoing>,
+}
+
+impl WsSender {
+    pub fn send_text(&self, text: String) {
+        let _ = self.outgoing.send(Outgoing::Frame(OPCODE_TEXT, text.into_bytes()));
+    }
+
+    pub fn close(&self) {
+        let _ = self.outgoing.send(Outgoing::Close);
+    }
+}
+
+/// Accepts connections on a background thread, at most `max_connections` at a time (two
+/// threads each). Upgrades that carry an `Origin` header come from browsers and are
+/// refused unless the origin is in `allowed_origins`.
+pub struct WsServer {
+    events: Receiver<ServerEvent>,
+    local_addr: SocketAddr,
+}
+
+impl WsServer {
+    pub fn bind(address: &str, allowed_origins: Vec<String>, max_connections: usize) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let local_addr = listener.local_addr()?;
+        let (events, receiver) = mpsc::channel();
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        std::thread::Builder::new()
+            .name("ws-server".to_string())
+            .spawn(move || {
+                let mut next_id = 0;
+                for stream in listener.incoming() {
+                    let Ok(stream) = stream else {
+                        continue;
+                    };
+                    if connections.load(Ordering::SeqCst) >= max_connections {
+                        log_debug!(Transport, "Dropping a WebSocket connection, {} are open", max_connections);
+                        continue;
+                    }
+                    connections.fetch_add(1, Ordering::SeqCst);
+                    next_id += 1;
+                    let id = next_id;
+                    let events = events.clone();
+                    let allowed_origins = allowed_origins.clone();
+                    let spawned = std::thread::Builder::new()
+                        .name(format!("ws-client-{}", id))
+                        .spawn({
+                            let connections = connections.clone();
+                            move || {
+                                serve_connection(id, stream, &allowed_origins, events);
+                                connections.fetch_sub(1, Ordering::SeqCst);
+                            }
+                        });
+                    if spawned.is_err() {
+                        connections.fetch_sub(1, Ordering::SeqCst);
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            events: receiver,
+            local_addr,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn poll_events(&self) -> Vec<ServerEvent> {
+        self.events.try_iter().collect()
+    }
+}
+
+fn serve_connection(id: u64, stream: TcpStream, allowed_origins: &[String], events: Sender<ServerEvent>) {
+    let Ok(peer) = stream.peer_addr() else {
+        return;
+    };
+    let mut reader = BufReader::new(match stream.try_clone() {
+        Ok(stream) => stream,
+        Err(_) => return,
+    });
+    let mut writer = stream;
+
+    let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+    let target = match accept_handshake(&mut reader, &mut writer, allowed_origins, deadline) {
+        Ok(target) => target,
+        Err(e) => {
+            log_debug!(Transport, "Rejected WebSocket handshake from {}: {}", peer, e);
+            return;
+        }
+    };
+    let _ = writer.set_read_timeout(None);
+    log_info!(Transport, "WebSocket client {} connected from {}", id, peer);
+
+    let (outgoing, outgoing_receiver) = mpsc::channel();
+    let sender = WsSender { outgoing };
+    let pong_sender = sender.clone();
+    let writer_thread = std::thread::spawn(move || {
+        for message in outgoing_receiver {
+            let result = match message {
+                Outgoing::Frame(opcode, payload) => write_frame(&mut writer, opcode, &payload),
+                Outgoing::Close => {
+                    let _ = write_frame(&mut writer, OPCODE_CLOSE, &[]);
+                    let _ = writer.shutdown(std::net::Shutdown::Both);
+                    break;
+                }
+            };
+            if result.is_err() {
+                break;
+            }
+        }
+    });
+
+    let _ = events.send(ServerEvent::Connected {
+        id,
+        peer,
+        target,
+        sender: sender.clone(),
+    });
+
+    let mut message = Vec::new();
+    let mut message_opcode = OPCODE_TEXT;
+    loop {
+        let (fin, opcode, payload) = match read_frame(&mut reader) {
+            Ok(frame) => frame,
+            Err(e) => {
+                log_debug!(Transport, "WebSocket client {} read ended: {}", id, e);
+                break;
+            }
+        };
+        match opcode {
+            OPCODE_PING => pong_sender
+                .outgoing
+                .send(Outgoing::Frame(OPCODE_PONG, payload))
+                .unwrap_or_default(),
+            OPCODE_PONG => {}
+            OPCODE_CLOSE => break,
+            OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION => {
+                if opcode != OPCODE_CONTINUATION {
+                    message.clear();
+                    message_opcode = opcode;
+                }
+                message.extend_from_slice(&payload);
+                if message.len() > MAX_MESSAGE_LEN {
+                    log_warn!(Transport, "WebSocket client {} sent an oversized message", id);
+                    break;
+                }
+                if !fin {
+                    continue;
+                }
+                if message_opcode == OPCODE_BINARY {
+                    log_debug!(Transport, "Ignoring binary message from WebSocket client {}", id);
+                    continue;
+                }
+                match String::from_utf8(std::mem::take(&mut message)) {
+                    Ok(text) => {
+                        let _ = events.send(ServerEvent::Text { id, text });
+                    }
+                    Err(_) => break,
+                }
+            }
+            _ => break,
+        }
+    }
+
+    sender.close();
+    let _ = writer_thread.join();
+    let _ = events.send(ServerEvent::Closed { id });
+    log_info!(Transport, "WebSocket client {} disconnected", id);
+}
+
+/// Reads the upgrade request and answers it, returns the request target. The whole request
+/// has to arrive before `deadline`, a client trickling bytes doesn't get to extend it.
+fn accept_handshake(
+    reader: &mut BufReader<TcpStream>,
+    writer: &mut impl Write,
+    allowed_origins: &[String],
+    deadline: Instant,
+) -> Result<String, String> {
+    // Bounds every line too, a client can't make us buffer one endless header
+    let mut remaining_len = MAX_HANDSHAKE_LEN;
+    let request_line = read_line_before(reader, deadline, &mut remaining_len)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some("GET"), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(format!("unexpected request line {:?}", request_line.trim()));
+    };
+    let target = target.to_string();
+
+    let mut key = None;
+    let mut origin = None;
+    let mut protocols = Vec::new();
+    loop {
+        let line = read_line_before(reader, deadline, &mut remaining_len)?;
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("Sec-WebSocket-Key") {
+            key = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("Origin") {
+            origin = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("Sec-WebSocket-Protocol") {
+            protocols.extend(value.split(',').map(|protocol| protocol.trim().to_string()));
+        }
+    }
+    let key = key.ok_or("missing Sec-WebSocket-Key")?;
+    // Browsers always send their page's origin, any site the user visits could connect otherwise
+    if let Some(origin) = origin.filter(|origin| !allowed_origins.contains(origin)) {
+        let _ = writer.write_all(b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        return Err(format!("origin {} is not allowed", origin));
+    }
+
+    let accept = general_purpose::STANDARD.encode(sha1(format!("{}{}", key, HANDSHAKE_GUID).as_bytes()));
+    let mut response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n",
+        accept
+    );
+    // Browsers fail the connection unless one of their subprotocols is echoed back,
+    // OpenAI's browser snippets offer "realtime" next to the key and beta flags
+    if let Some(protocol) = protocols
+        .iter()
+        .find(|protocol| *protocol == "realtime")
+        .or(protocols.first())
+    {
+        response.push_str(&format!("Sec-WebSocket-Protocol: {}\r\n", protocol));
+    }
+    response.push_str("\r\n");
+    writer
+        .write_all(response.as_bytes())
+        .map_err(|e| e.to_string())?;
+    Ok(target)
+}
+
+/// Fills the buffer with whatever arrives next, waiting at most until `deadline`. Also
+/// used by the token service, whose requests share the problem of slow clients.
+pub(crate) fn fill_buf_before(reader: &mut BufReader<TcpStream>, deadline: Instant) -> Result<&[u8], String> {
+    let timeout = deadline
+        .checked_duration_since(Instant::now())
+        .filter(|timeout| !timeout.is_zero())
+        .ok_or("timed out")?;
+    reader
+        .get_ref()
+        .set_read_timeout(Some(timeout))
+        .map_err(|e| e.to_string())?;
+    reader.fill_buf().map_err(|e| match e.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => "timed out".to_string(),
+        _ => e.to_string(),
+    })
+}
+
+/// One line of an HTTP request, each read only waits for what is left until `deadline`.
+/// `remaining_len` is what the whole request may still take up.
+pub(crate) fn read_line_before(
+    reader: &mut BufReader<TcpStream>,
+    deadline: Instant,
+    remaining_len: &mut usize,
+) -> Result<String, String> {
+    let mut line = Vec::new();
+    loop {
+        let available = fill_buf_before(reader, deadline)?;
+        if available.is_empty() {
+            return Err("cut short".to_string());
+        }
+        let (length, complete) = match available.iter().position(|&byte| byte == b'\n') {
+            Some(end) => (end + 1, true),
+            None => (available.len(), false),
+        };
+        if length > *remaining_len {
+            return Err("too large".to_string());
+        }
+        *remaining_len -= length;
+        line.extend_from_slice(&available[..length]);
+        reader.consume(length);
+        if complete {
+            return String::from_utf8(line).map_err(|_| "not UTF-8".to_string());
+        }
+    }
+}
+
+/// Returns (fin, opcode, unmasked payload). Frames a client may not send (RFC 6455 5.1
+/// and 5.5) are errors, which end the connection.
+fn read_frame(reader: &mut impl Read) -> std::io::Result<(bool, u8, Vec<u8>)> {
+    let mut header = [0u8; 2];
+    reader.read_exact(&mut header)?;
+    let fin = header[0] & 0x80 != 0;
+    let opcode = header[0] & 0x0F;
+    let masked = header[1] & 0x80 != 0;
+
+    let length = match header[1] & 0x7F {
+        126 => {
+            let mut length = [0u8; 2];
+            reader.read_exact(&mut length)?;
+            u16::from_be_bytes(length) as usize
+        }
+        127 => {
+            let mut length = [0u8; 8];
+            reader.read_exact(&mut length)?;
+            u64::from_be_bytes(length) as usize
+        }
+        length => length as usize,
+    };
+    if length > MAX_MESSAGE_LEN {
+        return Err(std::io::Error::other("frame too large"));
+    }
+    if !masked {
+        return Err(std::io::Error::other("unmasked client frame"));
+    }
+    // Control frames can be sent in the middle of a fragmented message, so they can't be fragmented
+    if opcode & 0x8 != 0 && (!fin || length > 125) {
+        return Err(std::io::Error::other("fragmented or oversized control frame"));
+    }
+
+    let mut mask = [0u8; 4];
+    reader.read_exact(&mut mask)?;
+    let mut payload = vec![0u8; length];
+    reader.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok((fin, opcode, payload))
+}
+
+/// Server frames are never masked.
+fn write_frame(writer: &mut impl Write, opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        length if length < 126 => frame.push(length as u8),
+        length if length <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)
+}
+
+// SHA-1 (FIPS 180-4), only used for Sec-WebSocket-Accept
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut words = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            words[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in words.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (chunk, value) in digest.chunks_exact_mut(4).zip(state) {
+        chunk.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A connected pair, the server side wrapped like `serve_connection` does
+    fn connect() -> (TcpStream, BufReader<TcpStream>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (client, BufReader::new(server))
+    }
+
+    #[test]
+    fn handshake_answers_with_the_accept_key() {
+        let (mut client, mut reader) = connect();
+        client
+            .write_all(
+                b"GET /?token=abc HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+                  Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Protocol: realtime, openai-beta.realtime-v1\r\n\r\n",
+            )
+            .unwrap();
+        let mut response = Vec::new();
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        let target = accept_handshake(&mut reader, &mut response, &[], deadline).unwrap();
+        assert_eq!(target, "/?token=abc");
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 101 "));
+        // The sample from RFC 6455 section 1.3
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+        assert!(response.contains("Sec-WebSocket-Protocol: realtime\r\n"));
+    }
+
+    #[test]
+    fn handshake_refuses_foreign_origins() {
+        let (mut client, mut reader) = connect();
+        client
+            .write_all(b"GET / HTTP/1.1\r\nOrigin: https://example.com\r\nSec-WebSocket-Key: a2V5\r\n\r\n")
+            .unwrap();
+        let mut response = Vec::new();
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        assert!(accept_handshake(&mut reader, &mut response, &[], deadline).is_err());
+        assert!(response.starts_with(b"HTTP/1.1 403 "));
+    }
+
+    #[test]
+    fn handshake_gives_up_at_the_deadline() {
+        let (mut client, mut reader) = connect();
+        // One byte every 20ms never hits a per-read timeout, the deadline still ends it
+        std::thread::spawn(move || {
+            let _ = client.write_all(b"GET / HTTP/1.1\r\nX-Slow: ");
+            for _ in 0..100 {
+                std::thread::sleep(Duration::from_millis(20));
+                if client.write_all(b"a").is_err() {
+                    break;
+                }
+            }
+        });
+
+        let started = Instant::now();
+        let deadline = started + Duration::from_millis(200);
+        let result = accept_handshake(&mut reader, &mut Vec::new(), &[], deadline);
+        assert_eq!(result, Err("timed out".to_string()));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    /// A client frame, masked unless `mask` is None
+    fn client_frame(first_byte: u8, payload: &[u8], mask: Option<[u8; 4]>) -> Vec<u8> {
+        let mut frame = Vec::new();
+        write_frame(&mut frame, 0, payload).unwrap();
+        frame[0] = first_byte;
+        let Some(mask) = mask else {
+            return frame;
+        };
+        frame[1] |= 0x80;
+        let header_len = frame.len() - payload.len();
+        let mut masked = frame[..header_len].to_vec();
+        masked.extend_from_slice(&mask);
+        masked.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+        masked
+    }
+
+    #[test]
+    fn read_frame_unmasks_client_frames() {
+        let frame = client_frame(0x80 | OPCODE_TEXT, b"Hello", Some([0x37, 0xFA, 0x21, 0x3D]));
+        // The masked example from RFC 6455 section 5.7
+        assert_eq!(frame, [0x81, 0x85, 0x37, 0xFA, 0x21, 0x3D, 0x7F, 0x9F, 0x4D, 0x51, 0x58]);
+        let (fin, opcode, payload) = read_frame(&mut frame.as_slice()).unwrap();
+        assert!(fin);
+        assert_eq!(opcode, OPCODE_TEXT);
+        assert_eq!(payload, b"Hello");
+
+        let long = vec![b'a'; 300];
+        let frame = client_frame(OPCODE_TEXT, &long, Some([1, 2, 3, 4]));
+        let (fin, _, payload) = read_frame(&mut frame.as_slice()).unwrap();
+        assert!(!fin);
+        assert_eq!(payload, long);
+    }
+
+    #[test]
+    fn read_frame_refuses_what_clients_may_not_send() {
+        let mask = Some([1, 2, 3, 4]);
+        let unmasked = client_frame(0x80 | OPCODE_TEXT, b"Hello", None);
+        assert!(read_frame(&mut unmasked.as_slice()).is_err());
+
+        let fragmented_ping = client_frame(OPCODE_PING, b"ping", mask);
+        assert!(read_frame(&mut fragmented_ping.as_slice()).is_err());
+
+        let oversized_ping = client_frame(0x80 | OPCODE_PING, &[0; 126], mask);
+        assert!(read_frame(&mut oversized_ping.as_slice()).is_err());
+        let largest_ping = client_frame(0x80 | OPCODE_PING, &[0; 125], mask);
+        assert!(read_frame(&mut largest_ping.as_slice()).is_ok());
+    }
+
+    #[test]
+    fn sha1_matches_known_answers() {
+        let hex = |digest: [u8; 20]| digest.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hex(sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        // Two blocks once padded
+        assert_eq!(
+            hex(sha1(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+        let accept = sha1(format!("dGhlIHNhbXBsZSBub25jZQ=={}", HANDSHAKE_GUID).as_bytes());
+        assert_eq!(general_purpose::STANDARD.encode(accept), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+}